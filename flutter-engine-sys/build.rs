@@ -32,6 +32,9 @@ fn main() {
         .default_enum_style(EnumVariation::Rust {
             non_exhaustive: false,
         })
+        .bitfield_enum("FlutterAccessibilityFeature")
+        .bitfield_enum("FlutterSemanticsAction")
+        .bitfield_enum("FlutterSemanticsFlag")
         .clang_args(&clang_args)
         .generate()
         .expect("Unable to generate bindings");
@@ -5,27 +5,30 @@
 
 include!(concat!(env!("OUT_DIR"), "/flutter-engine-sys.rs"));
 
-#[cfg(target_os = "android")]
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(all(target_os = "android", not(feature = "mock")))]
 #[link(name = "flutter_engine")]
 extern "C" {}
 
-#[cfg(target_os = "ios")]
+#[cfg(all(target_os = "ios", not(feature = "mock")))]
 #[link(name = "flutter_engine")]
 extern "C" {}
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
 #[link(name = "flutter_engine")]
 extern "C" {}
 
-#[cfg(target_os = "macos")]
+#[cfg(all(target_os = "macos", not(feature = "mock")))]
 #[link(name = "flutter_engine")]
 extern "C" {}
 
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", not(feature = "mock")))]
 #[link(name = "flutter_engine")]
 extern "C" {}
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "mock")))]
 mod tests {
     #[allow(unused)]
     use super::*;
@@ -0,0 +1,844 @@
+//! A fake implementation of the flutter embedder API.
+//!
+//! When the `mock` feature is enabled this module exports every symbol of
+//! `flutter-engine.h` instead of linking against `libflutter_engine`. Every
+//! call made by the embedder is recorded and tests can drive the embedder by
+//! injecting platform messages, tasks and isolate creation through
+//! [`MockEngine`].
+
+#![allow(clippy::missing_safety_doc)]
+
+use super::*;
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{mem, ptr, slice};
+
+thread_local! {
+    static RUNS_AOT_COMPILED_DART_CODE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Sets the value returned by `FlutterEngineRunsAOTCompiledDartCode` on the
+/// current thread, so tests running in parallel don't affect each other.
+pub fn set_runs_aot_compiled_dart_code(aot: bool) {
+    RUNS_AOT_COMPILED_DART_CODE.with(|cell| cell.set(aot));
+}
+
+/// The project args that are not callbacks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MockProjectArgs {
+    pub icu_data_path: Option<String>,
+    pub persistent_cache_path: Option<String>,
+    pub is_persistent_cache_read_only: bool,
+    pub custom_dart_entrypoint: Option<String>,
+    pub shutdown_dart_vm_when_done: bool,
+}
+
+/// A call made by the embedder into the engine.
+#[derive(Clone, Debug)]
+pub enum Call {
+    Run {
+        assets_path: String,
+        arguments: Vec<String>,
+    },
+    Initialize {
+        assets_path: String,
+        arguments: Vec<String>,
+    },
+    RunInitialized,
+    Deinitialize,
+    Shutdown,
+    SendWindowMetricsEvent(FlutterWindowMetricsEvent),
+    SendPointerEvent(Vec<FlutterPointerEvent>),
+    SendPlatformMessage {
+        channel: String,
+        message: Vec<u8>,
+        response_handle: Option<usize>,
+    },
+    CreateResponseHandle(usize),
+    ReleaseResponseHandle(usize),
+    SendPlatformMessageResponse {
+        response_handle: usize,
+        data: Vec<u8>,
+    },
+    RegisterExternalTexture(i64),
+    UnregisterExternalTexture(i64),
+    MarkExternalTextureFrameAvailable(i64),
+    UpdateSemanticsEnabled(bool),
+    UpdateAccessibilityFeatures(u32),
+    DispatchSemanticsAction {
+        id: u64,
+        action: u32,
+        data: Vec<u8>,
+    },
+    OnVsync {
+        baton: isize,
+        frame_start_time_nanos: u64,
+        frame_target_time_nanos: u64,
+    },
+    ReloadSystemFonts,
+    PostRenderThreadTask,
+    RunTask(u64),
+    UpdateLocales(Vec<(String, String, String, String)>),
+}
+
+enum ResponseHandle {
+    /// Created by the mock for a message sent from "dart" to the embedder.
+    Engine(usize),
+    /// Created by the embedder to receive the reply to one of its messages.
+    Embedder {
+        id: usize,
+        data_callback: FlutterDataCallback,
+        user_data: *mut c_void,
+    },
+}
+
+impl ResponseHandle {
+    fn id(&self) -> usize {
+        match self {
+            ResponseHandle::Engine(id) => *id,
+            ResponseHandle::Embedder { id, .. } => *id,
+        }
+    }
+}
+
+/// The state of a fake engine instance.
+///
+/// The `FlutterEngine` handle returned by `FlutterEngineRun` and
+/// `FlutterEngineInitialize` points to one of these.
+pub struct MockEngine {
+    user_data: *mut c_void,
+    renderer_config: FlutterRendererConfig,
+    platform_message_callback: FlutterPlatformMessageCallback,
+    root_isolate_create_callback: VoidCallback,
+    update_semantics_node_callback: FlutterUpdateSemanticsNodeCallback,
+    update_semantics_custom_action_callback: FlutterUpdateSemanticsCustomActionCallback,
+    vsync_callback: VsyncCallback,
+    platform_task_runner: Option<FlutterTaskRunnerDescription>,
+    compositor: Option<FlutterCompositor>,
+    aot_snapshot: [*const u8; 4],
+    project_args: MockProjectArgs,
+    calls: Mutex<Vec<Call>>,
+    pending_replies: Mutex<Vec<(usize, FlutterDataCallback, *mut c_void)>>,
+    next_handle_id: AtomicUsize,
+}
+
+unsafe impl Send for MockEngine {}
+
+unsafe impl Sync for MockEngine {}
+
+impl MockEngine {
+    unsafe fn new(
+        config: &FlutterRendererConfig,
+        args: &FlutterProjectArgs,
+        user_data: *mut c_void,
+    ) -> Self {
+        let platform_task_runner = if args.custom_task_runners.is_null() {
+            None
+        } else {
+            let runners = &*args.custom_task_runners;
+            if runners.platform_task_runner.is_null() {
+                None
+            } else {
+                Some(*runners.platform_task_runner)
+            }
+        };
+        let compositor = if args.compositor.is_null() {
+            None
+        } else {
+            Some(*args.compositor)
+        };
+        Self {
+            user_data,
+            renderer_config: *config,
+            platform_message_callback: args.platform_message_callback,
+            root_isolate_create_callback: args.root_isolate_create_callback,
+            update_semantics_node_callback: args.update_semantics_node_callback,
+            update_semantics_custom_action_callback: args.update_semantics_custom_action_callback,
+            vsync_callback: args.vsync_callback,
+            platform_task_runner,
+            compositor,
+            aot_snapshot: [
+                args.vm_snapshot_data,
+                args.vm_snapshot_instructions,
+                args.isolate_snapshot_data,
+                args.isolate_snapshot_instructions,
+            ],
+            project_args: MockProjectArgs {
+                icu_data_path: optional_string(args.icu_data_path),
+                persistent_cache_path: optional_string(args.persistent_cache_path),
+                is_persistent_cache_read_only: args.is_persistent_cache_read_only,
+                custom_dart_entrypoint: optional_string(args.custom_dart_entrypoint),
+                shutdown_dart_vm_when_done: args.shutdown_dart_vm_when_done,
+            },
+            calls: Mutex::new(Vec::new()),
+            pending_replies: Mutex::new(Vec::new()),
+            next_handle_id: AtomicUsize::new(1),
+        }
+    }
+
+    /// Returns the mock behind an engine handle.
+    ///
+    /// # Safety
+    /// `engine` must be a handle returned by the mock `FlutterEngineRun` or
+    /// `FlutterEngineInitialize`.
+    pub unsafe fn from_engine(engine: FlutterEngine) -> Arc<MockEngine> {
+        let this = Arc::from_raw(engine as *const MockEngine);
+        let clone = Arc::clone(&this);
+        mem::forget(this);
+        clone
+    }
+
+    /// The VM data, VM instructions, isolate data and isolate instructions
+    /// snapshots passed in the project args.
+    pub fn aot_snapshot(&self) -> [*const u8; 4] {
+        self.aot_snapshot
+    }
+
+    pub fn project_args(&self) -> &MockProjectArgs {
+        &self.project_args
+    }
+
+    /// All calls made on this engine so far.
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Returns and forgets all calls made on this engine so far.
+    pub fn take_calls(&self) -> Vec<Call> {
+        mem::take(&mut *self.calls.lock().unwrap())
+    }
+
+    /// Platform messages the embedder sent on `channel`.
+    pub fn sent_messages(&self, channel: &str) -> Vec<Vec<u8>> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|call| match call {
+                Call::SendPlatformMessage {
+                    channel: c,
+                    message,
+                    ..
+                } if c == channel => Some(message.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The response the embedder sent for a message injected with
+    /// [`send_platform_message`](Self::send_platform_message).
+    pub fn response(&self, response_handle: usize) -> Option<Vec<u8>> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .find_map(|call| match call {
+                Call::SendPlatformMessageResponse {
+                    response_handle: handle,
+                    data,
+                } if *handle == response_handle => Some(data.clone()),
+                _ => None,
+            })
+    }
+
+    fn record(&self, call: Call) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    fn next_handle_id(&self) -> usize {
+        self.next_handle_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Sends a platform message to the embedder as if it came from dart.
+    ///
+    /// Returns the id of the response handle attached to the message.
+    pub fn send_platform_message(&self, channel: &str, message: &[u8]) -> usize {
+        let id = self.next_handle_id();
+        let handle = Box::into_raw(Box::new(ResponseHandle::Engine(id)));
+        let channel = CString::new(channel).unwrap();
+        let message = FlutterPlatformMessage {
+            struct_size: mem::size_of::<FlutterPlatformMessage>(),
+            channel: channel.as_ptr(),
+            message: message.as_ptr(),
+            message_size: message.len(),
+            response_handle: handle as *const FlutterPlatformMessageResponseHandle,
+        };
+        if let Some(callback) = self.platform_message_callback {
+            unsafe { callback(&message, self.user_data) };
+        }
+        id
+    }
+
+    /// Answers a message the embedder sent with a response handle created by
+    /// `FlutterPlatformMessageCreateResponseHandle`.
+    ///
+    /// Returns false if there is no such message waiting for a reply.
+    pub fn reply(&self, response_handle: usize, data: &[u8]) -> bool {
+        let pending = {
+            let mut replies = self.pending_replies.lock().unwrap();
+            let index = replies.iter().position(|(id, _, _)| *id == response_handle);
+            index.map(|index| replies.remove(index))
+        };
+        if let Some((_, Some(callback), user_data)) = pending {
+            unsafe { callback(data.as_ptr(), data.len(), user_data) };
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Posts a task to the platform task runner of the embedder.
+    pub fn post_task(&self, task: u64, target_time_nanos: u64) {
+        if let Some(runner) = &self.platform_task_runner {
+            if let Some(post_task) = runner.post_task_callback {
+                let task = FlutterTask {
+                    runner: ptr::null_mut(),
+                    task,
+                };
+                unsafe { post_task(task, target_time_nanos, runner.user_data) };
+            }
+        }
+    }
+
+    /// Asks the embedder whether the current thread is the platform thread.
+    pub fn runs_task_on_current_thread(&self) -> bool {
+        if let Some(runner) = &self.platform_task_runner {
+            if let Some(callback) = runner.runs_task_on_current_thread_callback {
+                return unsafe { callback(runner.user_data) };
+            }
+        }
+        false
+    }
+
+    /// Invokes the root isolate create callback.
+    pub fn create_root_isolate(&self) {
+        if let Some(callback) = self.root_isolate_create_callback {
+            unsafe { callback(self.user_data) };
+        }
+    }
+
+    /// Presents a frame through the OpenGL renderer callbacks.
+    pub fn present_gl_surface(&self) -> bool {
+        if self.renderer_config.type_ != FlutterRendererType::kOpenGL {
+            return false;
+        }
+        let open_gl = unsafe { self.renderer_config.__bindgen_anon_1.open_gl };
+        match (open_gl.make_current, open_gl.present, open_gl.clear_current) {
+            (Some(make_current), Some(present), Some(clear_current)) => unsafe {
+                make_current(self.user_data)
+                    && present(self.user_data)
+                    && clear_current(self.user_data)
+            },
+            _ => false,
+        }
+    }
+
+    /// Presents a frame through the software renderer callbacks.
+    pub fn present_software_surface(&self, pixels: &[u8], row_bytes: usize, height: usize) -> bool {
+        if self.renderer_config.type_ != FlutterRendererType::kSoftware {
+            return false;
+        }
+        let software = unsafe { self.renderer_config.__bindgen_anon_1.software };
+        if let Some(present) = software.surface_present_callback {
+            unsafe {
+                present(
+                    self.user_data,
+                    pixels.as_ptr() as *const c_void,
+                    row_bytes,
+                    height,
+                )
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Requests a vsync from the embedder.
+    pub fn request_vsync(&self, baton: isize) {
+        if let Some(callback) = self.vsync_callback {
+            unsafe { callback(self.user_data, baton) };
+        }
+    }
+
+    /// Sends a semantics node update to the embedder.
+    pub fn update_semantics_node(&self, node: &FlutterSemanticsNode) {
+        if let Some(callback) = self.update_semantics_node_callback {
+            unsafe { callback(node, self.user_data) };
+        }
+    }
+
+    /// Sends a semantics custom action update to the embedder.
+    pub fn update_semantics_custom_action(&self, action: &FlutterSemanticsCustomAction) {
+        if let Some(callback) = self.update_semantics_custom_action_callback {
+            unsafe { callback(action, self.user_data) };
+        }
+    }
+
+    /// The compositor passed in the project args, if any.
+    pub fn compositor(&self) -> Option<FlutterCompositor> {
+        self.compositor
+    }
+
+    /// Asks the compositor of the embedder for a backing store.
+    pub fn create_backing_store(&self, width: f64, height: f64) -> Option<FlutterBackingStore> {
+        let compositor = self.compositor?;
+        let create = compositor.create_backing_store_callback?;
+        let config = FlutterBackingStoreConfig {
+            struct_size: mem::size_of::<FlutterBackingStoreConfig>(),
+            size: FlutterSize { width, height },
+        };
+        unsafe {
+            let mut backing_store = mem::zeroed::<FlutterBackingStore>();
+            if create(&config, &mut backing_store, compositor.user_data) {
+                Some(backing_store)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Hands a backing store back to the compositor of the embedder.
+    pub fn collect_backing_store(&self, backing_store: &FlutterBackingStore) -> bool {
+        match self.compositor {
+            Some(FlutterCompositor {
+                collect_backing_store_callback: Some(collect),
+                user_data,
+                ..
+            }) => unsafe { collect(backing_store, user_data) },
+            _ => false,
+        }
+    }
+
+    /// Presents layers through the compositor of the embedder.
+    pub fn present_layers(&self, layers: &[FlutterLayer]) -> bool {
+        let mut layers: Vec<*const FlutterLayer> =
+            layers.iter().map(|layer| layer as *const _).collect();
+        match self.compositor {
+            Some(FlutterCompositor {
+                present_layers_callback: Some(present),
+                user_data,
+                ..
+            }) => unsafe { present(layers.as_mut_ptr(), layers.len(), user_data) },
+            _ => false,
+        }
+    }
+}
+
+unsafe fn mock<'a>(engine: FlutterEngine) -> Option<&'a MockEngine> {
+    (engine as *const MockEngine).as_ref()
+}
+
+unsafe fn string(s: *const c_char) -> String {
+    if s.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(s).to_string_lossy().into_owned()
+    }
+}
+
+unsafe fn optional_string(s: *const c_char) -> Option<String> {
+    if s.is_null() {
+        None
+    } else {
+        Some(string(s))
+    }
+}
+
+unsafe fn arguments(args: &FlutterProjectArgs) -> Vec<String> {
+    if args.command_line_argv.is_null() {
+        return Vec::new();
+    }
+    slice::from_raw_parts(args.command_line_argv, args.command_line_argc as usize)
+        .iter()
+        .map(|arg| string(*arg))
+        .collect()
+}
+
+unsafe fn create(
+    config: *const FlutterRendererConfig,
+    args: *const FlutterProjectArgs,
+    user_data: *mut c_void,
+    engine_out: *mut FlutterEngine,
+    call: fn(String, Vec<String>) -> Call,
+) -> FlutterEngineResult {
+    if config.is_null() || args.is_null() || engine_out.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    let args = &*args;
+    // Like the real engine, release builds refuse to start without snapshots.
+    if FlutterEngineRunsAOTCompiledDartCode()
+        && (args.vm_snapshot_data.is_null()
+            || args.vm_snapshot_instructions.is_null()
+            || args.isolate_snapshot_data.is_null()
+            || args.isolate_snapshot_instructions.is_null())
+    {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    let engine = MockEngine::new(&*config, args, user_data);
+    engine.record(call(string(args.assets_path), arguments(args)));
+    *engine_out = Arc::into_raw(Arc::new(engine)) as FlutterEngine;
+    FlutterEngineResult::kSuccess
+}
+
+macro_rules! with_mock {
+    ($engine:expr, |$mock:ident| $body:expr) => {
+        match mock($engine) {
+            Some($mock) => {
+                $body;
+                FlutterEngineResult::kSuccess
+            }
+            None => FlutterEngineResult::kInvalidArguments,
+        }
+    };
+}
+
+#[no_mangle]
+pub static kFlutterSemanticsNodeIdBatchEnd: i32 = -1;
+
+#[no_mangle]
+pub static kFlutterSemanticsCustomActionIdBatchEnd: i32 = -1;
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineRun(
+    _version: usize,
+    config: *const FlutterRendererConfig,
+    args: *const FlutterProjectArgs,
+    user_data: *mut c_void,
+    engine_out: *mut FlutterEngine,
+) -> FlutterEngineResult {
+    create(
+        config,
+        args,
+        user_data,
+        engine_out,
+        |assets_path, arguments| Call::Run {
+            assets_path,
+            arguments,
+        },
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineShutdown(engine: FlutterEngine) -> FlutterEngineResult {
+    if engine.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    let engine = Arc::from_raw(engine as *const MockEngine);
+    engine.record(Call::Shutdown);
+    FlutterEngineResult::kSuccess
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineInitialize(
+    _version: usize,
+    config: *const FlutterRendererConfig,
+    args: *const FlutterProjectArgs,
+    user_data: *mut c_void,
+    engine_out: *mut FlutterEngine,
+) -> FlutterEngineResult {
+    create(
+        config,
+        args,
+        user_data,
+        engine_out,
+        |assets_path, arguments| Call::Initialize {
+            assets_path,
+            arguments,
+        },
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineDeinitialize(engine: FlutterEngine) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock.record(Call::Deinitialize))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineRunInitialized(engine: FlutterEngine) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock.record(Call::RunInitialized))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineSendWindowMetricsEvent(
+    engine: FlutterEngine,
+    event: *const FlutterWindowMetricsEvent,
+) -> FlutterEngineResult {
+    if event.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    with_mock!(engine, |mock| mock
+        .record(Call::SendWindowMetricsEvent(*event)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineSendPointerEvent(
+    engine: FlutterEngine,
+    events: *const FlutterPointerEvent,
+    events_count: usize,
+) -> FlutterEngineResult {
+    if events.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    let events = slice::from_raw_parts(events, events_count).to_vec();
+    with_mock!(engine, |mock| mock.record(Call::SendPointerEvent(events)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineSendPlatformMessage(
+    engine: FlutterEngine,
+    message: *const FlutterPlatformMessage,
+) -> FlutterEngineResult {
+    if message.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    let message = &*message;
+    let response_handle = (message.response_handle as *const ResponseHandle).as_ref();
+    with_mock!(engine, |mock| {
+        if let Some(ResponseHandle::Embedder {
+            id,
+            data_callback,
+            user_data,
+        }) = response_handle
+        {
+            mock.pending_replies
+                .lock()
+                .unwrap()
+                .push((*id, *data_callback, *user_data));
+        }
+        mock.record(Call::SendPlatformMessage {
+            channel: string(message.channel),
+            message: slice::from_raw_parts(message.message, message.message_size).to_vec(),
+            response_handle: response_handle.map(ResponseHandle::id),
+        })
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterPlatformMessageCreateResponseHandle(
+    engine: FlutterEngine,
+    data_callback: FlutterDataCallback,
+    user_data: *mut c_void,
+    response_out: *mut *mut FlutterPlatformMessageResponseHandle,
+) -> FlutterEngineResult {
+    if response_out.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    with_mock!(engine, |mock| {
+        let id = mock.next_handle_id();
+        let handle = Box::new(ResponseHandle::Embedder {
+            id,
+            data_callback,
+            user_data,
+        });
+        *response_out = Box::into_raw(handle) as *mut FlutterPlatformMessageResponseHandle;
+        mock.record(Call::CreateResponseHandle(id))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterPlatformMessageReleaseResponseHandle(
+    engine: FlutterEngine,
+    response: *mut FlutterPlatformMessageResponseHandle,
+) -> FlutterEngineResult {
+    if response.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    with_mock!(engine, |mock| {
+        let handle = Box::from_raw(response as *mut ResponseHandle);
+        mock.record(Call::ReleaseResponseHandle(handle.id()))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineSendPlatformMessageResponse(
+    engine: FlutterEngine,
+    handle: *const FlutterPlatformMessageResponseHandle,
+    data: *const u8,
+    data_length: usize,
+) -> FlutterEngineResult {
+    if handle.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    let data = if data.is_null() {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(data, data_length).to_vec()
+    };
+    with_mock!(engine, |mock| {
+        // the engine owns the handles of messages sent to the embedder and
+        // frees them once the response is sent
+        let handle = Box::from_raw(handle as *mut ResponseHandle);
+        mock.record(Call::SendPlatformMessageResponse {
+            response_handle: handle.id(),
+            data,
+        })
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn __FlutterEngineFlushPendingTasksNow() -> FlutterEngineResult {
+    FlutterEngineResult::kSuccess
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineRegisterExternalTexture(
+    engine: FlutterEngine,
+    texture_identifier: i64,
+) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock
+        .record(Call::RegisterExternalTexture(texture_identifier)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineUnregisterExternalTexture(
+    engine: FlutterEngine,
+    texture_identifier: i64,
+) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock
+        .record(Call::UnregisterExternalTexture(texture_identifier)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineMarkExternalTextureFrameAvailable(
+    engine: FlutterEngine,
+    texture_identifier: i64,
+) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock.record(
+        Call::MarkExternalTextureFrameAvailable(texture_identifier)
+    ))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineUpdateSemanticsEnabled(
+    engine: FlutterEngine,
+    enabled: bool,
+) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock
+        .record(Call::UpdateSemanticsEnabled(enabled)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineUpdateAccessibilityFeatures(
+    engine: FlutterEngine,
+    features: FlutterAccessibilityFeature,
+) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock
+        .record(Call::UpdateAccessibilityFeatures(features.0)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineDispatchSemanticsAction(
+    engine: FlutterEngine,
+    id: u64,
+    action: FlutterSemanticsAction,
+    data: *const u8,
+    data_length: usize,
+) -> FlutterEngineResult {
+    let data = if data.is_null() {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(data, data_length).to_vec()
+    };
+    with_mock!(engine, |mock| mock.record(Call::DispatchSemanticsAction {
+        id,
+        action: action.0,
+        data,
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineOnVsync(
+    engine: FlutterEngine,
+    baton: isize,
+    frame_start_time_nanos: u64,
+    frame_target_time_nanos: u64,
+) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock.record(Call::OnVsync {
+        baton,
+        frame_start_time_nanos,
+        frame_target_time_nanos,
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineReloadSystemFonts(
+    engine: FlutterEngine,
+) -> FlutterEngineResult {
+    with_mock!(engine, |mock| mock.record(Call::ReloadSystemFonts))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineTraceEventDurationBegin(_name: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineTraceEventDurationEnd(_name: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineTraceEventInstant(_name: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEnginePostRenderThreadTask(
+    engine: FlutterEngine,
+    callback: VoidCallback,
+    callback_data: *mut c_void,
+) -> FlutterEngineResult {
+    // the mock has no render thread, so render thread tasks run right away
+    with_mock!(engine, |mock| {
+        mock.record(Call::PostRenderThreadTask);
+        if let Some(callback) = callback {
+            callback(callback_data);
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineGetCurrentTime() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineRunTask(
+    engine: FlutterEngine,
+    task: *const FlutterTask,
+) -> FlutterEngineResult {
+    if task.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    with_mock!(engine, |mock| mock.record(Call::RunTask((*task).task)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineUpdateLocales(
+    engine: FlutterEngine,
+    locales: *mut *const FlutterLocale,
+    locales_count: usize,
+) -> FlutterEngineResult {
+    if locales.is_null() {
+        return FlutterEngineResult::kInvalidArguments;
+    }
+    let locales = slice::from_raw_parts(locales, locales_count)
+        .iter()
+        .map(|locale| {
+            let locale = &**locale;
+            (
+                string(locale.language_code),
+                string(locale.country_code),
+                string(locale.script_code),
+                string(locale.variant_code),
+            )
+        })
+        .collect();
+    with_mock!(engine, |mock| mock.record(Call::UpdateLocales(locales)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn FlutterEngineRunsAOTCompiledDartCode() -> bool {
+    RUNS_AOT_COMPILED_DART_CODE.with(|cell| cell.get())
+}
@@ -0,0 +1,9 @@
+#![no_main]
+use flutter_engine::codec::{standard_codec::CODEC, MessageCodec, MethodCodec};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = CODEC.decode_message(data);
+    let _ = CODEC.decode_method_call(data);
+    let _ = CODEC.decode_envelope(data);
+});
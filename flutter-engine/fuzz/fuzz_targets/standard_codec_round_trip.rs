@@ -0,0 +1,13 @@
+#![no_main]
+use flutter_engine::codec::{standard_codec::CODEC, MessageCodec};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(value) = CODEC.decode_message(data) {
+        let buf = CODEC.encode_message(&value);
+        let decoded = CODEC
+            .decode_message(&buf)
+            .expect("encoded value does not decode");
+        assert_eq!(value, decoded);
+    }
+});
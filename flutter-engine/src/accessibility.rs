@@ -0,0 +1,388 @@
+//! Accessibility features requested by the platform.
+//!
+//! [`AccessibilityFeatures`] are pushed to the app with
+//! `FlutterEngine::update_accessibility_features`. On desktops, where these
+//! preferences live in the settings of the desktop environment,
+//! [`watch_desktop_settings`] keeps the engine in sync with a
+//! [`DesktopSettings`] source such as [`GSettings`].
+
+use crate::FlutterEngine;
+use bitflags::bitflags;
+use flutter_engine_sys::FlutterAccessibilityFeature;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+bitflags! {
+    /// Accessibility features that affect how the app is rendered.
+    #[derive(Default)]
+    pub struct AccessibilityFeatures: u32 {
+        /// An assistive technology such as a screen reader is navigating the
+        /// app.
+        const ACCESSIBLE_NAVIGATION = FlutterAccessibilityFeature::kFlutterAccessibilityFeatureAccessibleNavigation.0;
+        const INVERT_COLORS = FlutterAccessibilityFeature::kFlutterAccessibilityFeatureInvertColors.0;
+        const DISABLE_ANIMATIONS = FlutterAccessibilityFeature::kFlutterAccessibilityFeatureDisableAnimations.0;
+        const BOLD_TEXT = FlutterAccessibilityFeature::kFlutterAccessibilityFeatureBoldText.0;
+        const REDUCE_MOTION = FlutterAccessibilityFeature::kFlutterAccessibilityFeatureReduceMotion.0;
+        /// Not part of the embedder API of the engine version these bindings
+        /// are generated from, but understood by its `dart:ui`.
+        const HIGH_CONTRAST = 1 << 5;
+    }
+}
+
+impl From<FlutterAccessibilityFeature> for AccessibilityFeatures {
+    fn from(flags: FlutterAccessibilityFeature) -> Self {
+        Self::from_bits_truncate(flags.0)
+    }
+}
+
+impl From<AccessibilityFeatures> for FlutterAccessibilityFeature {
+    fn from(flags: AccessibilityFeatures) -> Self {
+        FlutterAccessibilityFeature(flags.bits())
+    }
+}
+
+/// Accessibility preferences of the desktop environment.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DesktopAccessibility {
+    pub screen_reader: bool,
+    pub reduce_motion: bool,
+    pub high_contrast: bool,
+    pub bold_text: bool,
+}
+
+impl DesktopAccessibility {
+    pub fn features(&self) -> AccessibilityFeatures {
+        let mut features = AccessibilityFeatures::empty();
+        if self.screen_reader {
+            features |= AccessibilityFeatures::ACCESSIBLE_NAVIGATION;
+        }
+        if self.reduce_motion {
+            features |= AccessibilityFeatures::DISABLE_ANIMATIONS;
+            features |= AccessibilityFeatures::REDUCE_MOTION;
+        }
+        if self.high_contrast {
+            features |= AccessibilityFeatures::HIGH_CONTRAST;
+        }
+        if self.bold_text {
+            features |= AccessibilityFeatures::BOLD_TEXT;
+        }
+        features
+    }
+}
+
+/// A source of desktop accessibility preferences.
+pub trait DesktopSettings: Send + 'static {
+    /// Returns the current preferences.
+    fn read(&mut self) -> DesktopAccessibility;
+
+    /// Blocks until the preferences may have changed. Returns `false` once
+    /// no more changes will be reported.
+    fn wait_for_change(&mut self) -> bool;
+}
+
+/// Preferences set by the application, e.g. to stand in for the desktop in
+/// tests.
+pub struct ManualSettings {
+    current: DesktopAccessibility,
+    receiver: Receiver<DesktopAccessibility>,
+}
+
+impl ManualSettings {
+    /// Returns the settings and a sender to change them. Dropping the sender
+    /// ends the watch.
+    pub fn new(initial: DesktopAccessibility) -> (Self, Sender<DesktopAccessibility>) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            Self {
+                current: initial,
+                receiver,
+            },
+            sender,
+        )
+    }
+}
+
+impl DesktopSettings for ManualSettings {
+    fn read(&mut self) -> DesktopAccessibility {
+        self.current
+    }
+
+    fn wait_for_change(&mut self) -> bool {
+        match self.receiver.recv() {
+            Ok(settings) => {
+                self.current = settings;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Reads preferences from a background thread and pushes every change to the
+/// engine.
+///
+/// `wait_for_change` blocks, so the thread exits at the first change after
+/// the engine is dropped, or when `settings` stops reporting changes. Until
+/// then it keeps `settings` alive, e.g. the `gsettings monitor` processes of
+/// [`GSettings`].
+pub fn watch_desktop_settings<S>(engine: &FlutterEngine, mut settings: S) -> JoinHandle<()>
+where
+    S: DesktopSettings,
+{
+    let engine = engine.downgrade();
+    thread::Builder::new()
+        .name("accessibility settings".into())
+        .spawn(move || {
+            let mut last = None;
+            loop {
+                let engine = match engine.upgrade() {
+                    Some(engine) => engine,
+                    None => return,
+                };
+                let features = settings.read().features();
+                if last != Some(features) {
+                    engine.run_on_platform_thread(move |engine| {
+                        engine.update_accessibility_features(features)
+                    });
+                    last = Some(features);
+                }
+                drop(engine);
+                if !settings.wait_for_change() {
+                    return;
+                }
+            }
+        })
+        .expect("Failed to spawn accessibility settings thread")
+}
+
+#[cfg(target_os = "linux")]
+pub use self::gsettings::GSettings;
+
+#[cfg(target_os = "linux")]
+mod gsettings {
+    use super::{DesktopAccessibility, DesktopSettings};
+    use log::debug;
+    use std::io::{self, BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    const INTERFACE: &str = "org.gnome.desktop.interface";
+    const A11Y_INTERFACE: &str = "org.gnome.desktop.a11y.interface";
+    const A11Y_APPLICATIONS: &str = "org.gnome.desktop.a11y.applications";
+
+    /// Preferences of GNOME and other GSettings based desktops, read with the
+    /// `gsettings` tool.
+    pub struct GSettings {
+        monitors: Vec<Child>,
+        changes: Receiver<()>,
+    }
+
+    impl GSettings {
+        /// Starts monitoring the relevant schemas for changes.
+        pub fn new() -> io::Result<Self> {
+            let (sender, changes) = mpsc::channel();
+            let mut monitors = Vec::new();
+            for schema in &[INTERFACE, A11Y_INTERFACE, A11Y_APPLICATIONS] {
+                let mut child = Command::new("gsettings")
+                    .args(["monitor", schema])
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()?;
+                let stdout = child.stdout.take().unwrap();
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for line in BufReader::new(stdout).lines() {
+                        match line {
+                            Ok(line) => debug!("gsettings changed: {}", line),
+                            Err(_) => break,
+                        }
+                        if sender.send(()).is_err() {
+                            break;
+                        }
+                    }
+                });
+                monitors.push(child);
+            }
+            Ok(Self { monitors, changes })
+        }
+
+        fn get(schema: &str, key: &str) -> Option<String> {
+            let output = Command::new("gsettings")
+                .args(["get", schema, key])
+                .stderr(Stdio::null())
+                .output()
+                .ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        }
+    }
+
+    impl DesktopSettings for GSettings {
+        fn read(&mut self) -> DesktopAccessibility {
+            parse(Self::get)
+        }
+
+        fn wait_for_change(&mut self) -> bool {
+            self.changes.recv().is_ok()
+        }
+    }
+
+    impl Drop for GSettings {
+        fn drop(&mut self) {
+            for monitor in &mut self.monitors {
+                monitor.kill().ok();
+                monitor.wait().ok();
+            }
+        }
+    }
+
+    /// Builds the preferences from `gsettings get` output. Missing schemas
+    /// leave the defaults.
+    pub(super) fn parse<F>(get: F) -> DesktopAccessibility
+    where
+        F: Fn(&str, &str) -> Option<String>,
+    {
+        let get_bool = |schema, key| get(schema, key).map(|value| value == "true");
+        DesktopAccessibility {
+            screen_reader: get_bool(A11Y_APPLICATIONS, "screen-reader-enabled").unwrap_or(false),
+            reduce_motion: !get_bool(INTERFACE, "enable-animations").unwrap_or(true),
+            high_contrast: get_bool(A11Y_INTERFACE, "high-contrast").unwrap_or(false)
+                || get(INTERFACE, "gtk-theme")
+                    .unwrap_or_default()
+                    .contains("HighContrast"),
+            // GNOME has no bold text preference, a bold interface font is the
+            // closest equivalent.
+            bold_text: get(INTERFACE, "font-name")
+                .unwrap_or_default()
+                .contains("Bold"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desktop_features() {
+        let settings = DesktopAccessibility {
+            reduce_motion: true,
+            high_contrast: true,
+            bold_text: true,
+            ..Default::default()
+        };
+        let features = settings.features();
+        assert!(features.contains(AccessibilityFeatures::HIGH_CONTRAST));
+        assert!(features.contains(AccessibilityFeatures::REDUCE_MOTION));
+        assert!(features.contains(AccessibilityFeatures::DISABLE_ANIMATIONS));
+        assert!(features.contains(AccessibilityFeatures::BOLD_TEXT));
+        assert!(!features.contains(AccessibilityFeatures::ACCESSIBLE_NAVIGATION));
+        assert!(DesktopAccessibility::default().features().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_gsettings() {
+        let settings = gsettings::parse(|schema, key| {
+            let value = match (schema, key) {
+                ("org.gnome.desktop.interface", "enable-animations") => "false",
+                ("org.gnome.desktop.interface", "gtk-theme") => "'HighContrast'",
+                ("org.gnome.desktop.interface", "font-name") => "'Cantarell Bold 11'",
+                _ => return None,
+            };
+            Some(value.to_owned())
+        });
+        assert_eq!(
+            settings,
+            DesktopAccessibility {
+                screen_reader: false,
+                reduce_motion: true,
+                high_contrast: true,
+                bold_text: true,
+            }
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_watch_desktop_settings() {
+        use crate::mock::{run_engine, Call, MockHandler};
+        use std::time::{Duration, Instant};
+
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let pushed = || {
+            mock.calls()
+                .into_iter()
+                .filter_map(|call| match call {
+                    Call::UpdateAccessibilityFeatures(features) => Some(features),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let wait_for = |count: usize| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while pushed().len() < count {
+                assert!(Instant::now() < deadline, "features were not pushed");
+                thread::sleep(Duration::from_millis(1));
+                engine.execute_platform_tasks();
+            }
+        };
+
+        let (settings, sender) = ManualSettings::new(DesktopAccessibility {
+            reduce_motion: true,
+            ..Default::default()
+        });
+        let watch = watch_desktop_settings(&engine, settings);
+        wait_for(1);
+
+        // Unchanged features are not pushed again.
+        sender
+            .send(DesktopAccessibility {
+                reduce_motion: true,
+                ..Default::default()
+            })
+            .unwrap();
+        sender
+            .send(DesktopAccessibility {
+                high_contrast: true,
+                ..Default::default()
+            })
+            .unwrap();
+        wait_for(2);
+        drop(sender);
+        watch.join().unwrap();
+        engine.execute_platform_tasks();
+
+        let reduce_motion =
+            AccessibilityFeatures::DISABLE_ANIMATIONS | AccessibilityFeatures::REDUCE_MOTION;
+        assert_eq!(
+            pushed(),
+            vec![
+                reduce_motion.bits(),
+                AccessibilityFeatures::HIGH_CONTRAST.bits()
+            ]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_watch_ends_after_engine_drop() {
+        use crate::mock::{run_engine, MockHandler};
+
+        let handler = MockHandler::new();
+        let (engine, _mock) = run_engine(&handler);
+        let (settings, sender) = ManualSettings::new(DesktopAccessibility::default());
+        let watch = watch_desktop_settings(&engine, settings);
+        drop(engine);
+
+        // The thread notices the dropped engine on the next change, even if
+        // the features stay the same.
+        sender.send(DesktopAccessibility::default()).unwrap();
+        watch.join().unwrap();
+        assert!(sender.send(DesktopAccessibility::default()).is_err());
+    }
+}
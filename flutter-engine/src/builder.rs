@@ -0,0 +1,394 @@
+//! Typed configuration of the engine.
+//!
+//! [`FlutterEngineBuilder`] covers every field of `FlutterProjectArgs` that is
+//! not a callback owned by the embedder and validates the configuration
+//! before the engine is created.
+
+use crate::compositor::Compositor;
+use crate::ffi::FlutterRendererType;
+use crate::snapshot::{AotSnapshot, LoadedSnapshot};
+use crate::vsync::VsyncSource;
+use crate::{FlutterEngine, FlutterEngineHandler};
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use std::{error, fmt, io, mem};
+
+/// A command line switch of the engine.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EngineSwitch {
+    /// The port the observatory listens on, `0` picks a free port.
+    ObservatoryPort(u16),
+    DisableObservatory,
+    DisableServiceAuthCodes,
+    StartPaused,
+    VerboseLogging,
+    TraceStartup,
+    TraceSkia,
+    EnableDartProfiling,
+    EnableSoftwareRendering,
+    SkiaDeterministicRendering,
+}
+
+impl EngineSwitch {
+    /// The switch as passed on the command line.
+    pub fn to_arg(&self) -> String {
+        let arg = match self {
+            EngineSwitch::ObservatoryPort(port) => return format!("--observatory-port={}", port),
+            EngineSwitch::DisableObservatory => "--disable-observatory",
+            EngineSwitch::DisableServiceAuthCodes => "--disable-service-auth-codes",
+            EngineSwitch::StartPaused => "--start-paused",
+            EngineSwitch::VerboseLogging => "--verbose-logging",
+            EngineSwitch::TraceStartup => "--trace-startup",
+            EngineSwitch::TraceSkia => "--trace-skia",
+            EngineSwitch::EnableDartProfiling => "--enable-dart-profiling",
+            EngineSwitch::EnableSoftwareRendering => "--enable-software-rendering",
+            EngineSwitch::SkiaDeterministicRendering => "--skia-deterministic-rendering",
+        };
+        arg.to_owned()
+    }
+
+    fn needs_observatory(&self) -> bool {
+        matches!(
+            self,
+            EngineSwitch::ObservatoryPort(_)
+                | EngineSwitch::DisableServiceAuthCodes
+                | EngineSwitch::StartPaused
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum BuildError {
+    /// A switch was given more than once.
+    DuplicateSwitch(EngineSwitch),
+    /// Two switches contradict each other.
+    ConflictingSwitches(EngineSwitch, EngineSwitch),
+    /// `EnableSoftwareRendering` was given with the OpenGL renderer.
+    SoftwareRenderingWithOpenGL,
+    /// The Dart entrypoint is empty or not a valid identifier.
+    InvalidEntrypoint(String),
+    /// The `icudtl.dat` file does not exist.
+    IcuDataNotFound(PathBuf),
+    /// The persistent cache path is not a directory.
+    InvalidPersistentCachePath(PathBuf),
+    /// The AOT snapshot could not be loaded.
+    AotSnapshot(io::Error),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::DuplicateSwitch(switch) => {
+                write!(f, "switch {} given more than once", switch.to_arg())
+            }
+            BuildError::ConflictingSwitches(a, b) => {
+                write!(f, "switches {} and {} conflict", a.to_arg(), b.to_arg())
+            }
+            BuildError::SoftwareRenderingWithOpenGL => write!(
+                f,
+                "--enable-software-rendering requires the software renderer"
+            ),
+            BuildError::InvalidEntrypoint(entrypoint) => {
+                write!(f, "invalid dart entrypoint {:?}", entrypoint)
+            }
+            BuildError::IcuDataNotFound(path) => {
+                write!(f, "icu data not found at {}", path.display())
+            }
+            BuildError::InvalidPersistentCachePath(path) => write!(
+                f,
+                "persistent cache path {} is not a directory",
+                path.display()
+            ),
+            BuildError::AotSnapshot(err) => write!(f, "failed to load aot snapshot: {}", err),
+        }
+    }
+}
+
+impl error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            BuildError::AotSnapshot(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of `FlutterProjectArgs` that are fixed when the engine is built.
+#[derive(Clone, Debug)]
+pub(crate) struct ProjectConfig {
+    pub icu_data_path: Option<PathBuf>,
+    pub persistent_cache_path: Option<PathBuf>,
+    pub is_persistent_cache_read_only: bool,
+    pub custom_dart_entrypoint: Option<String>,
+    pub shutdown_dart_vm_when_done: bool,
+    pub switches: Vec<EngineSwitch>,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        Self {
+            icu_data_path: None,
+            persistent_cache_path: None,
+            is_persistent_cache_read_only: false,
+            custom_dart_entrypoint: None,
+            shutdown_dart_vm_when_done: true,
+            switches: Vec::new(),
+        }
+    }
+}
+
+pub struct FlutterEngineBuilder {
+    handler: Weak<dyn FlutterEngineHandler>,
+    assets: PathBuf,
+    renderer: FlutterRendererType,
+    config: ProjectConfig,
+    compositor: Option<Weak<dyn Compositor>>,
+    vsync_source: Option<Arc<dyn VsyncSource>>,
+    aot_snapshot: Option<AotSnapshot>,
+}
+
+impl FlutterEngineBuilder {
+    pub fn new(handler: Weak<dyn FlutterEngineHandler>, assets: PathBuf) -> Self {
+        Self {
+            handler,
+            assets,
+            renderer: FlutterRendererType::OpenGL,
+            config: Default::default(),
+            compositor: None,
+            vsync_source: None,
+            aot_snapshot: None,
+        }
+    }
+
+    pub fn with_renderer(mut self, renderer: FlutterRendererType) -> Self {
+        self.renderer = renderer;
+        self
+    }
+
+    /// Loads ICU data from an `icudtl.dat` file instead of the data linked
+    /// into the engine.
+    pub fn with_icu_data_path(mut self, path: PathBuf) -> Self {
+        self.config.icu_data_path = Some(path);
+        self
+    }
+
+    /// Caches data such as compiled shaders across runs in `path`. With
+    /// `read_only` the engine uses the cache without adding to it.
+    pub fn with_persistent_cache(mut self, path: PathBuf, read_only: bool) -> Self {
+        self.config.persistent_cache_path = Some(path);
+        self.config.is_persistent_cache_read_only = read_only;
+        self
+    }
+
+    /// Runs the Dart function `entrypoint` instead of `main`. It has to be
+    /// annotated with `@pragma('vm:entry-point')`.
+    pub fn with_dart_entrypoint(mut self, entrypoint: &str) -> Self {
+        self.config.custom_dart_entrypoint = Some(entrypoint.to_owned());
+        self
+    }
+
+    /// Whether the Dart VM is shut down with the engine, defaults to `true`.
+    pub fn with_shutdown_dart_vm_when_done(mut self, shutdown: bool) -> Self {
+        self.config.shutdown_dart_vm_when_done = shutdown;
+        self
+    }
+
+    pub fn with_switch(mut self, switch: EngineSwitch) -> Self {
+        self.config.switches.push(switch);
+        self
+    }
+
+    pub fn with_compositor(mut self, compositor: Weak<dyn Compositor>) -> Self {
+        self.compositor = Some(compositor);
+        self
+    }
+
+    pub fn with_vsync_source(mut self, vsync_source: Arc<dyn VsyncSource>) -> Self {
+        self.vsync_source = Some(vsync_source);
+        self
+    }
+
+    pub fn with_aot_snapshot(mut self, snapshot: AotSnapshot) -> Self {
+        self.aot_snapshot = Some(snapshot);
+        self
+    }
+
+    /// Validates the configuration and creates the engine. The engine still
+    /// has to be started with `FlutterEngine::run`.
+    pub fn build(mut self) -> Result<FlutterEngine, BuildError> {
+        self.validate()?;
+        let snapshot = match self.aot_snapshot.take() {
+            Some(snapshot) => {
+                Some(LoadedSnapshot::load(snapshot).map_err(BuildError::AotSnapshot)?)
+            }
+            None => None,
+        };
+
+        let engine = FlutterEngine::with_config(
+            self.handler,
+            self.assets,
+            self.renderer,
+            mem::take(&mut self.config),
+        );
+        if let Some(compositor) = self.compositor {
+            engine.set_compositor(compositor);
+        }
+        if let Some(vsync_source) = self.vsync_source {
+            engine.set_vsync_source(vsync_source);
+        }
+        if let Some(snapshot) = snapshot {
+            engine.inner.aot_snapshot.write().replace(snapshot);
+        }
+        Ok(engine)
+    }
+
+    fn validate(&self) -> Result<(), BuildError> {
+        let switches = &self.config.switches;
+        for (i, switch) in switches.iter().enumerate() {
+            for other in &switches[i + 1..] {
+                if mem::discriminant(switch) == mem::discriminant(other) {
+                    return Err(BuildError::DuplicateSwitch(other.clone()));
+                }
+            }
+        }
+        if let Some(disable) = switches
+            .iter()
+            .find(|switch| **switch == EngineSwitch::DisableObservatory)
+        {
+            if let Some(switch) = switches.iter().find(|switch| switch.needs_observatory()) {
+                return Err(BuildError::ConflictingSwitches(
+                    disable.clone(),
+                    switch.clone(),
+                ));
+            }
+        }
+        if self.renderer == FlutterRendererType::OpenGL
+            && switches.contains(&EngineSwitch::EnableSoftwareRendering)
+        {
+            return Err(BuildError::SoftwareRenderingWithOpenGL);
+        }
+
+        if let Some(entrypoint) = &self.config.custom_dart_entrypoint {
+            if !is_dart_identifier(entrypoint) {
+                return Err(BuildError::InvalidEntrypoint(entrypoint.clone()));
+            }
+        }
+        if let Some(path) = &self.config.icu_data_path {
+            if !path.is_file() {
+                return Err(BuildError::IcuDataNotFound(path.clone()));
+            }
+        }
+        if let Some(path) = &self.config.persistent_cache_path {
+            if !path.is_dir() {
+                return Err(BuildError::InvalidPersistentCachePath(path.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_dart_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_engine, Call, MockHandler, MockProjectArgs};
+
+    fn builder(handler: &Arc<MockHandler>) -> FlutterEngineBuilder {
+        FlutterEngineBuilder::new(
+            Arc::downgrade(handler) as Weak<dyn FlutterEngineHandler>,
+            PathBuf::from("assets"),
+        )
+    }
+
+    #[test]
+    fn test_builder_configures_project_args() {
+        let handler = MockHandler::new();
+        let cache = std::env::temp_dir();
+        let engine = builder(&handler)
+            .with_switch(EngineSwitch::VerboseLogging)
+            .with_switch(EngineSwitch::ObservatoryPort(0))
+            .with_dart_entrypoint("customMain")
+            .with_persistent_cache(cache.clone(), true)
+            .with_shutdown_dart_vm_when_done(false)
+            .build()
+            .unwrap();
+        engine.run(&["--trace-systrace".into()]).unwrap();
+        let mock = mock_engine(&engine);
+
+        match &mock.calls()[0] {
+            Call::Run { arguments, .. } => assert_eq!(
+                arguments,
+                &[
+                    "flutter-rs",
+                    "--icu-symbol-prefix=gIcudtl",
+                    "--verbose-logging",
+                    "--observatory-port=0",
+                    "--trace-systrace",
+                ]
+            ),
+            call => panic!("unexpected call {:?}", call),
+        }
+        assert_eq!(
+            mock.project_args(),
+            &MockProjectArgs {
+                icu_data_path: None,
+                persistent_cache_path: Some(cache.to_string_lossy().into_owned()),
+                is_persistent_cache_read_only: true,
+                custom_dart_entrypoint: Some("customMain".into()),
+                shutdown_dart_vm_when_done: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let handler = MockHandler::new();
+        let error = |builder: FlutterEngineBuilder| builder.build().err().unwrap();
+
+        match error(
+            builder(&handler)
+                .with_switch(EngineSwitch::ObservatoryPort(1))
+                .with_switch(EngineSwitch::ObservatoryPort(2)),
+        ) {
+            BuildError::DuplicateSwitch(EngineSwitch::ObservatoryPort(2)) => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+        match error(
+            builder(&handler)
+                .with_switch(EngineSwitch::StartPaused)
+                .with_switch(EngineSwitch::DisableObservatory),
+        ) {
+            BuildError::ConflictingSwitches(
+                EngineSwitch::DisableObservatory,
+                EngineSwitch::StartPaused,
+            ) => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+        match error(builder(&handler).with_switch(EngineSwitch::EnableSoftwareRendering)) {
+            BuildError::SoftwareRenderingWithOpenGL => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+        match error(builder(&handler).with_dart_entrypoint("1main")) {
+            BuildError::InvalidEntrypoint(_) => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+        match error(builder(&handler).with_icu_data_path(PathBuf::from("missing/icudtl.dat"))) {
+            BuildError::IcuDataNotFound(_) => {}
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        assert!(builder(&handler)
+            .with_renderer(FlutterRendererType::Software)
+            .with_switch(EngineSwitch::EnableSoftwareRendering)
+            .build()
+            .is_ok());
+    }
+}
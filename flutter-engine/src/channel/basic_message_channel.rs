@@ -1,7 +1,10 @@
 use std::sync::{Arc, RwLock, Weak};
 
 use crate::{
-    channel::{ChannelImpl, MessageChannel, MessageHandler},
+    channel::{
+        AsyncMessageHandler, ChannelImpl, ExecutionPolicy, MessageChannel, MessageHandler,
+        MessageHandlerRef, MessageQueue,
+    },
     codec::MessageCodec,
     FlutterEngine, FlutterEngineWeakRef,
 };
@@ -11,9 +14,10 @@ use log::error;
 pub struct BasicMessageChannel {
     name: String,
     engine: FlutterEngineWeakRef,
-    message_handler: Weak<RwLock<dyn MessageHandler + Send + Sync>>,
+    message_handler: MessageHandlerRef,
     plugin_name: Option<&'static str>,
-    codec: &'static dyn MessageCodec,
+    queue: MessageQueue,
+    codec: Arc<dyn MessageCodec>,
 }
 
 impl BasicMessageChannel {
@@ -25,14 +29,49 @@ impl BasicMessageChannel {
         Self {
             name: name.as_ref().to_owned(),
             engine: Default::default(),
-            message_handler,
+            message_handler: MessageHandlerRef::Sync(message_handler),
             plugin_name: None,
-            codec,
+            queue: Default::default(),
+            codec: Arc::new(codec),
         }
     }
 
     pub fn set_handler(&mut self, message_handler: Weak<RwLock<dyn MessageHandler + Send + Sync>>) {
-        self.message_handler = message_handler;
+        self.message_handler = MessageHandlerRef::Sync(message_handler);
+    }
+
+    /// Creates a channel whose messages are answered by an async handler.
+    pub fn new_async<N: AsRef<str>>(
+        name: N,
+        message_handler: Weak<dyn AsyncMessageHandler + Send + Sync>,
+        codec: &'static dyn MessageCodec,
+    ) -> Self {
+        Self {
+            name: name.as_ref().to_owned(),
+            engine: Default::default(),
+            message_handler: MessageHandlerRef::Async(message_handler),
+            plugin_name: None,
+            queue: Default::default(),
+            codec: Arc::new(codec),
+        }
+    }
+
+    pub fn set_async_handler(
+        &mut self,
+        message_handler: Weak<dyn AsyncMessageHandler + Send + Sync>,
+    ) {
+        self.message_handler = MessageHandlerRef::Async(message_handler);
+    }
+
+    /// Sets how messages received on this channel are run.
+    pub fn set_execution_policy(&mut self, policy: ExecutionPolicy) {
+        self.queue = MessageQueue::new(policy);
+    }
+
+    /// Uses a codec created at runtime, like a standard codec with
+    /// extensions for custom types.
+    pub fn set_codec(&mut self, codec: Arc<dyn MessageCodec>) {
+        self.codec = codec;
     }
 }
 
@@ -60,11 +99,19 @@ impl ChannelImpl for BasicMessageChannel {
 
 impl MessageChannel for BasicMessageChannel {
     fn message_handler(&self) -> Option<Arc<RwLock<dyn MessageHandler + Send + Sync>>> {
-        self.message_handler.upgrade()
+        self.message_handler.sync_handler()
+    }
+
+    fn async_message_handler(&self) -> Option<Arc<dyn AsyncMessageHandler + Send + Sync>> {
+        self.message_handler.async_handler()
+    }
+
+    fn codec(&self) -> Arc<dyn MessageCodec> {
+        self.codec.clone()
     }
 
-    fn codec(&self) -> &'static dyn MessageCodec {
-        self.codec
+    fn message_queue(&self) -> &MessageQueue {
+        &self.queue
     }
 }
 
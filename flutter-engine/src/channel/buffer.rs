@@ -0,0 +1,95 @@
+//! Messages received on channels that are not registered yet.
+
+use std::collections::VecDeque;
+
+use crate::PlatformMessageResponseHandle;
+
+/// Which message to discard when a channel buffer is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest buffered message to make room for the new one.
+    DropOldest,
+    /// Discard the new message.
+    DropNewest,
+}
+
+/// How many messages are kept for a channel until it is registered.
+///
+/// The default keeps one message and discards older ones, like the channel
+/// buffers of flutter itself. Discarded messages get an empty reply, so their
+/// senders do not wait forever, e.g. for plugins the application does not
+/// use. A capacity of zero disables buffering.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BufferConfig {
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1,
+            overflow: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+pub(crate) struct BufferedMessage {
+    pub(crate) message: Vec<u8>,
+    pub(crate) response_handle: Option<PlatformMessageResponseHandle>,
+}
+
+/// A ring buffer of the messages of one channel.
+#[derive(Default)]
+pub(crate) struct ChannelBuffer {
+    messages: VecDeque<BufferedMessage>,
+}
+
+impl ChannelBuffer {
+    /// Adds a message and returns the messages that no longer fit.
+    pub(crate) fn push(
+        &mut self,
+        message: BufferedMessage,
+        config: BufferConfig,
+    ) -> Vec<BufferedMessage> {
+        if config.capacity == 0 {
+            return vec![message];
+        }
+        match config.overflow {
+            OverflowPolicy::DropOldest => {
+                let discarded = self.resize(config.capacity - 1, config.overflow);
+                self.messages.push_back(message);
+                discarded
+            }
+            OverflowPolicy::DropNewest => {
+                let mut discarded = self.resize(config.capacity, config.overflow);
+                if self.messages.len() < config.capacity {
+                    self.messages.push_back(message);
+                } else {
+                    discarded.push(message);
+                }
+                discarded
+            }
+        }
+    }
+
+    /// Shrinks the buffer to `capacity` and returns the discarded messages.
+    pub(crate) fn resize(
+        &mut self,
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> Vec<BufferedMessage> {
+        let excess = self.messages.len().saturating_sub(capacity);
+        match overflow {
+            OverflowPolicy::DropOldest => self.messages.drain(..excess).collect(),
+            OverflowPolicy::DropNewest => {
+                let len = self.messages.len();
+                self.messages.drain(len - excess..).collect()
+            }
+        }
+    }
+
+    pub(crate) fn into_messages(self) -> impl Iterator<Item = BufferedMessage> {
+        self.messages.into_iter()
+    }
+}
@@ -3,8 +3,10 @@ use std::sync::{Arc, RwLock, Weak};
 use log::error;
 
 use crate::{
-    channel::{ChannelImpl, EventHandler, MethodCallHandler, MethodChannel},
-    codec::{standard_codec::CODEC, MethodCall, MethodCodec, Value},
+    channel::{
+        ChannelImpl, EventHandler, ExecutionPolicy, MessageQueue, MethodCallHandler, MethodChannel,
+    },
+    codec::{standard_codec::StandardMethodCodec, MethodCall, MethodCodec, Value},
     error::MethodCallError,
     FlutterEngine, FlutterEngineWeakRef,
 };
@@ -14,6 +16,8 @@ pub struct EventChannel {
     engine: FlutterEngineWeakRef,
     method_handler: Arc<RwLock<dyn MethodCallHandler + Send + Sync>>,
     plugin_name: Option<&'static str>,
+    queue: MessageQueue,
+    codec: Arc<StandardMethodCodec>,
 }
 
 struct EventChannelMethodCallHandler {
@@ -30,8 +34,20 @@ impl EventChannel {
             engine: Default::default(),
             method_handler: Arc::new(RwLock::new(EventChannelMethodCallHandler::new(handler))),
             plugin_name: None,
+            queue: Default::default(),
+            codec: Default::default(),
         }
     }
+
+    /// Sets how messages received on this channel are run.
+    pub fn set_execution_policy(&mut self, policy: ExecutionPolicy) {
+        self.queue = MessageQueue::new(policy);
+    }
+
+    /// Uses a codec with extensions for custom types.
+    pub fn set_codec(&mut self, codec: Arc<StandardMethodCodec>) {
+        self.codec = codec;
+    }
 }
 
 impl ChannelImpl for EventChannel {
@@ -61,8 +77,12 @@ impl MethodChannel for EventChannel {
         Some(Arc::clone(&self.method_handler))
     }
 
-    fn codec(&self) -> &'static dyn MethodCodec {
-        &CODEC
+    fn codec(&self) -> Arc<dyn MethodCodec> {
+        self.codec.clone()
+    }
+
+    fn message_queue(&self) -> &MessageQueue {
+        &self.queue
     }
 }
 
@@ -0,0 +1,239 @@
+//! Hooks that see every platform message before it is handled or sent.
+
+use std::{borrow::Cow, sync::Arc};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+/// Where a platform message is going.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageDirection {
+    /// A message from dart to a channel.
+    Inbound,
+    /// A message from a channel to dart.
+    Outbound,
+    /// The reply to an inbound message.
+    Response,
+    /// The reply of dart to an outbound message.
+    Reply,
+}
+
+/// What to do with an intercepted message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Interception {
+    /// Pass the message, including any changes, to the next interceptor.
+    Continue,
+    /// Do not deliver the message. Its sender gets an empty reply, as if no
+    /// handler was registered for the channel.
+    Drop,
+    /// Do not deliver the message and answer it with these bytes instead.
+    /// Dropping or answering a response or reply replaces its bytes. The
+    /// answer to an inbound message is intercepted as a response, the answer
+    /// to an outbound message as a reply.
+    Reply(Vec<u8>),
+}
+
+/// Sees the raw bytes of platform messages on all channels.
+///
+/// Interceptors run in the order they were added on the platform thread. A
+/// message that is dropped or answered is not passed to later interceptors.
+pub trait ChannelInterceptor: Send + Sync {
+    fn intercept(
+        &self,
+        channel: &str,
+        direction: MessageDirection,
+        message: &mut Vec<u8>,
+    ) -> Interception;
+}
+
+impl<F> ChannelInterceptor for F
+where
+    F: Fn(&str, MessageDirection, &mut Vec<u8>) -> Interception + Send + Sync,
+{
+    fn intercept(
+        &self,
+        channel: &str,
+        direction: MessageDirection,
+        message: &mut Vec<u8>,
+    ) -> Interception {
+        self(channel, direction, message)
+    }
+}
+
+pub(crate) enum Intercepted<'a> {
+    Pass(Cow<'a, [u8]>),
+    Drop,
+    Reply(Vec<u8>),
+}
+
+/// The interceptors of an engine, shared between the plugin registrar and
+/// the engine.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Arc<RwLock<Vec<Arc<dyn ChannelInterceptor>>>>);
+
+impl Interceptors {
+    pub(crate) fn add(&self, interceptor: Arc<dyn ChannelInterceptor>) {
+        self.0.write().push(interceptor);
+    }
+
+    pub(crate) fn intercept<'a>(
+        &self,
+        channel: &str,
+        direction: MessageDirection,
+        message: &'a [u8],
+    ) -> Intercepted<'a> {
+        let interceptors = self.0.read().clone();
+        if interceptors.is_empty() {
+            return Intercepted::Pass(Cow::Borrowed(message));
+        }
+        let mut message = message.to_vec();
+        for interceptor in interceptors {
+            match interceptor.intercept(channel, direction, &mut message) {
+                Interception::Continue => {}
+                Interception::Drop => return Intercepted::Drop,
+                Interception::Reply(reply) => return Intercepted::Reply(reply),
+            }
+        }
+        Intercepted::Pass(Cow::Owned(message))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::channel::MethodChannel;
+    use crate::codec::{MethodCall, Value};
+    use crate::error::MethodCallError;
+    use crate::mock::{
+        block_on, echo_call, echoed, register_echo, run_engine, Call, MockHandler, ECHO_CHANNEL,
+    };
+    use crate::FlutterEngine;
+    use parking_lot::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Recorder {
+        messages: Arc<Mutex<Vec<(String, MessageDirection)>>>,
+    }
+
+    impl ChannelInterceptor for Recorder {
+        fn intercept(
+            &self,
+            channel: &str,
+            direction: MessageDirection,
+            _message: &mut Vec<u8>,
+        ) -> Interception {
+            self.messages.lock().push((channel.to_owned(), direction));
+            Interception::Continue
+        }
+    }
+
+    struct Answer(Interception);
+
+    impl ChannelInterceptor for Answer {
+        fn intercept(&self, _: &str, _: MessageDirection, _: &mut Vec<u8>) -> Interception {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn test_interceptor_sees_and_modifies_messages() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let _echo = register_echo(&engine);
+        let recorder = Recorder::default();
+        engine.add_interceptor(recorder.clone());
+        engine.add_interceptor(|channel: &str, direction, message: &mut Vec<u8>| {
+            if channel == ECHO_CHANNEL && direction == MessageDirection::Inbound {
+                *message = echo_call("changed");
+            }
+            Interception::Continue
+        });
+
+        let handle = mock.send_platform_message(ECHO_CHANNEL, &echo_call("hello"));
+        handler.pump(&engine);
+        assert_eq!(
+            echoed(mock.response(handle)),
+            Value::String("changed".into())
+        );
+        assert_eq!(
+            *recorder.messages.lock(),
+            vec![
+                (ECHO_CHANNEL.to_owned(), MessageDirection::Inbound),
+                (ECHO_CHANNEL.to_owned(), MessageDirection::Response),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interceptor_answers_inbound_message() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let _echo = register_echo(&engine);
+        engine.add_interceptor(Answer(Interception::Reply(b"denied".to_vec())));
+
+        let handle = mock.send_platform_message(ECHO_CHANNEL, &echo_call("hello"));
+        handler.pump(&engine);
+        assert_eq!(mock.response(handle), Some(b"denied".to_vec()));
+    }
+
+    /// Answers inbound messages with `interception` and replaces the bytes of
+    /// every response.
+    fn answer_and_rewrite(engine: &FlutterEngine, interception: Interception) -> Recorder {
+        let recorder = Recorder::default();
+        engine.add_interceptor(move |_: &str, direction, _: &mut Vec<u8>| match direction {
+            MessageDirection::Inbound => interception.clone(),
+            _ => Interception::Continue,
+        });
+        engine.add_interceptor(recorder.clone());
+        engine.add_interceptor(|_: &str, direction, message: &mut Vec<u8>| {
+            if direction == MessageDirection::Response {
+                *message = b"rewritten".to_vec();
+            }
+            Interception::Continue
+        });
+        recorder
+    }
+
+    #[test]
+    fn test_answer_to_inbound_message_is_intercepted() {
+        for interception in [Interception::Reply(b"denied".to_vec()), Interception::Drop] {
+            let handler = MockHandler::new();
+            let (engine, mock) = run_engine(&handler);
+            let _echo = register_echo(&engine);
+            let recorder = answer_and_rewrite(&engine, interception);
+
+            let handle = mock.send_platform_message(ECHO_CHANNEL, &echo_call("hello"));
+            handler.pump(&engine);
+            assert_eq!(mock.response(handle), Some(b"rewritten".to_vec()));
+            assert_eq!(
+                *recorder.messages.lock(),
+                vec![(ECHO_CHANNEL.to_owned(), MessageDirection::Response)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_interceptor_drops_outbound_message() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let (_echo, channel) = register_echo(&engine);
+        engine.add_interceptor(Answer(Interception::Drop));
+
+        let reply = channel.upgrade().unwrap().invoke_method_with_reply(
+            MethodCall {
+                method: "getValue".into(),
+                args: Value::Null,
+            },
+            None,
+        );
+        match block_on(Box::pin(reply)) {
+            Err(MethodCallError::NotImplemented) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert!(!mock
+            .calls()
+            .iter()
+            .any(|call| matches!(call, Call::SendPlatformMessage { .. })));
+    }
+}
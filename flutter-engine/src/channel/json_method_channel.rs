@@ -3,7 +3,10 @@ use std::sync::{Arc, RwLock, Weak};
 use log::error;
 
 use crate::{
-    channel::{ChannelImpl, MethodCallHandler, MethodChannel},
+    channel::{
+        AsyncMethodCallHandler, ChannelImpl, ExecutionPolicy, MessageQueue, MethodCallHandler,
+        MethodCallHandlerRef, MethodChannel,
+    },
     codec::{json_codec::CODEC, MethodCodec},
     FlutterEngine, FlutterEngineWeakRef,
 };
@@ -11,8 +14,9 @@ use crate::{
 pub struct JsonMethodChannel {
     name: String,
     engine: FlutterEngineWeakRef,
-    method_handler: Weak<RwLock<dyn MethodCallHandler + Send + Sync>>,
+    method_handler: MethodCallHandlerRef,
     plugin_name: Option<&'static str>,
+    queue: MessageQueue,
 }
 
 impl JsonMethodChannel {
@@ -23,8 +27,9 @@ impl JsonMethodChannel {
         Self {
             name: name.as_ref().to_owned(),
             engine: Default::default(),
-            method_handler,
+            method_handler: MethodCallHandlerRef::Sync(method_handler),
             plugin_name: None,
+            queue: Default::default(),
         }
     }
 
@@ -32,7 +37,33 @@ impl JsonMethodChannel {
         &mut self,
         method_handler: Weak<RwLock<dyn MethodCallHandler + Send + Sync>>,
     ) {
-        self.method_handler = method_handler;
+        self.method_handler = MethodCallHandlerRef::Sync(method_handler);
+    }
+
+    /// Creates a channel whose calls are answered by an async handler.
+    pub fn new_async<N: AsRef<str>>(
+        name: N,
+        method_handler: Weak<dyn AsyncMethodCallHandler + Send + Sync>,
+    ) -> Self {
+        Self {
+            name: name.as_ref().to_owned(),
+            engine: Default::default(),
+            method_handler: MethodCallHandlerRef::Async(method_handler),
+            plugin_name: None,
+            queue: Default::default(),
+        }
+    }
+
+    pub fn set_async_handler(
+        &mut self,
+        method_handler: Weak<dyn AsyncMethodCallHandler + Send + Sync>,
+    ) {
+        self.method_handler = MethodCallHandlerRef::Async(method_handler);
+    }
+
+    /// Sets how messages received on this channel are run.
+    pub fn set_execution_policy(&mut self, policy: ExecutionPolicy) {
+        self.queue = MessageQueue::new(policy);
     }
 }
 
@@ -60,11 +91,19 @@ impl ChannelImpl for JsonMethodChannel {
 
 impl MethodChannel for JsonMethodChannel {
     fn method_handler(&self) -> Option<Arc<RwLock<dyn MethodCallHandler + Send + Sync>>> {
-        self.method_handler.upgrade()
+        self.method_handler.sync_handler()
+    }
+
+    fn async_method_handler(&self) -> Option<Arc<dyn AsyncMethodCallHandler + Send + Sync>> {
+        self.method_handler.async_handler()
+    }
+
+    fn codec(&self) -> Arc<dyn MethodCodec> {
+        Arc::new(CODEC)
     }
 
-    fn codec(&self) -> &'static dyn MethodCodec {
-        &CODEC
+    fn message_queue(&self) -> &MessageQueue {
+        &self.queue
     }
 }
 
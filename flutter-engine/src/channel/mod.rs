@@ -4,20 +4,36 @@
 
 use std::{
     borrow::Cow,
-    sync::{Arc, RwLock},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, PoisonError, RwLock, Weak},
+    time::Duration,
 };
 
+use parking_lot::Mutex;
+
 use crate::{
     codec::{MessageCodec, MethodCall, MethodCallResult, MethodCodec, Value},
     error::{MessageError, MethodCallError},
     FlutterEngine, FlutterEngineWeakRef, PlatformMessage, PlatformMessageResponseHandle,
 };
 
+/// The error code of the reply to a method call that could not be decoded.
+pub const DECODE_ERROR_CODE: &str = "decode_error";
+
+/// The error code of the reply to a method call whose handler panicked.
+pub const PANIC_ERROR_CODE: &str = "panic";
+
 pub use self::{
     basic_message_channel::BasicMessageChannel,
+    buffer::{BufferConfig, OverflowPolicy},
     event_channel::EventChannel,
+    interceptor::{ChannelInterceptor, Interception, MessageDirection},
     json_method_channel::JsonMethodChannel,
+    queue::{ExecutionPolicy, MessageQueue},
+    recording::{ChannelRecorder, RecordedMessage, Recording, Replayer},
     registry::{ChannelRegistrar, ChannelRegistry},
+    reply::MethodCallReply,
     standard_method_channel::StandardMethodChannel,
 };
 
@@ -25,9 +41,14 @@ pub use self::{
 mod macros;
 
 mod basic_message_channel;
+mod buffer;
 mod event_channel;
+pub(crate) mod interceptor;
 mod json_method_channel;
+mod queue;
+mod recording;
 mod registry;
+mod reply;
 mod standard_method_channel;
 
 trait ChannelImpl {
@@ -51,7 +72,7 @@ pub trait Channel {
     /// This method send a response to flutter. This is a low level method.
     fn send_response(&self, response_handle: PlatformMessageResponseHandle, buf: &[u8]) {
         if let Some(engine) = self.engine() {
-            engine.send_platform_message_response(response_handle, buf);
+            engine.send_platform_message_response(self.name(), response_handle, buf);
         } else {
             log::error!("Channel {} was not initialized", self.name());
         }
@@ -78,29 +99,78 @@ pub trait Channel {
 
 pub trait MethodChannel: Channel {
     fn method_handler(&self) -> Option<Arc<RwLock<dyn MethodCallHandler + Send + Sync>>>;
-    fn codec(&self) -> &'static dyn MethodCodec;
+    fn codec(&self) -> Arc<dyn MethodCodec>;
+    fn message_queue(&self) -> &MessageQueue;
+
+    fn async_method_handler(&self) -> Option<Arc<dyn AsyncMethodCallHandler + Send + Sync>> {
+        None
+    }
 
     /// Handle incoming message received on this channel
     fn handle_platform_message(&self, mut msg: PlatformMessage) {
         debug_assert_eq!(msg.channel, self.name());
-        if let Some(handler) = self.method_handler() {
-            if let Some(engine) = self.engine() {
-                let call = self.codec().decode_method_call(msg.message).unwrap();
-                let channel = self.name().to_owned();
-                log::trace!(
-                    "on channel {}, got method call {} with args {:?}",
-                    channel,
-                    call.method,
-                    call.args
-                );
-                let plugin_name = self.plugin_name();
-                let mut response_handle = msg.response_handle.take();
-                let codec = self.codec();
-
-                engine.clone().run_in_background(async move {
-                    let mut handler = handler.write().unwrap();
+        let handler = match self.async_method_handler() {
+            Some(handler) => AnyMethodCallHandler::Async(handler),
+            None => match self.method_handler() {
+                Some(handler) => AnyMethodCallHandler::Sync(handler),
+                None => return,
+            },
+        };
+        if let Some(engine) = self.engine() {
+            let channel = self.name().to_owned();
+            let call = match self.codec().decode_method_call(msg.message) {
+                Ok(call) => call,
+                Err(error) => {
+                    log::error!(
+                        target: self.plugin_name(),
+                        "invalid method call on channel {}: {}",
+                        channel,
+                        error
+                    );
+                    let response_handle = Arc::new(Mutex::new(msg.response_handle.take()));
+                    let buf = self.codec().encode_error_envelope(
+                        DECODE_ERROR_CODE,
+                        &error.to_string(),
+                        &Value::Null,
+                    );
+                    // replied through the queue to keep the order of replies
+                    self.message_queue().submit(
+                        engine,
+                        channel,
+                        async move { queue::respond(response_handle, buf) },
+                        || None,
+                    );
+                    return;
+                }
+            };
+            log::trace!(
+                "on channel {}, got method call {} with args {:?}",
+                channel,
+                call.method,
+                call.args
+            );
+            let plugin_name = self.plugin_name();
+            let response_handle = Arc::new(Mutex::new(msg.response_handle.take()));
+            let codec = self.codec();
+            let on_panic = {
+                let response_handle = response_handle.clone();
+                let codec = codec.clone();
+                move || {
+                    let buf = codec.encode_error_envelope(
+                        PANIC_ERROR_CODE,
+                        "the method call handler panicked",
+                        &Value::Null,
+                    );
+                    queue::respond(response_handle, buf)
+                }
+            };
+
+            self.message_queue().submit(
+                engine.clone(),
+                channel.clone(),
+                async move {
                     let method = call.method.clone();
-                    let result = handler.on_method_call(call, engine.clone());
+                    let result = handler.on_method_call(call, engine.clone()).await;
                     let response = match result {
                         Ok(value) => MethodCallResult::Ok(value),
                         Err(MethodCallError::NotImplemented) => {
@@ -125,17 +195,11 @@ pub trait MethodChannel: Channel {
                             error.into()
                         }
                     };
-                    engine.post_platform_callback(crate::MainThreadCallback::Channel((
-                        channel,
-                        Box::new(move |channel| {
-                            let buf = codec.encode_method_call_response(&response);
-                            if let Some(handle) = response_handle.take() {
-                                channel.send_response(handle, &buf);
-                            }
-                        }),
-                    )));
-                });
-            }
+                    let buf = codec.encode_method_call_response(&response);
+                    queue::respond(response_handle, buf)
+                },
+                on_panic,
+            );
         }
     }
 
@@ -145,6 +209,26 @@ pub trait MethodChannel: Channel {
         self.send_buffer(&buf);
     }
 
+    /// Invoke a flutter method using this channel and wait for its result.
+    /// Fails with `MethodCallError::Timeout` if flutter does not answer
+    /// within `timeout`.
+    ///
+    /// Can be called from any thread, e.g. from an async handler. The call is
+    /// sent once the platform thread runs its tasks.
+    fn invoke_method_with_reply(
+        &self,
+        method_call: MethodCall,
+        timeout: Option<Duration>,
+    ) -> MethodCallReply {
+        let buf = self.codec().encode_method_call(&method_call);
+        if let Some(engine) = self.engine() {
+            MethodCallReply::send(&engine, self.name(), &buf, self.codec(), timeout)
+        } else {
+            log::error!("Channel {} was not initialized", self.name());
+            MethodCallReply::channel_closed()
+        }
+    }
+
     /// When flutter listen to a stream of events using EventChannel.
     /// This method send back a success event.
     /// It can be call multiple times to simulate stream.
@@ -164,48 +248,74 @@ pub trait MethodChannel: Channel {
 
 pub trait MessageChannel: Channel {
     fn message_handler(&self) -> Option<Arc<RwLock<dyn MessageHandler + Send + Sync>>>;
-    fn codec(&self) -> &'static dyn MessageCodec;
+    fn codec(&self) -> Arc<dyn MessageCodec>;
+    fn message_queue(&self) -> &MessageQueue;
+
+    fn async_message_handler(&self) -> Option<Arc<dyn AsyncMessageHandler + Send + Sync>> {
+        None
+    }
 
     /// Handle incoming message received on this channel
     fn handle_platform_message(&self, mut msg: PlatformMessage) {
         debug_assert_eq!(msg.channel, self.name());
-        if let Some(handler) = self.message_handler() {
-            if let Some(engine) = self.engine() {
-                let message = self.codec().decode_message(msg.message).unwrap();
-                let channel = self.name().to_owned();
+        let handler = match self.async_message_handler() {
+            Some(handler) => AnyMessageHandler::Async(handler),
+            None => match self.message_handler() {
+                Some(handler) => AnyMessageHandler::Sync(handler),
+                None => return,
+            },
+        };
+        if let Some(engine) = self.engine() {
+            let channel = self.name().to_owned();
+            let message = self.codec().decode_message(msg.message);
+            if let Ok(message) = &message {
                 log::trace!("on channel {}, got message {:?}", channel, message);
-                let plugin_name = self.plugin_name();
-                let mut response_handle = msg.response_handle.take();
-                let codec = self.codec();
-                engine.clone().run_in_background(async move {
-                    let mut handler = handler.write().unwrap();
-                    let result = handler.on_message(message, engine.clone());
+            }
+            let plugin_name = self.plugin_name();
+            let response_handle = Arc::new(Mutex::new(msg.response_handle.take()));
+            let codec = self.codec();
+            // message channels have no error replies
+            let on_panic = {
+                let response_handle = response_handle.clone();
+                let codec = codec.clone();
+                move || queue::respond(response_handle, codec.encode_message(&Value::Null))
+            };
+            self.message_queue().submit(
+                engine.clone(),
+                channel.clone(),
+                async move {
+                    let message = match message {
+                        Ok(message) => message,
+                        Err(error) => {
+                            // message channels have no error replies
+                            log::error!(
+                                target: handler.log_target().unwrap_or(plugin_name),
+                                "invalid message on channel {}: {}",
+                                channel,
+                                error
+                            );
+                            let buf = codec.encode_message(&Value::Null);
+                            return queue::respond(response_handle, buf);
+                        }
+                    };
+                    let result = handler.on_message(message, engine.clone()).await;
                     let response = match result {
                         Ok(value) => value,
                         Err(error) => {
                             log::error!(
-                                target: handler
-                                    .log_target()
-                                    .unwrap_or(plugin_name),
+                                target: handler.log_target().unwrap_or(plugin_name),
                                 "error in message handler on channel {}: {}",
                                 channel,
-                                error);
+                                error
+                            );
                             Value::Null
                         }
                     };
-                    if response_handle.is_some() {
-                        engine.post_platform_callback(crate::MainThreadCallback::Channel((
-                            channel,
-                            Box::new(move |channel| {
-                                let buf = codec.encode_message(&response);
-                                if let Some(handle) = response_handle.take() {
-                                    channel.send_response(handle, &buf);
-                                }
-                            }),
-                        )));
-                    }
-                });
-            }
+                    let buf = codec.encode_message(&response);
+                    queue::respond(response_handle, buf)
+                },
+                on_panic,
+            );
         }
     }
 
@@ -236,6 +346,185 @@ pub trait MethodCallHandler {
     ) -> Result<Value, MethodCallError>;
 }
 
+/// The future returned by async handlers.
+pub type HandlerFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send + 'static>>;
+
+/// An async version of [`MessageHandler`].
+///
+/// No lock is held while the returned future runs, so a slow message does
+/// not block other messages to the same handler. Every `RwLock` around a
+/// `MessageHandler` implements this trait.
+pub trait AsyncMessageHandler {
+    fn log_target(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn on_message(
+        self: Arc<Self>,
+        msg: Value,
+        engine: FlutterEngine,
+    ) -> HandlerFuture<Value, MessageError>;
+}
+
+/// An async version of [`MethodCallHandler`].
+///
+/// No lock is held while the returned future runs, so a slow call does not
+/// block other calls to the same handler. Every `RwLock` around a
+/// `MethodCallHandler` implements this trait.
+pub trait AsyncMethodCallHandler {
+    fn log_target(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn on_method_call(
+        self: Arc<Self>,
+        call: MethodCall,
+        engine: FlutterEngine,
+    ) -> HandlerFuture<Value, MethodCallError>;
+}
+
+impl<H> AsyncMessageHandler for RwLock<H>
+where
+    H: MessageHandler + Send + Sync + ?Sized + 'static,
+{
+    fn log_target(&self) -> Option<&'static str> {
+        self.read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .log_target()
+    }
+
+    fn on_message(
+        self: Arc<Self>,
+        msg: Value,
+        engine: FlutterEngine,
+    ) -> HandlerFuture<Value, MessageError> {
+        // a panic in an earlier message must not take the channel down
+        Box::pin(async move {
+            self.write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .on_message(msg, engine)
+        })
+    }
+}
+
+impl<H> AsyncMethodCallHandler for RwLock<H>
+where
+    H: MethodCallHandler + Send + Sync + ?Sized + 'static,
+{
+    fn log_target(&self) -> Option<&'static str> {
+        self.read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .log_target()
+    }
+
+    fn on_method_call(
+        self: Arc<Self>,
+        call: MethodCall,
+        engine: FlutterEngine,
+    ) -> HandlerFuture<Value, MethodCallError> {
+        // a panic in an earlier message must not take the channel down
+        Box::pin(async move {
+            self.write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .on_method_call(call, engine)
+        })
+    }
+}
+
+/// The handler a method channel was created with.
+pub(crate) enum MethodCallHandlerRef {
+    Sync(Weak<RwLock<dyn MethodCallHandler + Send + Sync>>),
+    Async(Weak<dyn AsyncMethodCallHandler + Send + Sync>),
+}
+
+impl MethodCallHandlerRef {
+    pub(crate) fn sync_handler(&self) -> Option<Arc<RwLock<dyn MethodCallHandler + Send + Sync>>> {
+        match self {
+            MethodCallHandlerRef::Sync(handler) => handler.upgrade(),
+            MethodCallHandlerRef::Async(_) => None,
+        }
+    }
+
+    pub(crate) fn async_handler(&self) -> Option<Arc<dyn AsyncMethodCallHandler + Send + Sync>> {
+        match self {
+            MethodCallHandlerRef::Sync(_) => None,
+            MethodCallHandlerRef::Async(handler) => handler.upgrade(),
+        }
+    }
+}
+
+/// The handler a message channel was created with.
+pub(crate) enum MessageHandlerRef {
+    Sync(Weak<RwLock<dyn MessageHandler + Send + Sync>>),
+    Async(Weak<dyn AsyncMessageHandler + Send + Sync>),
+}
+
+impl MessageHandlerRef {
+    pub(crate) fn sync_handler(&self) -> Option<Arc<RwLock<dyn MessageHandler + Send + Sync>>> {
+        match self {
+            MessageHandlerRef::Sync(handler) => handler.upgrade(),
+            MessageHandlerRef::Async(_) => None,
+        }
+    }
+
+    pub(crate) fn async_handler(&self) -> Option<Arc<dyn AsyncMessageHandler + Send + Sync>> {
+        match self {
+            MessageHandlerRef::Sync(_) => None,
+            MessageHandlerRef::Async(handler) => handler.upgrade(),
+        }
+    }
+}
+
+enum AnyMethodCallHandler {
+    Sync(Arc<RwLock<dyn MethodCallHandler + Send + Sync>>),
+    Async(Arc<dyn AsyncMethodCallHandler + Send + Sync>),
+}
+
+impl AnyMethodCallHandler {
+    fn log_target(&self) -> Option<&'static str> {
+        match self {
+            AnyMethodCallHandler::Sync(handler) => AsyncMethodCallHandler::log_target(&**handler),
+            AnyMethodCallHandler::Async(handler) => handler.log_target(),
+        }
+    }
+
+    fn on_method_call(
+        &self,
+        call: MethodCall,
+        engine: FlutterEngine,
+    ) -> HandlerFuture<Value, MethodCallError> {
+        match self {
+            AnyMethodCallHandler::Sync(handler) => {
+                AsyncMethodCallHandler::on_method_call(handler.clone(), call, engine)
+            }
+            AnyMethodCallHandler::Async(handler) => handler.clone().on_method_call(call, engine),
+        }
+    }
+}
+
+enum AnyMessageHandler {
+    Sync(Arc<RwLock<dyn MessageHandler + Send + Sync>>),
+    Async(Arc<dyn AsyncMessageHandler + Send + Sync>),
+}
+
+impl AnyMessageHandler {
+    fn log_target(&self) -> Option<&'static str> {
+        match self {
+            AnyMessageHandler::Sync(handler) => AsyncMessageHandler::log_target(&**handler),
+            AnyMessageHandler::Async(handler) => handler.log_target(),
+        }
+    }
+
+    fn on_message(&self, msg: Value, engine: FlutterEngine) -> HandlerFuture<Value, MessageError> {
+        match self {
+            AnyMessageHandler::Sync(handler) => {
+                AsyncMessageHandler::on_message(handler.clone(), msg, engine)
+            }
+            AnyMessageHandler::Async(handler) => handler.clone().on_message(msg, engine),
+        }
+    }
+}
+
 pub trait EventHandler {
     fn on_listen(&mut self, args: Value, engine: FlutterEngine) -> Result<Value, MethodCallError>;
     fn on_cancel(&mut self, engine: FlutterEngine) -> Result<Value, MethodCallError>;
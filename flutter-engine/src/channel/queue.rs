@@ -0,0 +1,400 @@
+//! Ordering of the messages received on a channel.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use parking_lot::Mutex;
+
+use super::Channel;
+use crate::{
+    FlutterEngine, MainThreadCallback, MainThreadChannelFn, PlatformMessageResponseHandle,
+};
+
+/// How the messages received on a channel are run.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ExecutionPolicy {
+    /// Messages are handled one after another in the order they were
+    /// received.
+    #[default]
+    Serial,
+    /// Messages are handled at the same time. Replies are still sent in the
+    /// order the messages were received.
+    Concurrent,
+}
+
+/// Sends the reply to a message.
+pub(crate) type ResponseFn = Box<dyn FnMut(&dyn Channel) + Send>;
+
+/// The reply to a message, if it expects one.
+pub(crate) type Response = Option<ResponseFn>;
+
+/// The response handle of a message, shared by the reply of its handler and
+/// the reply sent if the handler panics.
+pub(crate) type SharedResponseHandle = Arc<Mutex<Option<PlatformMessageResponseHandle>>>;
+
+/// Answers `handle` with `buf` unless it was answered already.
+pub(crate) fn respond(handle: SharedResponseHandle, buf: Vec<u8>) -> Response {
+    handle.lock().as_ref()?;
+    let response: ResponseFn = Box::new(move |channel| {
+        if let Some(handle) = handle.lock().take() {
+            channel.send_response(handle, &buf);
+        }
+    });
+    Some(response)
+}
+
+type Task = Pin<Box<dyn Future<Output = Response> + Send>>;
+
+/// Resolves to `Err` if polling the task panics.
+struct CatchUnwind(Task);
+
+impl Future for CatchUnwind {
+    type Output = std::thread::Result<Response>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let task = &mut self.0;
+        match panic::catch_unwind(AssertUnwindSafe(|| task.as_mut().poll(cx))) {
+            Ok(Poll::Ready(response)) => Poll::Ready(Ok(response)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// Messages waiting for a serial channel to become idle.
+    pending: VecDeque<(u64, Task)>,
+    running: bool,
+    next_message: u64,
+    next_reply: u64,
+    /// Replies that are done but wait for the replies of earlier messages.
+    replies: BTreeMap<u64, Response>,
+}
+
+/// Runs the messages of a channel according to its [`ExecutionPolicy`].
+#[derive(Default)]
+pub struct MessageQueue {
+    policy: ExecutionPolicy,
+    state: Arc<Mutex<QueueState>>,
+}
+
+impl MessageQueue {
+    pub fn new(policy: ExecutionPolicy) -> Self {
+        Self {
+            policy,
+            state: Default::default(),
+        }
+    }
+
+    pub fn policy(&self) -> ExecutionPolicy {
+        self.policy
+    }
+
+    /// Runs `task` in the background and posts its response to the platform
+    /// thread once the responses of all earlier messages were posted.
+    ///
+    /// If `task` panics, the response of `on_panic` is posted in its place.
+    pub(crate) fn submit<F, P>(&self, engine: FlutterEngine, channel: String, task: F, on_panic: P)
+    where
+        F: Future<Output = Response> + Send + 'static,
+        P: FnOnce() -> Response + Send + 'static,
+    {
+        let task = {
+            let channel = channel.clone();
+            async move {
+                match CatchUnwind(Box::pin(task)).await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        log::error!("handler of a message on channel {} panicked", channel);
+                        on_panic()
+                    }
+                }
+            }
+        };
+        let state = self.state.clone();
+        let mut guard = state.lock();
+        let id = guard.next_message;
+        guard.next_message += 1;
+
+        match self.policy {
+            ExecutionPolicy::Concurrent => {
+                drop(guard);
+                engine.clone().run_in_background(async move {
+                    let response = task.await;
+                    complete(&state, &engine, &channel, id, response);
+                });
+            }
+            ExecutionPolicy::Serial => {
+                guard.pending.push_back((id, Box::pin(task)));
+                if guard.running {
+                    return;
+                }
+                guard.running = true;
+                drop(guard);
+                engine.clone().run_in_background(async move {
+                    loop {
+                        let next = {
+                            let mut guard = state.lock();
+                            let next = guard.pending.pop_front();
+                            if next.is_none() {
+                                guard.running = false;
+                            }
+                            next
+                        };
+                        match next {
+                            Some((id, task)) => {
+                                let response = task.await;
+                                complete(&state, &engine, &channel, id, response);
+                            }
+                            None => break,
+                        }
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Records the response of message `id` and posts every response that is
+/// no longer waiting for an earlier one.
+fn complete(
+    state: &Mutex<QueueState>,
+    engine: &FlutterEngine,
+    channel: &str,
+    id: u64,
+    response: Response,
+) {
+    // The lock is held while posting so responses of different messages
+    // cannot overtake each other.
+    let mut state = state.lock();
+    state.replies.insert(id, response);
+    loop {
+        let next = state.next_reply;
+        let response = match state.replies.remove(&next) {
+            Some(response) => response,
+            None => break,
+        };
+        state.next_reply += 1;
+        if let Some(response) = response {
+            let callback: MainThreadChannelFn = (channel.to_owned(), response);
+            engine.post_platform_callback(MainThreadCallback::Channel(callback));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::channel::{
+        AsyncMethodCallHandler, HandlerFuture, JsonMethodChannel, MethodCallHandler,
+        PANIC_ERROR_CODE,
+    };
+    use crate::codec::{json_codec::CODEC, MethodCall, MethodCallResult, MethodCodec, Value};
+    use crate::error::MethodCallError;
+    use crate::mock::{block_on, run_engine, Call, MockEngine, MockHandler};
+    use std::sync::RwLock;
+
+    const CHANNEL_NAME: &str = "flutter-rs/queue";
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        calls: Vec<String>,
+    }
+
+    impl MethodCallHandler for RecordingHandler {
+        fn on_method_call(
+            &mut self,
+            call: MethodCall,
+            _: FlutterEngine,
+        ) -> Result<Value, MethodCallError> {
+            self.calls.push(call.method.clone());
+            Ok(Value::String(call.method))
+        }
+    }
+
+    fn register(engine: &FlutterEngine, policy: ExecutionPolicy) -> Arc<RwLock<RecordingHandler>> {
+        let handler = Arc::new(RwLock::new(RecordingHandler::default()));
+        let weak = Arc::downgrade(&handler);
+        engine.with_channel_registrar("queue", |registrar| {
+            let mut channel = JsonMethodChannel::new(CHANNEL_NAME, weak);
+            channel.set_execution_policy(policy);
+            registrar.register_channel(channel);
+        });
+        handler
+    }
+
+    /// Panics on the method `panic`.
+    struct PanickingHandler;
+
+    impl AsyncMethodCallHandler for PanickingHandler {
+        fn on_method_call(
+            self: Arc<Self>,
+            call: MethodCall,
+            _: FlutterEngine,
+        ) -> HandlerFuture<Value, MethodCallError> {
+            Box::pin(async move {
+                if call.method == "panic" {
+                    panic!("handler panicked");
+                }
+                Ok(Value::String(call.method))
+            })
+        }
+    }
+
+    /// Panics on the method `panic`.
+    struct PanickingSyncHandler;
+
+    impl MethodCallHandler for PanickingSyncHandler {
+        fn on_method_call(
+            &mut self,
+            call: MethodCall,
+            _: FlutterEngine,
+        ) -> Result<Value, MethodCallError> {
+            if call.method == "panic" {
+                panic!("handler panicked");
+            }
+            Ok(Value::String(call.method))
+        }
+    }
+
+    fn register_panicking(
+        engine: &FlutterEngine,
+        policy: ExecutionPolicy,
+    ) -> Arc<PanickingHandler> {
+        let handler = Arc::new(PanickingHandler);
+        let weak = Arc::downgrade(&handler);
+        engine.with_channel_registrar("queue", |registrar| {
+            let mut channel = JsonMethodChannel::new_async(CHANNEL_NAME, weak);
+            channel.set_execution_policy(policy);
+            registrar.register_channel(channel);
+        });
+        handler
+    }
+
+    fn send(mock: &MockEngine, method: &str) -> usize {
+        let call = MethodCall {
+            method: method.into(),
+            args: Value::Null,
+        };
+        mock.send_platform_message(CHANNEL_NAME, &CODEC.encode_method_call(&call))
+    }
+
+    fn responses(mock: &MockEngine) -> Vec<usize> {
+        mock.calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                Call::SendPlatformMessageResponse {
+                    response_handle, ..
+                } => Some(response_handle),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_serial_channel_handles_messages_in_order() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let recording = register(&engine, ExecutionPolicy::Serial);
+
+        let first = send(&mock, "setEditingState");
+        let second = send(&mock, "show");
+        // both messages are handled by a single background task
+        let mut background = handler.take_background();
+        assert_eq!(background.len(), 1);
+
+        block_on(background.pop().unwrap());
+        engine.execute_platform_tasks();
+        assert_eq!(
+            recording.read().unwrap().calls,
+            vec!["setEditingState", "show"]
+        );
+        assert_eq!(responses(&mock), vec![first, second]);
+
+        // the channel keeps working once it was idle
+        let third = send(&mock, "hide");
+        handler.pump(&engine);
+        assert_eq!(responses(&mock), vec![first, second, third]);
+    }
+
+    #[test]
+    fn test_concurrent_channel_replies_in_order() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let _recording = register(&engine, ExecutionPolicy::Concurrent);
+
+        let first = send(&mock, "first");
+        let second = send(&mock, "second");
+        let mut background = handler.take_background();
+        assert_eq!(background.len(), 2);
+
+        // the second message finishes first, but its reply waits
+        block_on(background.pop().unwrap());
+        engine.execute_platform_tasks();
+        assert!(responses(&mock).is_empty());
+
+        block_on(background.pop().unwrap());
+        engine.execute_platform_tasks();
+        assert_eq!(responses(&mock), vec![first, second]);
+    }
+
+    fn assert_panic_is_answered(policy: ExecutionPolicy) {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let _handler = register_panicking(&engine, policy);
+
+        let first = send(&mock, "panic");
+        let second = send(&mock, "show");
+        handler.pump(&engine);
+        assert_eq!(responses(&mock), vec![first, second]);
+        match CODEC.decode_envelope(&mock.response(first).unwrap()) {
+            Ok(MethodCallResult::Err { code, .. }) => assert_eq!(code, PANIC_ERROR_CODE),
+            _ => panic!("expected an error envelope"),
+        }
+
+        // the channel keeps working after the panic
+        let third = send(&mock, "hide");
+        handler.pump(&engine);
+        assert_eq!(responses(&mock), vec![first, second, third]);
+    }
+
+    #[test]
+    fn test_serial_channel_answers_panicking_handler() {
+        assert_panic_is_answered(ExecutionPolicy::Serial);
+    }
+
+    #[test]
+    fn test_concurrent_channel_answers_panicking_handler() {
+        assert_panic_is_answered(ExecutionPolicy::Concurrent);
+    }
+
+    #[test]
+    fn test_sync_handler_keeps_working_after_panic() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let panicking = Arc::new(RwLock::new(PanickingSyncHandler));
+        let weak = Arc::downgrade(&panicking);
+        engine.with_channel_registrar("queue", |registrar| {
+            registrar.register_channel(JsonMethodChannel::new(CHANNEL_NAME, weak));
+        });
+
+        let first = send(&mock, "panic");
+        let second = send(&mock, "show");
+        handler.pump(&engine);
+        match CODEC.decode_envelope(&mock.response(first).unwrap()) {
+            Ok(MethodCallResult::Err { code, .. }) => assert_eq!(code, PANIC_ERROR_CODE),
+            _ => panic!("expected an error envelope"),
+        }
+        match CODEC.decode_envelope(&mock.response(second).unwrap()) {
+            Ok(MethodCallResult::Ok(value)) => assert_eq!(value, Value::String("show".into())),
+            _ => panic!("expected a success envelope"),
+        }
+    }
+}
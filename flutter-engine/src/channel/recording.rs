@@ -0,0 +1,490 @@
+//! Recording platform message traffic and replaying it against plugins.
+//!
+//! A recording is stored as JSON lines, one [`RecordedMessage`] per line.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    future::Future,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    mem,
+    os::raw::{c_char, c_void},
+    path::{Path, PathBuf},
+    pin::Pin,
+    ptr,
+    sync::{Arc, Weak},
+    time::Instant,
+};
+
+use log::error;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use super::{ChannelInterceptor, Interception, MessageDirection};
+use crate::{
+    utils::block_on, FlutterEngine, FlutterEngineHandler, PlatformMessage,
+    PlatformMessageResponseHandle,
+};
+
+/// A platform message seen by a [`ChannelRecorder`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub channel: String,
+    pub direction: MessageDirection,
+    /// Microseconds since the recording started.
+    pub timestamp: u64,
+    pub data: Vec<u8>,
+}
+
+/// The platform messages of a session in the order they were seen.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Recording {
+    pub messages: Vec<RecordedMessage>,
+}
+
+impl Recording {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut messages = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            messages.push(serde_json::from_str(&line)?);
+        }
+        Ok(Self { messages })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for message in &self.messages {
+            write_message(&mut writer, message)?;
+        }
+        Ok(())
+    }
+
+    /// The messages going in `direction`.
+    pub fn filter(&self, direction: MessageDirection) -> impl Iterator<Item = &RecordedMessage> {
+        self.messages
+            .iter()
+            .filter(move |message| message.direction == direction)
+    }
+}
+
+fn write_message<W: Write>(writer: &mut W, message: &RecordedMessage) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, message)?;
+    writer.write_all(b"\n")
+}
+
+enum Sink {
+    Memory(Vec<RecordedMessage>),
+    Writer(Box<dyn Write + Send>),
+}
+
+struct RecorderState {
+    start: Instant,
+    sink: Sink,
+}
+
+/// An interceptor that records every platform message.
+///
+/// Add a clone to the engine with `FlutterEngine::add_interceptor`. Messages
+/// are either kept in memory or written to a file as they are seen, so a
+/// recording survives a crash of the application.
+#[derive(Clone)]
+pub struct ChannelRecorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+impl Default for ChannelRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChannelRecorder {
+    /// Keeps the recorded messages in memory.
+    pub fn new() -> Self {
+        Self::with_sink(Sink::Memory(Vec::new()))
+    }
+
+    /// Writes the recorded messages to a file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::with_writer(File::create(path)?))
+    }
+
+    /// Writes the recorded messages to `writer`.
+    pub fn with_writer<W: Write + Send + 'static>(writer: W) -> Self {
+        Self::with_sink(Sink::Writer(Box::new(writer)))
+    }
+
+    fn with_sink(sink: Sink) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(RecorderState {
+                start: Instant::now(),
+                sink,
+            })),
+        }
+    }
+
+    /// Takes the messages recorded in memory so far.
+    pub fn take_recording(&self) -> Recording {
+        match &mut self.state.lock().sink {
+            Sink::Memory(messages) => Recording {
+                messages: mem::take(messages),
+            },
+            Sink::Writer(_) => Recording::default(),
+        }
+    }
+}
+
+impl ChannelInterceptor for ChannelRecorder {
+    fn intercept(
+        &self,
+        channel: &str,
+        direction: MessageDirection,
+        message: &mut Vec<u8>,
+    ) -> Interception {
+        let mut state = self.state.lock();
+        let message = RecordedMessage {
+            channel: channel.to_owned(),
+            direction,
+            timestamp: state.start.elapsed().as_micros() as u64,
+            data: message.clone(),
+        };
+        match &mut state.sink {
+            Sink::Memory(messages) => messages.push(message),
+            Sink::Writer(writer) => {
+                if let Err(err) = write_message(writer, &message).and_then(|_| writer.flush()) {
+                    error!("Failed to record message on channel {}: {}", channel, err);
+                }
+            }
+        }
+        Interception::Continue
+    }
+}
+
+type BackgroundFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Runs background futures of the replayed plugins on the replaying thread.
+#[derive(Default)]
+struct ReplayHandler {
+    background: Mutex<Vec<BackgroundFuture>>,
+}
+
+impl FlutterEngineHandler for ReplayHandler {
+    fn swap_buffers(&self) -> bool {
+        false
+    }
+
+    fn make_current(&self) -> bool {
+        false
+    }
+
+    fn clear_current(&self) -> bool {
+        false
+    }
+
+    fn fbo_callback(&self) -> u32 {
+        0
+    }
+
+    fn make_resource_current(&self) -> bool {
+        false
+    }
+
+    fn gl_proc_resolver(&self, _proc: *const c_char) -> *mut c_void {
+        ptr::null_mut()
+    }
+
+    fn wake_platform_thread(&self) {}
+
+    fn run_in_background(&self, func: Box<dyn Future<Output = ()> + Send + 'static>) {
+        self.background.lock().push(Pin::from(func));
+    }
+}
+
+/// Replays the inbound messages of a recording against plugins without a
+/// flutter engine.
+///
+/// The replayer owns an engine that is never run. Add the plugins under test
+/// to [`engine`](Self::engine), then call [`replay`](Self::replay) on the
+/// thread that created the replayer. Messages are replayed one after another
+/// as fast as the plugins answer them; the recorded timing is ignored.
+///
+/// Messages the plugins send to dart are answered with the recorded replies
+/// of their channel in the order they were recorded, or with an empty reply
+/// once those run out.
+pub struct Replayer {
+    handler: Arc<ReplayHandler>,
+    engine: FlutterEngine,
+    recorder: ChannelRecorder,
+    replies: Arc<Mutex<Replies>>,
+}
+
+/// The recorded replies of dart per channel.
+type Replies = HashMap<String, VecDeque<Vec<u8>>>;
+
+/// Records the replayed traffic and stands in for dart.
+struct ReplayInterceptor {
+    recorder: ChannelRecorder,
+    replies: Arc<Mutex<Replies>>,
+}
+
+impl ChannelInterceptor for ReplayInterceptor {
+    fn intercept(
+        &self,
+        channel: &str,
+        direction: MessageDirection,
+        message: &mut Vec<u8>,
+    ) -> Interception {
+        match direction {
+            MessageDirection::Outbound => {
+                self.recorder.intercept(channel, direction, message);
+                // there is no dart to deliver to, calls get an empty reply
+                Interception::Drop
+            }
+            MessageDirection::Reply => {
+                let reply = self
+                    .replies
+                    .lock()
+                    .get_mut(channel)
+                    .and_then(VecDeque::pop_front);
+                if let Some(reply) = reply {
+                    *message = reply;
+                }
+                self.recorder.intercept(channel, direction, message)
+            }
+            _ => self.recorder.intercept(channel, direction, message),
+        }
+    }
+}
+
+impl Replayer {
+    pub fn new<P: Into<PathBuf>>(assets: P) -> Self {
+        let handler = Arc::new(ReplayHandler::default());
+        let engine = FlutterEngine::new(
+            Arc::downgrade(&handler) as Weak<dyn FlutterEngineHandler>,
+            assets.into(),
+        );
+        let recorder = ChannelRecorder::new();
+        let replies = Arc::new(Mutex::new(Replies::new()));
+        engine.add_interceptor(ReplayInterceptor {
+            recorder: recorder.clone(),
+            replies: replies.clone(),
+        });
+        Self {
+            handler,
+            engine,
+            recorder,
+            replies,
+        }
+    }
+
+    pub fn engine(&self) -> &FlutterEngine {
+        &self.engine
+    }
+
+    /// Sends the inbound messages of `recording` to the plugins and returns
+    /// the traffic this caused: the replayed messages, the responses of the
+    /// plugins, the messages they sent to dart and the replies to those.
+    pub fn replay(&self, recording: &Recording) -> Recording {
+        {
+            let mut replies = self.replies.lock();
+            replies.clear();
+            for message in recording.filter(MessageDirection::Reply) {
+                replies
+                    .entry(message.channel.clone())
+                    .or_default()
+                    .push_back(message.data.clone());
+            }
+        }
+        for message in recording.filter(MessageDirection::Inbound) {
+            self.engine.inner.plugins.write().handle(PlatformMessage {
+                channel: message.channel.as_str().into(),
+                message: &message.data,
+                response_handle: Some(PlatformMessageResponseHandle::replayed()),
+            });
+            self.run_until_idle();
+        }
+        self.recorder.take_recording()
+    }
+
+    fn run_until_idle(&self) {
+        loop {
+            let futures = mem::take(&mut *self.handler.background.lock());
+            for future in futures {
+                block_on(future);
+            }
+            self.engine.execute_platform_tasks();
+            if self.handler.background.lock().is_empty() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::channel::{
+        ChannelRegistrar, JsonMethodChannel, MethodCallHandler, MethodCallReply, MethodChannel,
+    };
+    use crate::codec::{json_codec::CODEC, MethodCall, MethodCodec, Value};
+    use crate::error::MethodCallError;
+    use crate::mock::{echo_call, run_engine, Call, MockEngine, MockHandler};
+    use crate::plugins::Plugin;
+    use std::sync::RwLock;
+
+    const CHANNEL_NAME: &str = "flutter-rs/recorded";
+
+    /// Asks dart to translate every echoed value.
+    struct Handler {
+        channel: Weak<JsonMethodChannel>,
+        translations: Mutex<Vec<MethodCallReply>>,
+    }
+
+    impl MethodCallHandler for Handler {
+        fn on_method_call(
+            &mut self,
+            call: MethodCall,
+            _: FlutterEngine,
+        ) -> Result<Value, MethodCallError> {
+            let channel = self.channel.upgrade().unwrap();
+            let translation = channel.invoke_method_with_reply(
+                MethodCall {
+                    method: "translate".into(),
+                    args: call.args.clone(),
+                },
+                None,
+            );
+            self.translations.lock().push(translation);
+            Ok(call.args)
+        }
+    }
+
+    struct EchoPlugin {
+        handler: Arc<RwLock<Handler>>,
+    }
+
+    impl Plugin for EchoPlugin {
+        fn plugin_name() -> &'static str {
+            "echo"
+        }
+
+        fn init_channels(&mut self, registrar: &mut ChannelRegistrar) {
+            let handler = Arc::downgrade(&self.handler);
+            self.handler.write().unwrap().channel =
+                registrar.register_channel(JsonMethodChannel::new(CHANNEL_NAME, handler));
+        }
+    }
+
+    fn add_plugin(engine: &FlutterEngine) -> Arc<RwLock<Handler>> {
+        let handler = Arc::new(RwLock::new(Handler {
+            channel: Weak::new(),
+            translations: Default::default(),
+        }));
+        engine.add_plugin(EchoPlugin {
+            handler: handler.clone(),
+        });
+        handler
+    }
+
+    /// Answers the last message sent to dart that expects a reply.
+    fn reply(mock: &MockEngine, value: &str) {
+        let handle = mock
+            .calls()
+            .into_iter()
+            .rev()
+            .find_map(|call| match call {
+                Call::SendPlatformMessage {
+                    response_handle, ..
+                } => response_handle,
+                _ => None,
+            })
+            .unwrap();
+        let reply = CODEC.encode_success_envelope(&Value::String(value.into()));
+        assert!(mock.reply(handle, &reply));
+    }
+
+    fn without_timestamps(
+        recording: &Recording,
+        direction: MessageDirection,
+    ) -> Vec<(String, Vec<u8>)> {
+        recording
+            .filter(direction)
+            .map(|message| (message.channel.clone(), message.data.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+        let recorder = ChannelRecorder::new();
+        engine.add_interceptor(recorder.clone());
+
+        for (value, translation) in &[("first", "erste"), ("second", "zweite")] {
+            mock.send_platform_message(CHANNEL_NAME, &echo_call(value));
+            handler.pump(&engine);
+            reply(&mock, translation);
+        }
+        let recording = recorder.take_recording();
+        for direction in &[
+            MessageDirection::Inbound,
+            MessageDirection::Outbound,
+            MessageDirection::Response,
+            MessageDirection::Reply,
+        ] {
+            assert_eq!(recording.filter(*direction).count(), 2);
+        }
+
+        let mut file = Vec::new();
+        recording.write_to(&mut file).unwrap();
+        let loaded = Recording::read_from(&file[..]).unwrap();
+        assert_eq!(loaded, recording);
+
+        let replayer = Replayer::new("assets");
+        let replayed_handler = add_plugin(replayer.engine());
+        let replayed = replayer.replay(&loaded);
+        // replies arrive before the responses while replaying
+        for direction in &[
+            MessageDirection::Inbound,
+            MessageDirection::Outbound,
+            MessageDirection::Response,
+            MessageDirection::Reply,
+        ] {
+            assert_eq!(
+                without_timestamps(&replayed, *direction),
+                without_timestamps(&recording, *direction)
+            );
+        }
+
+        // the plugin got the recorded replies
+        let translations: Vec<_> = replayed_handler
+            .read()
+            .unwrap()
+            .translations
+            .lock()
+            .drain(..)
+            .map(|translation| block_on(Box::pin(translation)).unwrap())
+            .collect();
+        assert_eq!(
+            translations,
+            vec![
+                Value::String("erste".into()),
+                Value::String("zweite".into())
+            ]
+        );
+    }
+}
@@ -8,18 +8,25 @@ use log::{trace, warn};
 
 use crate::{FlutterEngineWeakRef, PlatformMessage};
 
-use super::Channel;
+use super::{
+    buffer::{BufferedMessage, ChannelBuffer},
+    BufferConfig, Channel,
+};
 
 #[derive(Default)]
 pub struct ChannelRegistry {
     channels: HashMap<String, Arc<dyn Channel>>,
     engine: FlutterEngineWeakRef,
+    buffers: HashMap<String, ChannelBuffer>,
+    default_buffer_config: BufferConfig,
+    buffer_configs: HashMap<String, BufferConfig>,
 }
 
 pub struct ChannelRegistrar<'a> {
     plugin_name: &'static str,
     engine: &'a FlutterEngineWeakRef,
     channels: &'a mut HashMap<String, Arc<dyn Channel>>,
+    buffers: &'a mut HashMap<String, ChannelBuffer>,
 }
 
 impl ChannelRegistry {
@@ -31,6 +38,57 @@ impl ChannelRegistry {
         self.engine = engine;
     }
 
+    /// Sets the buffer of channels without a config of their own.
+    pub fn set_default_buffer_config(&mut self, config: BufferConfig) {
+        self.default_buffer_config = config;
+        let names: Vec<String> = self
+            .buffers
+            .keys()
+            .filter(|name| !self.buffer_configs.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in names {
+            self.resize_buffer(&name, config);
+        }
+    }
+
+    /// Sets how many messages are kept for `channel_name` until a channel
+    /// with that name is registered.
+    pub fn set_buffer_config(&mut self, channel_name: &str, config: BufferConfig) {
+        self.buffer_configs.insert(channel_name.to_owned(), config);
+        self.resize_buffer(channel_name, config);
+    }
+
+    fn buffer_config(&self, channel_name: &str) -> BufferConfig {
+        self.buffer_configs
+            .get(channel_name)
+            .copied()
+            .unwrap_or(self.default_buffer_config)
+    }
+
+    fn resize_buffer(&mut self, channel_name: &str, config: BufferConfig) {
+        if let Some(buffer) = self.buffers.get_mut(channel_name) {
+            let discarded = buffer.resize(config.capacity, config.overflow);
+            self.discard(channel_name, discarded);
+        }
+    }
+
+    /// Answers messages that did not fit into a buffer with an empty
+    /// response.
+    fn discard(&self, channel_name: &str, messages: Vec<BufferedMessage>) {
+        for message in messages {
+            warn!(
+                "Discarding message on channel {}, no plugin registered to handle it",
+                channel_name
+            );
+            if let Some(handle) = message.response_handle {
+                if let Some(engine) = self.engine.upgrade() {
+                    engine.send_platform_message_response(channel_name, handle, &[]);
+                }
+            }
+        }
+    }
+
     pub fn remove_channel(&mut self, channel_name: &str) -> Option<Arc<dyn Channel>> {
         self.channels.remove(channel_name)
     }
@@ -43,6 +101,7 @@ impl ChannelRegistry {
             plugin_name,
             engine: &self.engine,
             channels: &mut self.channels,
+            buffers: &mut self.buffers,
         };
         f(&mut registrar);
     }
@@ -61,16 +120,21 @@ impl ChannelRegistry {
             trace!("Processing message from channel: {}", message.channel);
             channel.handle_platform_message(message);
         } else {
-            warn!(
-                "No plugin registered to handle messages from channel: {}",
-                &message.channel
+            trace!(
+                "Buffering message from channel {} until a plugin is registered",
+                message.channel
             );
-            if let Some(handle) = message.response_handle.take() {
-                self.engine
-                    .upgrade()
-                    .unwrap()
-                    .send_platform_message_response(handle, &[]);
-            }
+            let config = self.buffer_config(&message.channel);
+            let buffered = BufferedMessage {
+                message: message.message.to_vec(),
+                response_handle: message.response_handle.take(),
+            };
+            let discarded = self
+                .buffers
+                .entry(message.channel.to_string())
+                .or_default()
+                .push(buffered, config);
+            self.discard(&message.channel, discarded);
         }
     }
 }
@@ -84,7 +148,308 @@ impl<'a> ChannelRegistrar<'a> {
         let name = channel.name().to_owned();
         let arc = Arc::new(channel);
         let weak = Arc::downgrade(&arc);
+        if let Some(buffer) = self.buffers.remove(&name) {
+            for buffered in buffer.into_messages() {
+                arc.handle_platform_message(PlatformMessage {
+                    channel: name.as_str().into(),
+                    message: &buffered.message,
+                    response_handle: buffered.response_handle,
+                });
+            }
+        }
         self.channels.insert(name, arc);
         weak
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::channel::{
+        AsyncMethodCallHandler, BufferConfig, ExecutionPolicy, HandlerFuture, JsonMethodChannel,
+        MethodCallHandler, OverflowPolicy, StandardMethodChannel, DECODE_ERROR_CODE,
+    };
+    use crate::codec::standard_codec::{StandardCodecExtension, StandardMethodCodec};
+    use crate::codec::{json_codec::CODEC, MethodCall, MethodCallResult, MethodCodec, Value};
+    use crate::error::MethodCallError;
+    use crate::mock::{
+        block_on, echo_call, echoed, register_echo, run_engine, EchoHandler, MockHandler,
+        ECHO_CHANNEL,
+    };
+    use crate::FlutterEngine;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex, RwLock, Weak};
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+
+    struct AsyncEchoHandler;
+
+    impl AsyncMethodCallHandler for AsyncEchoHandler {
+        fn on_method_call(
+            self: Arc<Self>,
+            call: MethodCall,
+            _: FlutterEngine,
+        ) -> HandlerFuture<Value, MethodCallError> {
+            Box::pin(async move {
+                match call.method.as_str() {
+                    "echo" => Ok(call.args),
+                    _ => Err(MethodCallError::NotImplemented),
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_unknown_channel_gets_empty_response() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+
+        // the default buffer keeps the newest message
+        let first = mock.send_platform_message("flutter-rs/unknown", b"{}");
+        assert_eq!(mock.response(first), None);
+        let second = mock.send_platform_message("flutter-rs/unknown", b"{}");
+        assert_eq!(mock.response(first), Some(vec![]));
+        assert_eq!(mock.response(second), None);
+
+        engine.set_channel_buffer(
+            "flutter-rs/unknown",
+            BufferConfig {
+                capacity: 0,
+                overflow: OverflowPolicy::DropOldest,
+            },
+        );
+        assert_eq!(mock.response(second), Some(vec![]));
+        let third = mock.send_platform_message("flutter-rs/unknown", b"{}");
+        assert_eq!(mock.response(third), Some(vec![]));
+    }
+
+    #[test]
+    fn test_buffered_messages_are_delivered_on_register() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.set_channel_buffer(
+            ECHO_CHANNEL,
+            BufferConfig {
+                capacity: 2,
+                overflow: OverflowPolicy::DropOldest,
+            },
+        );
+
+        let first = mock.send_platform_message(ECHO_CHANNEL, &echo_call("first"));
+        let second = mock.send_platform_message(ECHO_CHANNEL, &echo_call("second"));
+        let third = mock.send_platform_message(ECHO_CHANNEL, &echo_call("third"));
+        // the oldest message did not fit
+        assert_eq!(mock.response(first), Some(vec![]));
+        assert_eq!(mock.response(second), None);
+
+        let _echo = register_echo(&engine);
+        handler.pump(&engine);
+        assert_eq!(
+            echoed(mock.response(second)),
+            Value::String("second".into())
+        );
+        assert_eq!(echoed(mock.response(third)), Value::String("third".into()));
+    }
+
+    #[test]
+    fn test_full_buffer_drops_newest_message() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.set_default_channel_buffer(BufferConfig {
+            capacity: 1,
+            overflow: OverflowPolicy::DropNewest,
+        });
+
+        let first = mock.send_platform_message(ECHO_CHANNEL, &echo_call("first"));
+        let second = mock.send_platform_message(ECHO_CHANNEL, &echo_call("second"));
+        assert_eq!(mock.response(second), Some(vec![]));
+
+        let _echo = register_echo(&engine);
+        handler.pump(&engine);
+        assert_eq!(echoed(mock.response(first)), Value::String("first".into()));
+    }
+
+    #[test]
+    fn test_method_call_is_answered() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let _echo = register_echo(&engine);
+
+        let handle = mock.send_platform_message(ECHO_CHANNEL, &echo_call("hello"));
+        assert_eq!(mock.response(handle), None);
+
+        handler.pump(&engine);
+        assert_eq!(echoed(mock.response(handle)), Value::String("hello".into()));
+    }
+
+    #[test]
+    fn test_async_method_call_is_answered() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let echo: Arc<dyn AsyncMethodCallHandler + Send + Sync> = Arc::new(AsyncEchoHandler);
+        engine.with_channel_registrar("echo", |registrar| {
+            registrar.register_channel(JsonMethodChannel::new_async(
+                ECHO_CHANNEL,
+                Arc::downgrade(&echo),
+            ));
+        });
+
+        let handle = mock.send_platform_message(ECHO_CHANNEL, &echo_call("hello"));
+        handler.pump(&engine);
+        assert_eq!(echoed(mock.response(handle)), Value::String("hello".into()));
+
+        let call = MethodCall {
+            method: "unknown".into(),
+            args: Value::Null,
+        };
+        let handle = mock.send_platform_message(ECHO_CHANNEL, &CODEC.encode_method_call(&call));
+        handler.pump(&engine);
+        assert_eq!(mock.response(handle), Some(vec![]));
+    }
+
+    /// A future that stays pending until it is opened.
+    #[derive(Clone, Default)]
+    struct Gate(Arc<Mutex<(bool, Option<Waker>)>>);
+
+    impl Gate {
+        fn open(&self) {
+            let mut state = self.0.lock().unwrap();
+            state.0 = true;
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl Future for Gate {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            let mut state = self.0.lock().unwrap();
+            if state.0 {
+                Poll::Ready(())
+            } else {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Answers `slow` once the gate opens and `fast` right away.
+    struct GatedHandler {
+        gate: Gate,
+        finished: Mutex<Vec<String>>,
+    }
+
+    impl AsyncMethodCallHandler for GatedHandler {
+        fn on_method_call(
+            self: Arc<Self>,
+            call: MethodCall,
+            _: FlutterEngine,
+        ) -> HandlerFuture<Value, MethodCallError> {
+            Box::pin(async move {
+                if call.method == "slow" {
+                    self.gate.clone().await;
+                }
+                self.finished.lock().unwrap().push(call.method.clone());
+                Ok(Value::String(call.method))
+            })
+        }
+    }
+
+    #[test]
+    fn test_slow_async_call_does_not_block_others() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let gated = Arc::new(GatedHandler {
+            gate: Gate::default(),
+            finished: Mutex::new(Vec::new()),
+        });
+        let weak = Arc::downgrade(&gated) as Weak<dyn AsyncMethodCallHandler + Send + Sync>;
+        engine.with_channel_registrar("gated", |registrar| {
+            let mut channel = JsonMethodChannel::new_async("flutter-rs/gated", weak);
+            channel.set_execution_policy(ExecutionPolicy::Concurrent);
+            registrar.register_channel(channel);
+        });
+
+        let call = |method: &str| {
+            let call = MethodCall {
+                method: method.into(),
+                args: Value::Null,
+            };
+            mock.send_platform_message("flutter-rs/gated", &CODEC.encode_method_call(&call))
+        };
+        let slow = call("slow");
+        let fast = call("fast");
+        let mut background = handler.take_background();
+        assert_eq!(background.len(), 2);
+
+        let fast_task = background.pop().unwrap();
+        let slow_task = thread::spawn(move || block_on(background.pop().unwrap()));
+        block_on(fast_task);
+        engine.execute_platform_tasks();
+        // the second call finished while the first is pending, its reply
+        // waits for the reply of the first
+        assert_eq!(*gated.finished.lock().unwrap(), vec!["fast"]);
+        assert_eq!(mock.response(fast), None);
+
+        gated.gate.open();
+        slow_task.join().unwrap();
+        engine.execute_platform_tasks();
+        assert_eq!(*gated.finished.lock().unwrap(), vec!["fast", "slow"]);
+        assert_eq!(echoed(mock.response(slow)), Value::String("slow".into()));
+        assert_eq!(echoed(mock.response(fast)), Value::String("fast".into()));
+    }
+
+    #[test]
+    fn test_malformed_method_call_gets_error_envelope() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let _echo = register_echo(&engine);
+
+        let malformed = mock.send_platform_message(ECHO_CHANNEL, b"{\"method\": ");
+        let valid = mock.send_platform_message(ECHO_CHANNEL, &echo_call("hello"));
+        handler.pump(&engine);
+        match CODEC.decode_envelope(&mock.response(malformed).unwrap()) {
+            Ok(MethodCallResult::Err { code, .. }) => assert_eq!(code, DECODE_ERROR_CODE),
+            _ => panic!("Expected an error envelope"),
+        }
+        assert_eq!(echoed(mock.response(valid)), Value::String("hello".into()));
+    }
+
+    struct CustomTypes;
+
+    impl StandardCodecExtension for CustomTypes {
+        fn handles(&self, type_code: u8) -> bool {
+            type_code >= 128
+        }
+    }
+
+    #[test]
+    fn test_channel_uses_extended_codec() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let mut codec = StandardMethodCodec::new();
+        codec.add_extension(CustomTypes);
+        let codec = Arc::new(codec);
+        let echo: Arc<RwLock<dyn MethodCallHandler + Send + Sync>> =
+            Arc::new(RwLock::new(EchoHandler));
+        engine.with_channel_registrar("echo", |registrar| {
+            let mut channel = StandardMethodChannel::new(ECHO_CHANNEL, Arc::downgrade(&echo));
+            channel.set_codec(codec.clone());
+            registrar.register_channel(channel);
+        });
+
+        let custom = Value::Custom(128, Box::new(Value::String("hello".into())));
+        let call = MethodCall {
+            method: "echo".into(),
+            args: custom.clone(),
+        };
+        let handle = mock.send_platform_message(ECHO_CHANNEL, &codec.encode_method_call(&call));
+        handler.pump(&engine);
+        match codec.decode_envelope(&mock.response(handle).unwrap()) {
+            Ok(MethodCallResult::Ok(value)) => assert_eq!(value, custom),
+            _ => panic!("Expected a success envelope"),
+        }
+    }
+}
@@ -0,0 +1,587 @@
+//! The result of a method call sent to flutter.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    mem,
+    os::raw::c_void,
+    pin::Pin,
+    ptr, slice,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock, Weak,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use flutter_engine_sys::FlutterPlatformMessageResponseHandle;
+use parking_lot::Mutex;
+
+use super::{interceptor::Intercepted, MessageDirection};
+use crate::{
+    codec::{MethodCallResult, MethodCodec, Value},
+    error::{DecodeError, MethodCallError},
+    FlutterEngine, FlutterEngineWeakRef, PlatformMessage,
+};
+
+enum ReplyState {
+    Pending { waker: Option<Waker> },
+    Ready(Outcome),
+    Done,
+}
+
+/// `MethodCallError` is not `Send`, so the state keeps what is needed to
+/// build it once the future is polled.
+enum Outcome {
+    Reply(MethodCallResult),
+    Undecodable(DecodeError),
+    Timeout,
+    ChannelClosed,
+}
+
+impl Outcome {
+    fn into_result(self) -> Result<Value, MethodCallError> {
+        match self {
+            Outcome::Reply(MethodCallResult::Ok(value)) => Ok(value),
+            Outcome::Reply(MethodCallResult::Err {
+                code,
+                message,
+                details,
+            }) => Err(MethodCallError::CustomError {
+                code,
+                message,
+                details,
+            }),
+            Outcome::Reply(MethodCallResult::NotImplemented) => {
+                Err(MethodCallError::NotImplemented)
+            }
+            Outcome::Undecodable(error) => Err(MethodCallError::from_error(error)),
+            Outcome::Timeout => Err(MethodCallError::Timeout),
+            Outcome::ChannelClosed => Err(MethodCallError::ChannelClosed),
+        }
+    }
+}
+
+type SharedState = Arc<Mutex<ReplyState>>;
+
+/// Completes a pending reply. Replies that arrive after the timeout or after
+/// the future was dropped are ignored.
+fn complete(state: &SharedState, outcome: Outcome) {
+    let previous = {
+        let mut state = state.lock();
+        if let ReplyState::Pending { .. } = *state {
+            mem::replace(&mut *state, ReplyState::Ready(outcome))
+        } else {
+            return;
+        }
+    };
+    if let ReplyState::Pending {
+        waker: Some(waker), ..
+    } = previous
+    {
+        waker.wake();
+    }
+}
+
+fn decode_reply(codec: &dyn MethodCodec, data: &[u8]) -> Outcome {
+    // dart sends an empty reply if no handler is registered for the channel
+    if data.is_empty() {
+        return Outcome::Reply(MethodCallResult::NotImplemented);
+    }
+    match codec.decode_envelope(data) {
+        Ok(result) => Outcome::Reply(result),
+        Err(error) => Outcome::Undecodable(error),
+    }
+}
+
+struct ReplyCallback {
+    state: SharedState,
+    codec: Arc<dyn MethodCodec>,
+    engine: FlutterEngineWeakRef,
+    channel: String,
+}
+
+/// Reply callbacks by the id the engine gets as user data. A reply that
+/// arrives after its handle was released finds no callback instead of freed
+/// memory.
+fn reply_callbacks() -> &'static Mutex<HashMap<usize, ReplyCallback>> {
+    static CALLBACKS: OnceLock<Mutex<HashMap<usize, ReplyCallback>>> = OnceLock::new();
+    CALLBACKS.get_or_init(Default::default)
+}
+
+static NEXT_CALLBACK_ID: AtomicUsize = AtomicUsize::new(1);
+
+extern "C" fn reply_callback(data: *const u8, size: usize, user_data: *mut c_void) {
+    let callback = match reply_callbacks().lock().remove(&(user_data as usize)) {
+        Some(callback) => callback,
+        None => return,
+    };
+    let data = if data.is_null() {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(data, size) }
+    };
+    let data = match callback.engine.upgrade() {
+        Some(engine) => engine.intercept_reply(&callback.channel, MessageDirection::Reply, data),
+        None => Cow::Borrowed(data),
+    };
+    complete(&callback.state, decode_reply(&*callback.codec, &data));
+}
+
+struct ResponseHandle(*mut FlutterPlatformMessageResponseHandle);
+
+unsafe impl Send for ResponseHandle {}
+
+/// The response handle of a method call, created on the platform thread.
+enum HandleState {
+    Unsent,
+    Sent(ResponseHandle),
+    Released,
+}
+
+/// Resolves to the result dart sent back for a method call.
+///
+/// Returned by [`MethodChannel::invoke_method_with_reply`]. The call is sent
+/// from the platform thread, so it can be made from any thread. Dropping the
+/// reply before it arrives releases the response handle and ignores the
+/// reply.
+///
+/// [`MethodChannel::invoke_method_with_reply`]: super::MethodChannel::invoke_method_with_reply
+pub struct MethodCallReply {
+    state: SharedState,
+    callback_id: Option<usize>,
+    handle: Option<(FlutterEngineWeakRef, Arc<Mutex<HandleState>>)>,
+}
+
+impl MethodCallReply {
+    pub(crate) fn send(
+        engine: &FlutterEngine,
+        channel: &str,
+        message: &[u8],
+        codec: Arc<dyn MethodCodec>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        let state = Arc::new(Mutex::new(ReplyState::Pending { waker: None }));
+        let callback_id = NEXT_CALLBACK_ID.fetch_add(1, Ordering::Relaxed);
+        reply_callbacks().lock().insert(
+            callback_id,
+            ReplyCallback {
+                state: state.clone(),
+                codec,
+                engine: engine.downgrade(),
+                channel: channel.to_owned(),
+            },
+        );
+        let handle = Arc::new(Mutex::new(HandleState::Unsent));
+        {
+            let handle = handle.clone();
+            let state = state.clone();
+            let channel = channel.to_owned();
+            let message = message.to_vec();
+            engine.run_on_platform_thread(move |engine| {
+                let mut handle = handle.lock();
+                // dropped before it was sent
+                if let HandleState::Released = *handle {
+                    return;
+                }
+                // an interceptor answers in place of dart without a handle
+                let message = match engine.intercept_outbound(&channel, &message) {
+                    Intercepted::Pass(message) => message.into_owned(),
+                    Intercepted::Drop => {
+                        reply_callback(ptr::null(), 0, callback_id as _);
+                        return;
+                    }
+                    Intercepted::Reply(reply) => {
+                        reply_callback(reply.as_ptr(), reply.len(), callback_id as _);
+                        return;
+                    }
+                };
+                let response_handle = match engine
+                    .create_platform_message_response_handle(Some(reply_callback), callback_id as _)
+                {
+                    Some(response_handle) => response_handle,
+                    None => {
+                        reply_callbacks().lock().remove(&callback_id);
+                        complete(&state, Outcome::ChannelClosed);
+                        return;
+                    }
+                };
+                *handle = HandleState::Sent(ResponseHandle(response_handle));
+                engine.send_intercepted_platform_message(PlatformMessage {
+                    channel: Cow::Owned(channel),
+                    message: &message,
+                    response_handle: Some(
+                        (response_handle as *const FlutterPlatformMessageResponseHandle).into(),
+                    ),
+                });
+            });
+        }
+        if let Some(timeout) = timeout {
+            timer()
+                .send((Instant::now() + timeout, Arc::downgrade(&state)))
+                .ok();
+        }
+
+        Self {
+            state,
+            callback_id: Some(callback_id),
+            handle: Some((engine.downgrade(), handle)),
+        }
+    }
+
+    /// A reply that fails right away because the message could not be sent.
+    pub(crate) fn channel_closed() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ReplyState::Ready(Outcome::ChannelClosed))),
+            callback_id: None,
+            handle: None,
+        }
+    }
+}
+
+type Timeout = (Instant, Weak<Mutex<ReplyState>>);
+
+/// The sender of the thread that times out all pending replies.
+fn timer() -> &'static Sender<Timeout> {
+    static TIMER: OnceLock<Sender<Timeout>> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        let (sender, receiver) = unbounded();
+        thread::Builder::new()
+            .name("reply timer".into())
+            .spawn(move || run_timer(receiver))
+            .expect("Failed to spawn reply timer thread");
+        sender
+    })
+}
+
+fn run_timer(timeouts: Receiver<Timeout>) {
+    // ordered by deadline, the counter keeps equal deadlines apart
+    let mut pending: BTreeMap<(Instant, u64), Weak<Mutex<ReplyState>>> = BTreeMap::new();
+    let mut next_order = 0u64;
+    loop {
+        let received = match pending.keys().next() {
+            Some(&(deadline, _)) => {
+                timeouts.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => timeouts.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok((deadline, state)) => {
+                pending.insert((deadline, next_order), state);
+                next_order += 1;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        let now = Instant::now();
+        while let Some(&key) = pending.keys().next() {
+            if key.0 > now {
+                break;
+            }
+            // replies that completed or were dropped are gone or no longer
+            // pending
+            if let Some(state) = pending.remove(&key).and_then(|state| state.upgrade()) {
+                complete(&state, Outcome::Timeout);
+            }
+        }
+    }
+}
+
+impl Future for MethodCallReply {
+    type Output = Result<Value, MethodCallError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        if let ReplyState::Pending { waker, .. } = &mut *state {
+            *waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        match mem::replace(&mut *state, ReplyState::Done) {
+            ReplyState::Ready(outcome) => Poll::Ready(outcome.into_result()),
+            _ => panic!("MethodCallReply polled after completion"),
+        }
+    }
+}
+
+impl Drop for MethodCallReply {
+    fn drop(&mut self) {
+        *self.state.lock() = ReplyState::Done;
+        if let Some(callback_id) = self.callback_id.take() {
+            reply_callbacks().lock().remove(&callback_id);
+        }
+        if let Some((engine, handle)) = self.handle.take() {
+            let handle = mem::replace(&mut *handle.lock(), HandleState::Released);
+            if let (HandleState::Sent(handle), Some(engine)) = (handle, engine.upgrade()) {
+                engine.run_on_platform_thread(move |engine| {
+                    engine.release_platform_message_response_handle(handle.0)
+                });
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::channel::{ChannelRegistrar, JsonMethodChannel, MethodCallHandler, MethodChannel};
+    use crate::codec::{json_codec::CODEC, MethodCall};
+    use crate::mock::{block_on, run_engine, Call, MockEngine, MockHandler};
+    use crate::plugins::Plugin;
+    use std::sync::{RwLock, Weak};
+    use std::time::Instant;
+
+    const CHANNEL_NAME: &str = "flutter-rs/reply";
+
+    struct Handler;
+
+    impl MethodCallHandler for Handler {
+        fn on_method_call(
+            &mut self,
+            _call: MethodCall,
+            _engine: FlutterEngine,
+        ) -> Result<Value, MethodCallError> {
+            Err(MethodCallError::NotImplemented)
+        }
+    }
+
+    struct ReplyPlugin {
+        channel: Weak<JsonMethodChannel>,
+        handler: Arc<RwLock<Handler>>,
+    }
+
+    impl Plugin for ReplyPlugin {
+        fn plugin_name() -> &'static str {
+            "reply"
+        }
+
+        fn init_channels(&mut self, registrar: &mut ChannelRegistrar) {
+            let handler = Arc::downgrade(&self.handler);
+            self.channel =
+                registrar.register_channel(JsonMethodChannel::new(CHANNEL_NAME, handler));
+        }
+    }
+
+    fn invoke(engine: &FlutterEngine, timeout: Option<Duration>) -> MethodCallReply {
+        let mut reply = None;
+        engine.with_plugin(|plugin: &ReplyPlugin| {
+            let channel = plugin.channel.upgrade().unwrap();
+            reply = Some(channel.invoke_method_with_reply(
+                MethodCall {
+                    method: "getValue".into(),
+                    args: Value::Null,
+                },
+                timeout,
+            ));
+        });
+        reply.unwrap()
+    }
+
+    fn add_plugin(engine: &FlutterEngine) {
+        engine.add_plugin(ReplyPlugin {
+            channel: Weak::new(),
+            handler: Arc::new(RwLock::new(Handler)),
+        });
+    }
+
+    /// The response handle of the last message sent on the channel.
+    fn response_handle(mock: &MockEngine) -> usize {
+        mock.calls()
+            .into_iter()
+            .rev()
+            .find_map(|call| match call {
+                Call::SendPlatformMessage {
+                    channel,
+                    response_handle,
+                    ..
+                } if channel == CHANNEL_NAME => response_handle,
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn released(mock: &MockEngine, handle: usize) -> bool {
+        mock.calls()
+            .iter()
+            .any(|call| matches!(call, Call::ReleaseResponseHandle(id) if *id == handle))
+    }
+
+    #[test]
+    fn test_invoke_method_with_reply() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+
+        let reply = invoke(&engine, None);
+        let handle = response_handle(&mock);
+        assert!(mock.reply(handle, &CODEC.encode_success_envelope(&Value::I32(42))));
+
+        match block_on(Box::pin(reply)) {
+            Ok(Value::I64(42)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert!(released(&mock, handle));
+    }
+
+    #[test]
+    fn test_invoke_method_with_reply_off_platform_thread() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+
+        let mut channel = None;
+        engine.with_plugin(|plugin: &ReplyPlugin| channel = plugin.channel.upgrade());
+        let channel = channel.unwrap();
+        let reply = thread::spawn(move || {
+            channel.invoke_method_with_reply(
+                MethodCall {
+                    method: "getValue".into(),
+                    args: Value::Null,
+                },
+                None,
+            )
+        })
+        .join()
+        .unwrap();
+        assert!(mock.sent_messages(CHANNEL_NAME).is_empty());
+
+        engine.execute_platform_tasks();
+        assert!(mock.reply(
+            response_handle(&mock),
+            &CODEC.encode_success_envelope(&Value::I32(42))
+        ));
+        match block_on(Box::pin(reply)) {
+            Ok(Value::I64(42)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_drop_before_send() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+
+        let mut channel = None;
+        engine.with_plugin(|plugin: &ReplyPlugin| channel = plugin.channel.upgrade());
+        let channel = channel.unwrap();
+        thread::spawn(move || {
+            channel.invoke_method_with_reply(
+                MethodCall {
+                    method: "getValue".into(),
+                    args: Value::Null,
+                },
+                None,
+            );
+        })
+        .join()
+        .unwrap();
+
+        // the call is not sent once its reply is gone
+        engine.execute_platform_tasks();
+        assert!(mock.sent_messages(CHANNEL_NAME).is_empty());
+        assert!(!mock
+            .calls()
+            .iter()
+            .any(|call| matches!(call, Call::CreateResponseHandle(_))));
+    }
+
+    #[test]
+    fn test_invoke_method_with_reply_error() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+
+        let reply = invoke(&engine, None);
+        let error = CODEC.encode_error_envelope("denied", "Not allowed", &Value::Null);
+        assert!(mock.reply(response_handle(&mock), &error));
+        match block_on(Box::pin(reply)) {
+            Err(MethodCallError::CustomError { code, message, .. }) => {
+                assert_eq!(code, "denied");
+                assert_eq!(message, "Not allowed");
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        let reply = invoke(&engine, None);
+        assert!(mock.reply(response_handle(&mock), &[]));
+        match block_on(Box::pin(reply)) {
+            Err(MethodCallError::NotImplemented) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_invoke_method_with_reply_timeout() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+
+        let start = Instant::now();
+        let reply = invoke(&engine, Some(Duration::from_millis(10)));
+        let handle = response_handle(&mock);
+        match block_on(Box::pin(reply)) {
+            Err(MethodCallError::Timeout) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert!(start.elapsed() >= Duration::from_millis(10));
+        assert!(released(&mock, handle));
+        // a late reply is ignored
+        assert!(mock.reply(handle, &CODEC.encode_success_envelope(&Value::Null)));
+    }
+
+    #[test]
+    fn test_drop_releases_response_handle() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+
+        let reply = invoke(&engine, None);
+        let handle = response_handle(&mock);
+        assert!(!released(&mock, handle));
+        drop(reply);
+        assert!(released(&mock, handle));
+    }
+
+    #[test]
+    fn test_drop_frees_reply_callback() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+
+        let reply = invoke(&engine, None);
+        let handle = response_handle(&mock);
+        let callback_id = reply.callback_id.unwrap();
+        assert!(reply_callbacks().lock().contains_key(&callback_id));
+        drop(reply);
+        assert!(!reply_callbacks().lock().contains_key(&callback_id));
+        // a late reply finds no callback
+        assert!(mock.reply(handle, &CODEC.encode_success_envelope(&Value::Null)));
+    }
+
+    #[test]
+    fn test_timeouts_share_timer() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        add_plugin(&engine);
+
+        let long = invoke(&engine, Some(Duration::from_secs(60)));
+        let long_handle = response_handle(&mock);
+        let short = invoke(&engine, Some(Duration::from_millis(10)));
+
+        // the earlier deadline fires first although it was queued later
+        match block_on(Box::pin(short)) {
+            Err(MethodCallError::Timeout) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert!(mock.reply(long_handle, &CODEC.encode_success_envelope(&Value::Null)));
+        match block_on(Box::pin(long)) {
+            Ok(Value::Null) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
@@ -3,16 +3,21 @@ use std::sync::{Arc, RwLock, Weak};
 use log::error;
 
 use crate::{
-    channel::{ChannelImpl, MethodCallHandler, MethodChannel},
-    codec::{standard_codec::CODEC, MethodCodec},
+    channel::{
+        AsyncMethodCallHandler, ChannelImpl, ExecutionPolicy, MessageQueue, MethodCallHandler,
+        MethodCallHandlerRef, MethodChannel,
+    },
+    codec::{standard_codec::StandardMethodCodec, MethodCodec},
     FlutterEngine, FlutterEngineWeakRef,
 };
 
 pub struct StandardMethodChannel {
     name: String,
     engine: FlutterEngineWeakRef,
-    method_handler: Weak<RwLock<dyn MethodCallHandler + Send + Sync>>,
+    method_handler: MethodCallHandlerRef,
     plugin_name: Option<&'static str>,
+    queue: MessageQueue,
+    codec: Arc<StandardMethodCodec>,
 }
 
 impl StandardMethodChannel {
@@ -23,8 +28,10 @@ impl StandardMethodChannel {
         Self {
             name: name.as_ref().to_owned(),
             engine: Default::default(),
-            method_handler,
+            method_handler: MethodCallHandlerRef::Sync(method_handler),
             plugin_name: None,
+            queue: Default::default(),
+            codec: Default::default(),
         }
     }
 
@@ -32,7 +39,39 @@ impl StandardMethodChannel {
         &mut self,
         method_handler: Weak<RwLock<dyn MethodCallHandler + Send + Sync>>,
     ) {
-        self.method_handler = method_handler;
+        self.method_handler = MethodCallHandlerRef::Sync(method_handler);
+    }
+
+    /// Creates a channel whose calls are answered by an async handler.
+    pub fn new_async<N: AsRef<str>>(
+        name: N,
+        method_handler: Weak<dyn AsyncMethodCallHandler + Send + Sync>,
+    ) -> Self {
+        Self {
+            name: name.as_ref().to_owned(),
+            engine: Default::default(),
+            method_handler: MethodCallHandlerRef::Async(method_handler),
+            plugin_name: None,
+            queue: Default::default(),
+            codec: Default::default(),
+        }
+    }
+
+    pub fn set_async_handler(
+        &mut self,
+        method_handler: Weak<dyn AsyncMethodCallHandler + Send + Sync>,
+    ) {
+        self.method_handler = MethodCallHandlerRef::Async(method_handler);
+    }
+
+    /// Sets how messages received on this channel are run.
+    pub fn set_execution_policy(&mut self, policy: ExecutionPolicy) {
+        self.queue = MessageQueue::new(policy);
+    }
+
+    /// Uses a codec with extensions for custom types.
+    pub fn set_codec(&mut self, codec: Arc<StandardMethodCodec>) {
+        self.codec = codec;
     }
 }
 
@@ -60,11 +99,19 @@ impl ChannelImpl for StandardMethodChannel {
 
 impl MethodChannel for StandardMethodChannel {
     fn method_handler(&self) -> Option<Arc<RwLock<dyn MethodCallHandler + Send + Sync>>> {
-        self.method_handler.upgrade()
+        self.method_handler.sync_handler()
+    }
+
+    fn async_method_handler(&self) -> Option<Arc<dyn AsyncMethodCallHandler + Send + Sync>> {
+        self.method_handler.async_handler()
+    }
+
+    fn codec(&self) -> Arc<dyn MethodCodec> {
+        self.codec.clone()
     }
 
-    fn codec(&self) -> &'static dyn MethodCodec {
-        &CODEC
+    fn message_queue(&self) -> &MessageQueue {
+        &self.queue
     }
 }
 
@@ -1,60 +1,107 @@
-use log::error;
+use log::{error, warn};
+use serde::de::DeserializeOwned;
 use serde_json::json;
 
 use super::{MessageCodec, MethodCall, MethodCallResult, MethodCodec, Value};
+use crate::error::{DecodeError, DecodeErrorReason};
 
+/// Encodes values as json.
+///
+/// Json objects only have string keys, so map keys that are not strings are
+/// written as their json text. If several keys of a map have the same text,
+/// like `1` and `"1"`, the value of the last of them is written.
 pub struct JsonMethodCodec;
 
 pub const CODEC: JsonMethodCodec = JsonMethodCodec {};
 
-impl MethodCodec for JsonMethodCodec {
-    fn decode_method_call(&self, buf: &[u8]) -> Option<MethodCall> {
-        unsafe {
-            let s = std::str::from_utf8_unchecked(buf);
-            serde_json::from_str::<MethodCall>(s).ok()
+/// Parses `buf` as json.
+fn parse<T: DeserializeOwned>(buf: &[u8]) -> Result<T, DecodeError> {
+    let s = std::str::from_utf8(buf)
+        .map_err(|err| DecodeError::new(err.valid_up_to(), DecodeErrorReason::InvalidUtf8))?;
+    serde_json::from_str(s).map_err(|err| {
+        // serde_json reports 1-based lines and columns
+        let position = s
+            .split('\n')
+            .take(err.line().saturating_sub(1))
+            .map(|line| line.len() + 1)
+            .sum::<usize>()
+            + err.column().saturating_sub(1);
+        DecodeError::new(
+            position.min(s.len()),
+            DecodeErrorReason::Json(err.to_string()),
+        )
+    })
+}
+
+/// Converts `v` to json. Json objects only have string keys, so other map
+/// keys are replaced by their json text. A key whose text is already taken by
+/// an earlier key of the map replaces its value.
+fn to_json(v: &Value) -> serde_json::Value {
+    match v {
+        Value::List(list) => serde_json::Value::Array(list.iter().map(to_json).collect()),
+        Value::Map(map) => {
+            let mut object = serde_json::Map::new();
+            for (k, v) in map.iter() {
+                let key = match k {
+                    Value::String(s) => s.clone(),
+                    k => to_json(k).to_string(),
+                };
+                if object.insert(key, to_json(v)).is_some() {
+                    warn!("Map key {:?} replaces the value of an earlier key", k);
+                }
+            }
+            serde_json::Value::Object(object)
         }
+        Value::Custom(_, v) => to_json(v),
+        v => json!(v),
     }
+}
 
-    fn decode_envelope(&self, buf: &[u8]) -> Option<MethodCallResult> {
-        unsafe {
-            let s = std::str::from_utf8_unchecked(buf);
-            let json: Value = serde_json::from_str(s).unwrap();
-            if let Value::List(mut v) = json {
-                if v.len() == 1 {
-                    return Some(MethodCallResult::Ok(v.swap_remove(0)));
-                } else if v.len() == 3 {
-                    return Some(MethodCallResult::Err {
-                        code: match &v[0] {
-                            Value::String(s) => s.clone(),
-                            _ => "".into(),
-                        },
-                        message: match &v[1] {
-                            Value::String(s) => s.clone(),
-                            _ => "".into(),
-                        },
-                        details: v.swap_remove(2),
-                    });
-                }
+impl MethodCodec for JsonMethodCodec {
+    fn decode_method_call(&self, buf: &[u8]) -> Result<MethodCall, DecodeError> {
+        parse(buf)
+    }
+
+    fn decode_envelope(&self, buf: &[u8]) -> Result<MethodCallResult, DecodeError> {
+        let json: Value = parse(buf)?;
+        if let Value::List(mut v) = json {
+            if v.len() == 1 {
+                return Ok(MethodCallResult::Ok(v.swap_remove(0)));
+            } else if v.len() == 3 {
+                return Ok(MethodCallResult::Err {
+                    code: match &v[0] {
+                        Value::String(s) => s.clone(),
+                        _ => "".into(),
+                    },
+                    message: match &v[1] {
+                        Value::String(s) => s.clone(),
+                        _ => "".into(),
+                    },
+                    details: v.swap_remove(2),
+                });
             }
-            error!("Invalid envelope: {}", s);
-            None
         }
+        error!("Invalid envelope: {}", String::from_utf8_lossy(buf));
+        Err(DecodeError::new(0, DecodeErrorReason::InvalidEnvelope))
     }
 
     fn encode_method_call(&self, v: &MethodCall) -> Vec<u8> {
-        let json = json!(v);
+        let json = json!({
+            "method": v.method,
+            "args": to_json(&v.args),
+        });
         let s = serde_json::to_string(&json).unwrap();
         s.into_bytes()
     }
 
     fn encode_success_envelope(&self, v: &Value) -> Vec<u8> {
-        let json = json!([v]);
+        let json = json!([to_json(v)]);
         let s = serde_json::to_string(&json).unwrap();
         s.into_bytes()
     }
 
     fn encode_error_envelope(&self, code: &str, message: &str, v: &Value) -> Vec<u8> {
-        let json = json!([code, message, v]);
+        let json = json!([code, message, to_json(v)]);
         let s = serde_json::to_string(&json).unwrap();
         s.into_bytes()
     }
@@ -62,15 +109,49 @@ impl MethodCodec for JsonMethodCodec {
 
 impl MessageCodec for JsonMethodCodec {
     fn encode_message(&self, v: &Value) -> Vec<u8> {
-        let json = json!(v);
+        let json = to_json(v);
         let s = serde_json::to_string(&json).unwrap();
         s.into_bytes()
     }
 
-    fn decode_message(&self, buf: &[u8]) -> Option<Value> {
-        unsafe {
-            let s = std::str::from_utf8_unchecked(buf);
-            serde_json::from_str::<Value>(s).ok()
-        }
+    fn decode_message(&self, buf: &[u8]) -> Result<Value, DecodeError> {
+        parse(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::ValueMap;
+
+    #[test]
+    fn test_map_keys_are_written_as_strings() {
+        let mut map = ValueMap::new();
+        map.insert("text", Value::Boolean(true));
+        map.insert(Value::I32(1), Value::F64(f64::NAN));
+        map.insert(Value::List(vec![Value::Null]), Value::F32List(vec![0.5]));
+        let buf = CODEC.encode_message(&Value::Map(map));
+
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json, json!({ "text": true, "1": null, "[null]": [0.5] }));
+    }
+
+    #[test]
+    fn test_last_colliding_map_key_wins() {
+        let mut map = ValueMap::new();
+        map.insert(Value::I32(1), Value::String("a".into()));
+        map.insert("1", Value::String("b".into()));
+        let buf = CODEC.encode_message(&Value::Map(map));
+
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json, json!({ "1": "b" }));
+
+        let mut map = ValueMap::new();
+        map.insert("1", Value::String("a".into()));
+        map.insert(Value::I32(1), Value::String("b".into()));
+        let buf = CODEC.encode_message(&Value::Map(map));
+
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json, json!({ "1": "b" }));
     }
 }
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-pub use self::value::Value;
+use crate::error::DecodeError;
+
+pub use self::value::{Value, ValueMap};
 
 pub mod json_codec;
 pub mod standard_codec;
@@ -26,7 +28,7 @@ pub enum MethodCallResult {
 
 pub trait MethodCodec: Send + Sync {
     /// Methods for handling dart call
-    fn decode_method_call(&self, buf: &[u8]) -> Option<MethodCall>;
+    fn decode_method_call(&self, buf: &[u8]) -> Result<MethodCall, DecodeError>;
     fn encode_success_envelope(&self, v: &Value) -> Vec<u8>;
     fn encode_error_envelope(&self, code: &str, message: &str, details: &Value) -> Vec<u8>;
 
@@ -44,11 +46,21 @@ pub trait MethodCodec: Send + Sync {
 
     /// Methods for calling into dart
     fn encode_method_call(&self, v: &MethodCall) -> Vec<u8>;
-    fn decode_envelope(&self, buf: &[u8]) -> Option<MethodCallResult>;
+    fn decode_envelope(&self, buf: &[u8]) -> Result<MethodCallResult, DecodeError>;
 }
 
 pub trait MessageCodec: Send + Sync {
     /// Methods for plain messages
     fn encode_message(&self, v: &Value) -> Vec<u8>;
-    fn decode_message(&self, buf: &[u8]) -> Option<Value>;
+    fn decode_message(&self, buf: &[u8]) -> Result<Value, DecodeError>;
+}
+
+impl<T: MessageCodec + ?Sized> MessageCodec for &T {
+    fn encode_message(&self, v: &Value) -> Vec<u8> {
+        (**self).encode_message(v)
+    }
+
+    fn decode_message(&self, buf: &[u8]) -> Result<Value, DecodeError> {
+        (**self).decode_message(buf)
+    }
 }
@@ -1,9 +1,8 @@
 use std::convert::AsMut;
-use std::{collections::HashMap, slice};
+use std::sync::Arc;
 
-use log::error;
-
-use super::{MessageCodec, MethodCall, MethodCallResult, MethodCodec, Value};
+use super::{MessageCodec, MethodCall, MethodCallResult, MethodCodec, Value, ValueMap};
+use crate::error::{DecodeError, DecodeErrorReason};
 
 const VALUE_NULL: u8 = 0;
 const VALUE_TRUE: u8 = 1;
@@ -19,87 +18,145 @@ const VALUE_INT64LIST: u8 = 10;
 const VALUE_FLOAT64LIST: u8 = 11;
 const VALUE_LIST: u8 = 12;
 const VALUE_MAP: u8 = 13;
+const VALUE_FLOAT32LIST: u8 = 14;
+
+/// Values nested deeper than this are rejected instead of overflowing the
+/// stack.
+const MAX_DEPTH: usize = 128;
 
-#[derive(Debug)]
-enum DecodeError {
-    Invalid,
-    Ended,
+/// Reads and writes values with custom type codes, like a subclass of
+/// `StandardMessageCodec` in dart.
+///
+/// The values are decoded into [`Value::Custom`] with their type code. Type
+/// codes of standard values are never passed to an extension; flutter
+/// suggests codes from 128 on for custom types.
+pub trait StandardCodecExtension: Send + Sync {
+    /// Whether this extension reads and writes values with `type_code`.
+    fn handles(&self, type_code: u8) -> bool;
+
+    /// Reads the payload of a value with `type_code`. The payload is a single
+    /// standard value by default.
+    fn read_value(&self, type_code: u8, reader: &mut Reader) -> Result<Value, DecodeError> {
+        let _ = type_code;
+        reader.read_value()
+    }
+
+    /// Writes the payload of a value with `type_code`. Must be the inverse of
+    /// [`read_value`](Self::read_value).
+    fn write_value(&self, type_code: u8, value: &Value, writer: &mut Writer) {
+        let _ = type_code;
+        writer.write_value(value);
+    }
 }
 
-pub struct StandardMethodCodec;
+/// The binary codec of flutter's `StandardMethodCodec` and
+/// `StandardMessageCodec`.
+///
+/// Use [`CODEC`] unless custom types are needed. A codec with extensions can
+/// be shared by channels with `set_codec`.
+#[derive(Clone, Default)]
+pub struct StandardMethodCodec {
+    extensions: Vec<Arc<dyn StandardCodecExtension>>,
+}
 
-pub const CODEC: StandardMethodCodec = StandardMethodCodec {};
+pub static CODEC: StandardMethodCodec = StandardMethodCodec::new();
 
 impl StandardMethodCodec {
+    pub const fn new() -> Self {
+        Self {
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Adds an extension for custom type codes. Extensions added first are
+    /// asked first.
+    pub fn add_extension<E>(&mut self, extension: E)
+    where
+        E: StandardCodecExtension + 'static,
+    {
+        self.extensions.push(Arc::new(extension));
+    }
+
+    fn extension(&self, type_code: u8) -> Option<&dyn StandardCodecExtension> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.handles(type_code))
+            .map(|extension| extension.as_ref())
+    }
+
     fn read_value(reader: &mut Reader) -> Result<Value, DecodeError> {
         if reader.ended() {
-            return Err(DecodeError::Ended);
+            return Err(reader.error(DecodeErrorReason::UnexpectedEnd));
         }
 
-        let t = reader.read_u8();
+        let position = reader.pos;
+        let t = reader.read_u8()?;
         Ok(match t {
             VALUE_NULL => Value::Null,
             VALUE_FALSE => Value::Boolean(false),
             VALUE_TRUE => Value::Boolean(true),
-            VALUE_INT32 => Value::I32(reader.read_i32()),
-            VALUE_INT64 => Value::I64(reader.read_i64()),
-            VALUE_LARGEINT => panic!("Not implemented"),
+            VALUE_INT32 => Value::I32(reader.read_i32()?),
+            VALUE_INT64 => Value::I64(reader.read_i64()?),
+            VALUE_LARGEINT => {
+                let len = reader.read_length(1, 1)?;
+                Value::LargeInt(reader.read_string(len)?)
+            }
             VALUE_FLOAT64 => {
                 reader.align_to(8);
-                Value::F64(reader.read_f64())
+                Value::F64(reader.read_f64()?)
             }
             VALUE_STRING => {
-                let len = reader.read_size();
-                Value::String(reader.read_string(len))
+                let len = reader.read_length(1, 1)?;
+                Value::String(reader.read_string(len)?)
             }
             VALUE_UINT8LIST => {
-                let len = reader.read_size();
-                Value::U8List(reader.read_u8_list(len))
+                let len = reader.read_length(1, 1)?;
+                Value::U8List(reader.read_u8_list(len)?)
             }
             VALUE_INT32LIST => {
-                let len = reader.read_size();
-                Value::I32List(reader.read_i32_list(len))
+                let len = reader.read_length(4, 4)?;
+                Value::I32List(reader.read_i32_list(len)?)
             }
             VALUE_INT64LIST => {
-                let len = reader.read_size();
-                Value::I64List(reader.read_i64_list(len))
+                let len = reader.read_length(8, 8)?;
+                Value::I64List(reader.read_i64_list(len)?)
+            }
+            VALUE_FLOAT32LIST => {
+                let len = reader.read_length(4, 4)?;
+                Value::F32List(reader.read_f32_list(len)?)
             }
             VALUE_FLOAT64LIST => {
-                let len = reader.read_size();
-                Value::F64List(reader.read_f64_list(len))
+                let len = reader.read_length(8, 8)?;
+                Value::F64List(reader.read_f64_list(len)?)
             }
             VALUE_LIST => {
-                let len = reader.read_size();
-                let mut list = Vec::new();
+                // every element takes at least one byte
+                let len = reader.read_length(1, 1)?;
+                let mut list = Vec::with_capacity(len);
                 for _ in 0..len {
-                    if let Ok(e) = Self::read_value(reader) {
-                        list.push(e);
-                    } else {
-                        return Err(DecodeError::Invalid);
-                    }
+                    list.push(reader.read_value()?);
                 }
                 Value::List(list)
             }
             VALUE_MAP => {
-                let len = reader.read_size();
-                let mut map = HashMap::new();
+                let len = reader.read_length(1, 2)?;
+                let mut map = ValueMap::with_capacity(len);
                 for _ in 0..len {
-                    let k = Self::read_value(reader);
-                    let v = Self::read_value(reader);
-                    if k.is_err() || v.is_err() {
-                        return Err(DecodeError::Invalid);
-                    }
-                    let k = k.unwrap();
-                    let v = v.unwrap();
-                    if let Value::String(k) = k {
-                        map.insert(k, v);
-                    } else {
-                        return Err(DecodeError::Invalid);
-                    }
+                    let k = reader.read_value()?;
+                    let v = reader.read_value()?;
+                    map.insert(k, v);
                 }
                 Value::Map(map)
             }
-            _ => Value::Null,
+            _ => match reader.codec.extension(t) {
+                Some(extension) => Value::Custom(t, Box::new(extension.read_value(t, reader)?)),
+                None => {
+                    return Err(DecodeError::new(
+                        position,
+                        DecodeErrorReason::UnknownType(t),
+                    ))
+                }
+            },
         })
     }
     fn write_string(writer: &mut Writer, s: &str) {
@@ -123,6 +180,11 @@ impl StandardMethodCodec {
                 writer.write_u8(VALUE_INT64);
                 writer.write_i64(*n);
             }
+            Value::LargeInt(s) => {
+                writer.write_u8(VALUE_LARGEINT);
+                writer.write_size(s.len());
+                writer.write_string(s);
+            }
             Value::F64(n) => {
                 writer.write_u8(VALUE_FLOAT64);
                 writer.align_to(8);
@@ -155,6 +217,14 @@ impl StandardMethodCodec {
                     writer.write_i64(*n);
                 }
             }
+            Value::F32List(list) => {
+                writer.write_u8(VALUE_FLOAT32LIST);
+                writer.write_size(list.len());
+                writer.align_to(4);
+                for n in list {
+                    writer.write_f32(*n);
+                }
+            }
             Value::F64List(list) => {
                 writer.write_u8(VALUE_FLOAT64LIST);
                 writer.write_size(list.len());
@@ -174,62 +244,75 @@ impl StandardMethodCodec {
                 writer.write_u8(VALUE_MAP);
                 writer.write_size(map.len());
                 map.iter().for_each(|(k, v)| {
-                    Self::write_string(writer, k);
+                    Self::write_value(writer, k);
                     Self::write_value(writer, v);
                 });
             }
+            Value::Custom(type_code, value) => {
+                debug_assert!(
+                    *type_code > VALUE_FLOAT32LIST,
+                    "type code of a standard value"
+                );
+                writer.write_u8(*type_code);
+                // written like the default extension would without one
+                match writer.codec.extension(*type_code) {
+                    Some(extension) => extension.write_value(*type_code, value, writer),
+                    None => Self::write_value(writer, value),
+                }
+            }
         }
     }
 }
 
 impl MethodCodec for StandardMethodCodec {
     fn encode_method_call(&self, v: &MethodCall) -> Vec<u8> {
-        let mut writer = Writer::new(Vec::new());
+        let mut writer = Writer::new(self);
         // Can we avoid this clone?
         StandardMethodCodec::write_value(&mut writer, &Value::String(v.method.to_owned()));
         StandardMethodCodec::write_value(&mut writer, &v.args);
-        writer.0
+        writer.buf
     }
 
-    fn decode_method_call(&self, buf: &[u8]) -> Option<MethodCall> {
-        let mut reader = Reader::new(buf);
-        let method: Value = StandardMethodCodec::read_value(&mut reader).unwrap();
-        let args: Value = StandardMethodCodec::read_value(&mut reader).unwrap();
-
-        if let Value::String(method) = method {
-            return Some(MethodCall { method, args });
-        }
-        error!("Invalid method call");
-        None
+    fn decode_method_call(&self, buf: &[u8]) -> Result<MethodCall, DecodeError> {
+        let mut reader = Reader::new(self, buf);
+        let method = match reader.read_value()? {
+            Value::String(method) => method,
+            _ => return Err(DecodeError::new(0, DecodeErrorReason::InvalidMethodCall)),
+        };
+        let args = reader.read_value()?;
+        Ok(MethodCall { method, args })
     }
 
     fn encode_success_envelope(&self, result: &Value) -> Vec<u8> {
-        let mut writer = Writer::new(Vec::new());
+        let mut writer = Writer::new(self);
         writer.write_u8(0);
         StandardMethodCodec::write_value(&mut writer, result);
-        writer.0
+        writer.buf
     }
 
     fn encode_error_envelope(&self, code: &str, message: &str, v: &Value) -> Vec<u8> {
-        let mut writer = Writer::new(Vec::new());
+        let mut writer = Writer::new(self);
         writer.write_u8(1);
         StandardMethodCodec::write_value(&mut writer, &Value::String(code.to_owned()));
         StandardMethodCodec::write_value(&mut writer, &Value::String(message.to_owned()));
         StandardMethodCodec::write_value(&mut writer, v);
-        writer.0
+        writer.buf
     }
 
-    fn decode_envelope(&self, buf: &[u8]) -> Option<MethodCallResult> {
-        let mut reader = Reader::new(buf);
-        let n = reader.read_u8();
+    fn decode_envelope(&self, buf: &[u8]) -> Result<MethodCallResult, DecodeError> {
+        let mut reader = Reader::new(self, buf);
+        if reader.ended() {
+            return Err(reader.error(DecodeErrorReason::UnexpectedEnd));
+        }
+        let n = reader.read_u8()?;
         if n == 0 {
-            let ret = StandardMethodCodec::read_value(&mut reader).unwrap();
-            Some(MethodCallResult::Ok(ret))
+            let ret = reader.read_value()?;
+            Ok(MethodCallResult::Ok(ret))
         } else if n == 1 {
-            let code = StandardMethodCodec::read_value(&mut reader).unwrap();
-            let message = StandardMethodCodec::read_value(&mut reader).unwrap();
-            let details = StandardMethodCodec::read_value(&mut reader).unwrap();
-            Some(MethodCallResult::Err {
+            let code = reader.read_value()?;
+            let message = reader.read_value()?;
+            let details = reader.read_value()?;
+            Ok(MethodCallResult::Err {
                 code: match code {
                     Value::String(s) => s,
                     _ => "".into(),
@@ -241,122 +324,160 @@ impl MethodCodec for StandardMethodCodec {
                 details,
             })
         } else {
-            None
+            Err(DecodeError::new(0, DecodeErrorReason::InvalidEnvelope))
         }
     }
 }
 
 impl MessageCodec for StandardMethodCodec {
     fn encode_message(&self, v: &Value) -> Vec<u8> {
-        let mut writer = Writer::new(Vec::new());
+        let mut writer = Writer::new(self);
         StandardMethodCodec::write_value(&mut writer, v);
-        writer.0
+        writer.buf
     }
 
-    fn decode_message(&self, buf: &[u8]) -> Option<Value> {
-        let mut reader = Reader::new(buf);
-        StandardMethodCodec::read_value(&mut reader).ok()
+    fn decode_message(&self, buf: &[u8]) -> Result<Value, DecodeError> {
+        let mut reader = Reader::new(self, buf);
+        reader.read_value()
     }
 }
 
-struct Reader<'a> {
+/// Reads a message of the standard codec. Given to extensions to read
+/// custom values.
+pub struct Reader<'a> {
+    codec: &'a StandardMethodCodec,
     buf: &'a [u8],
     pos: usize,
+    depth: usize,
 }
 
 impl<'a> Reader<'a> {
-    fn new(buf: &'a [u8]) -> Self {
-        Reader { buf, pos: 0 }
-    }
-    fn read_u8(&mut self) -> u8 {
-        let n = self.buf[self.pos];
-        self.pos += 1;
-        n
-    }
-    fn read_u16(&mut self) -> u16 {
-        self.pos += 2;
-        let s = &self.buf[self.pos - 2..self.pos];
-        u16::from_ne_bytes(clone_into_array(s))
-    }
-    fn read_u32(&mut self) -> u32 {
-        self.pos += 4;
-        let s = &self.buf[self.pos - 4..self.pos];
-        u32::from_ne_bytes(clone_into_array(s))
-    }
-    fn read_i32(&mut self) -> i32 {
-        self.pos += 4;
-        let s = &self.buf[self.pos - 4..self.pos];
-        i32::from_ne_bytes(clone_into_array(s))
-    }
-    fn read_u64(&mut self) -> u64 {
-        self.pos += 8;
-        let s = &self.buf[self.pos - 8..self.pos];
-        u64::from_ne_bytes(clone_into_array(s))
-    }
-    fn read_i64(&mut self) -> i64 {
-        self.pos += 8;
-        let s = &self.buf[self.pos - 8..self.pos];
-        i64::from_ne_bytes(clone_into_array(s))
-    }
-    fn read_f64(&mut self) -> f64 {
-        let n = self.read_u64();
-        f64::from_bits(n)
-    }
-    fn read_size(&mut self) -> usize {
-        let n = self.read_u8();
-        match n {
-            254 => self.read_u16() as usize,
-            255 => self.read_u32() as usize,
+    fn new(codec: &'a StandardMethodCodec, buf: &'a [u8]) -> Self {
+        Reader {
+            codec,
+            buf,
+            pos: 0,
+            depth: 0,
+        }
+    }
+    /// Reads a value including its type code.
+    pub fn read_value(&mut self) -> Result<Value, DecodeError> {
+        if self.depth > MAX_DEPTH {
+            return Err(self.error(DecodeErrorReason::NestingTooDeep));
+        }
+        self.depth += 1;
+        let value = StandardMethodCodec::read_value(self);
+        self.depth -= 1;
+        value
+    }
+    fn remaining(&self) -> usize {
+        self.buf.len().saturating_sub(self.pos)
+    }
+    /// Reads `len` raw bytes.
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.remaining() {
+            return Err(self.error(DecodeErrorReason::UnexpectedEnd));
+        }
+        let s = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(s)
+    }
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+    fn read_u16(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_ne_bytes(clone_into_array(self.read_bytes(2)?)))
+    }
+    fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_ne_bytes(clone_into_array(self.read_bytes(4)?)))
+    }
+    pub fn read_i32(&mut self) -> Result<i32, DecodeError> {
+        Ok(i32::from_ne_bytes(clone_into_array(self.read_bytes(4)?)))
+    }
+    fn read_u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_ne_bytes(clone_into_array(self.read_bytes(8)?)))
+    }
+    pub fn read_i64(&mut self) -> Result<i64, DecodeError> {
+        Ok(i64::from_ne_bytes(clone_into_array(self.read_bytes(8)?)))
+    }
+    pub fn read_f32(&mut self) -> Result<f32, DecodeError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+    pub fn read_f64(&mut self) -> Result<f64, DecodeError> {
+        Ok(f64::from_bits(self.read_u64()?))
+    }
+    pub fn read_size(&mut self) -> Result<usize, DecodeError> {
+        let n = self.read_u8()?;
+        Ok(match n {
+            254 => self.read_u16()? as usize,
+            255 => self.read_u32()? as usize,
             _ => n as usize,
+        })
+    }
+    /// Reads a size prefix and aligns to the elements that follow it. Fails
+    /// if `len` elements of `size` bytes cannot fit in the remaining bytes,
+    /// so the size can be used to allocate.
+    pub fn read_length(&mut self, align: usize, size: usize) -> Result<usize, DecodeError> {
+        let position = self.pos;
+        let len = self.read_size()?;
+        self.align_to(align);
+        match len.checked_mul(size) {
+            Some(n) if n <= self.remaining() => Ok(len),
+            _ => Err(DecodeError::new(
+                position,
+                DecodeErrorReason::InvalidLength(len),
+            )),
         }
     }
-    fn read_string(&mut self, len: usize) -> String {
-        unsafe {
-            if len == 0 {
-                String::from("")
-            } else {
-                let v = slice::from_raw_parts(&self.buf[self.pos], len);
-                self.pos += len;
-                String::from_utf8_lossy(v).to_owned().to_string()
-            }
+    pub fn read_string(&mut self, len: usize) -> Result<String, DecodeError> {
+        let position = self.pos;
+        let v = self.read_bytes(len)?;
+        match std::str::from_utf8(v) {
+            Ok(s) => Ok(s.to_owned()),
+            Err(err) => Err(DecodeError::new(
+                position + err.valid_up_to(),
+                DecodeErrorReason::InvalidUtf8,
+            )),
         }
     }
-    fn read_u8_list(&mut self, len: usize) -> Vec<u8> {
+    fn read_u8_list(&mut self, len: usize) -> Result<Vec<u8>, DecodeError> {
+        Ok(self.read_bytes(len)?.to_vec())
+    }
+    fn read_i32_list(&mut self, len: usize) -> Result<Vec<i32>, DecodeError> {
         let mut v = Vec::with_capacity(len);
         for _ in 0..len {
-            v.push(self.read_u8());
+            v.push(self.read_i32()?);
         }
-        v
+        Ok(v)
     }
-    fn read_i32_list(&mut self, len: usize) -> Vec<i32> {
+    fn read_i64_list(&mut self, len: usize) -> Result<Vec<i64>, DecodeError> {
         let mut v = Vec::with_capacity(len);
-        self.align_to(4);
         for _ in 0..len {
-            v.push(self.read_i32());
+            v.push(self.read_i64()?);
         }
-        v
+        Ok(v)
     }
-    fn read_i64_list(&mut self, len: usize) -> Vec<i64> {
+    fn read_f32_list(&mut self, len: usize) -> Result<Vec<f32>, DecodeError> {
         let mut v = Vec::with_capacity(len);
-        self.align_to(8);
         for _ in 0..len {
-            v.push(self.read_i64());
+            v.push(self.read_f32()?);
         }
-        v
+        Ok(v)
     }
-    fn read_f64_list(&mut self, len: usize) -> Vec<f64> {
+    fn read_f64_list(&mut self, len: usize) -> Result<Vec<f64>, DecodeError> {
         let mut v = Vec::with_capacity(len);
-        self.align_to(8);
         for _ in 0..len {
-            let n = self.read_u64();
-            v.push(f64::from_bits(n));
+            v.push(self.read_f64()?);
         }
-        v
+        Ok(v)
     }
     fn ended(&self) -> bool {
         self.pos >= self.buf.len()
     }
-    fn align_to(&mut self, align: usize) {
+    fn error(&self, reason: DecodeErrorReason) -> DecodeError {
+        DecodeError::new(self.pos, reason)
+    }
+    pub fn align_to(&mut self, align: usize) {
         let m = self.pos % align;
         if m > 0 {
             self.pos += align - m;
@@ -364,34 +485,49 @@ impl<'a> Reader<'a> {
     }
 }
 
-struct Writer(Vec<u8>);
+/// Writes a message of the standard codec. Given to extensions to write
+/// custom values.
+pub struct Writer<'a> {
+    codec: &'a StandardMethodCodec,
+    buf: Vec<u8>,
+}
 
-impl Writer {
-    fn new(v: Vec<u8>) -> Self {
-        Writer(v)
+impl<'a> Writer<'a> {
+    fn new(codec: &'a StandardMethodCodec) -> Self {
+        Writer {
+            codec,
+            buf: Vec::new(),
+        }
+    }
+    /// Writes a value including its type code.
+    pub fn write_value(&mut self, value: &Value) {
+        StandardMethodCodec::write_value(self, value);
     }
-    fn write_u8(&mut self, n: u8) {
-        self.0.push(n);
+    pub fn write_u8(&mut self, n: u8) {
+        self.buf.push(n);
     }
     fn write_u16(&mut self, n: u16) {
-        self.0.extend_from_slice(&n.to_ne_bytes());
+        self.buf.extend_from_slice(&n.to_ne_bytes());
     }
     fn write_u32(&mut self, n: u32) {
-        self.0.extend_from_slice(&n.to_ne_bytes());
+        self.buf.extend_from_slice(&n.to_ne_bytes());
     }
-    fn write_i32(&mut self, n: i32) {
-        self.0.extend_from_slice(&n.to_ne_bytes());
+    pub fn write_i32(&mut self, n: i32) {
+        self.buf.extend_from_slice(&n.to_ne_bytes());
     }
     fn write_u64(&mut self, n: u64) {
-        self.0.extend_from_slice(&n.to_ne_bytes());
+        self.buf.extend_from_slice(&n.to_ne_bytes());
     }
-    fn write_i64(&mut self, n: i64) {
-        self.0.extend_from_slice(&n.to_ne_bytes());
+    pub fn write_i64(&mut self, n: i64) {
+        self.buf.extend_from_slice(&n.to_ne_bytes());
     }
-    fn write_f64(&mut self, n: f64) {
+    pub fn write_f32(&mut self, n: f32) {
+        self.write_u32(n.to_bits());
+    }
+    pub fn write_f64(&mut self, n: f64) {
         self.write_u64(n.to_bits());
     }
-    fn write_size(&mut self, n: usize) {
+    pub fn write_size(&mut self, n: usize) {
         if n < 254 {
             self.write_u8(n as u8);
         } else if n <= u16::max_value() as usize {
@@ -405,11 +541,15 @@ impl Writer {
             panic!("Not implemented");
         }
     }
-    fn write_string(&mut self, s: &str) {
-        self.0.extend_from_slice(s.as_bytes());
+    pub fn write_string(&mut self, s: &str) {
+        self.write_bytes(s.as_bytes());
+    }
+    /// Writes raw bytes.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
     }
-    fn align_to(&mut self, align: usize) {
-        let m = self.0.len() % align;
+    pub fn align_to(&mut self, align: usize) {
+        let m = self.buf.len() % align;
         if m == 0 {
             return;
         }
@@ -429,3 +569,231 @@ where
     <A as AsMut<[T]>>::as_mut(&mut a).clone_from_slice(slice);
     a
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    /// Custom values whose payload is a standard value, like pigeon writes
+    /// them.
+    struct Payload;
+
+    impl StandardCodecExtension for Payload {
+        fn handles(&self, type_code: u8) -> bool {
+            type_code >= 128
+        }
+    }
+
+    /// A point with two coordinates that are written without type codes.
+    struct Point;
+
+    impl StandardCodecExtension for Point {
+        fn handles(&self, type_code: u8) -> bool {
+            type_code == 129
+        }
+
+        fn read_value(&self, _: u8, reader: &mut Reader) -> Result<Value, DecodeError> {
+            reader.align_to(8);
+            Ok(Value::F64List(vec![reader.read_f64()?, reader.read_f64()?]))
+        }
+
+        fn write_value(&self, _: u8, value: &Value, writer: &mut Writer) {
+            writer.align_to(8);
+            if let Value::F64List(point) = value {
+                writer.write_f64(point[0]);
+                writer.write_f64(point[1]);
+            }
+        }
+    }
+
+    fn extended() -> StandardMethodCodec {
+        let mut codec = StandardMethodCodec::new();
+        codec.add_extension(Point);
+        codec.add_extension(Payload);
+        codec
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::Boolean),
+            any::<i32>().prop_map(Value::I32),
+            any::<i64>().prop_map(Value::I64),
+            "-?[1-9a-f][0-9a-f]{16,40}".prop_map(Value::LargeInt),
+            any::<f64>().prop_map(Value::F64),
+            any::<String>().prop_map(Value::String),
+            // long enough to need a two byte size
+            vec(any::<u8>(), 0..300).prop_map(Value::U8List),
+            vec(any::<i32>(), 0..16).prop_map(Value::I32List),
+            vec(any::<i64>(), 0..16).prop_map(Value::I64List),
+            vec(any::<f32>(), 0..16).prop_map(Value::F32List),
+            vec(any::<f64>(), 0..16).prop_map(Value::F64List),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                vec(inner.clone(), 0..8).prop_map(Value::List),
+                vec((inner.clone(), inner.clone()), 0..8)
+                    .prop_map(|entries| Value::Map(entries.into_iter().collect())),
+                // 129 is read by the point extension
+                (130u8.., inner).prop_map(|(t, value)| Value::Custom(t, Box::new(value))),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_message_round_trip(value in value()) {
+            let codec = extended();
+            let buf = codec.encode_message(&value);
+            prop_assert_eq!(codec.decode_message(&buf), Ok(value));
+        }
+
+        #[test]
+        fn test_method_call_round_trip(method in any::<String>(), args in value()) {
+            let codec = extended();
+            let buf = codec.encode_method_call(&MethodCall {
+                method: method.clone(),
+                args: args.clone(),
+            });
+            let call = codec.decode_method_call(&buf).unwrap();
+            prop_assert_eq!(call.method, method);
+            prop_assert_eq!(call.args, args);
+        }
+
+        #[test]
+        fn test_envelope_round_trip(code in any::<String>(), details in value()) {
+            let codec = extended();
+            let buf = codec.encode_success_envelope(&details);
+            match codec.decode_envelope(&buf) {
+                Ok(MethodCallResult::Ok(value)) => prop_assert_eq!(value, details.clone()),
+                _ => panic!("Expected a success envelope"),
+            }
+
+            let buf = codec.encode_error_envelope(&code, "message", &details);
+            match codec.decode_envelope(&buf) {
+                Ok(MethodCallResult::Err { code: c, message, details: d }) => {
+                    prop_assert_eq!(c, code);
+                    prop_assert_eq!(message, "message");
+                    prop_assert_eq!(d, details);
+                }
+                _ => panic!("Expected an error envelope"),
+            }
+        }
+
+        #[test]
+        fn test_truncated_message_is_rejected(value in value(), cut in any::<prop::sample::Index>()) {
+            let codec = extended();
+            let buf = codec.encode_message(&value);
+            let len = cut.index(buf.len());
+            // only the alignment after an empty list may be missing
+            if let Ok(decoded) = codec.decode_message(&buf[..len]) {
+                prop_assert_eq!(decoded, value);
+            }
+        }
+
+        #[test]
+        fn test_decoding_never_panics(buf in vec(any::<u8>(), 0..256)) {
+            let codec = extended();
+            let _ = codec.decode_message(&buf);
+            let _ = codec.decode_method_call(&buf);
+            let _ = codec.decode_envelope(&buf);
+        }
+    }
+
+    #[test]
+    fn test_decode_large_int() {
+        let mut buf = vec![VALUE_LARGEINT, 17];
+        buf.extend_from_slice(b"1ffffffffffffffff");
+        assert_eq!(
+            CODEC.decode_message(&buf),
+            Ok(Value::LargeInt("1ffffffffffffffff".into()))
+        );
+    }
+
+    #[test]
+    fn test_size_is_checked_before_allocating() {
+        let mut buf = vec![VALUE_LIST, 255];
+        buf.extend_from_slice(&0xffff_ffffu32.to_ne_bytes());
+        assert_eq!(
+            CODEC.decode_message(&buf),
+            Err(DecodeError::new(
+                1,
+                DecodeErrorReason::InvalidLength(0xffff_ffff)
+            ))
+        );
+
+        // the elements of an int64 list start at the next multiple of eight
+        let buf = [VALUE_INT64LIST, 1, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(
+            CODEC.decode_message(&buf),
+            Err(DecodeError::new(1, DecodeErrorReason::InvalidLength(1)))
+        );
+    }
+
+    #[test]
+    fn test_deep_nesting_is_rejected() {
+        let mut buf = Vec::new();
+        for _ in 0..=MAX_DEPTH {
+            buf.extend_from_slice(&[VALUE_LIST, 1]);
+        }
+        buf.push(VALUE_NULL);
+        assert_eq!(
+            CODEC.decode_message(&buf).map_err(|err| err.reason),
+            Err(DecodeErrorReason::NestingTooDeep)
+        );
+
+        let buf = &buf[2..];
+        assert!(CODEC.decode_message(buf).is_ok());
+    }
+
+    #[test]
+    fn test_map_keys_can_be_any_value() {
+        let mut map = ValueMap::new();
+        map.insert("b", Value::I32(1));
+        map.insert(Value::I32(3), Value::Null);
+        map.insert(Value::List(vec![Value::F64(f64::NAN)]), Value::I32(2));
+        map.insert("a", Value::I32(4));
+        let buf = CODEC.encode_message(&Value::Map(map.clone()));
+
+        let decoded = match CODEC.decode_message(&buf) {
+            Ok(Value::Map(decoded)) => decoded,
+            _ => panic!("Expected a map"),
+        };
+        assert_eq!(decoded, map);
+        assert!(decoded.keys().eq(map.keys()));
+        assert_eq!(
+            decoded.get(&Value::List(vec![Value::F64(-f64::NAN)])),
+            Some(&Value::I32(2))
+        );
+    }
+
+    #[test]
+    fn test_float32_list() {
+        let value = Value::F32List(vec![1.5, -0.25]);
+        let buf = CODEC.encode_message(&value);
+        let mut expected = vec![VALUE_FLOAT32LIST, 2, 0, 0];
+        expected.extend_from_slice(&1.5f32.to_ne_bytes());
+        expected.extend_from_slice(&(-0.25f32).to_ne_bytes());
+        assert_eq!(buf, expected);
+        assert_eq!(CODEC.decode_message(&buf), Ok(value));
+    }
+
+    #[test]
+    fn test_extension_reads_custom_type() {
+        let point = Value::Custom(129, Box::new(Value::F64List(vec![1.0, 2.0])));
+        let buf = extended().encode_message(&Value::List(vec![point.clone()]));
+        assert_eq!(buf.len(), 24);
+        assert_eq!(
+            extended().decode_message(&buf),
+            Ok(Value::List(vec![point]))
+        );
+
+        // the default codec does not know the type
+        assert_eq!(
+            CODEC.decode_message(&buf),
+            Err(DecodeError::new(2, DecodeErrorReason::UnknownType(129)))
+        );
+    }
+}
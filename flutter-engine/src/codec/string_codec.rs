@@ -1,6 +1,7 @@
 use log::error;
 
 use super::{MessageCodec, Value};
+use crate::error::{DecodeError, DecodeErrorReason};
 
 pub struct StringCodec;
 
@@ -18,10 +19,13 @@ impl MessageCodec for StringCodec {
         }
     }
 
-    fn decode_message(&self, buf: &[u8]) -> Option<Value> {
-        unsafe {
-            let s = std::str::from_utf8_unchecked(buf);
-            Some(Value::String(s.to_owned()))
+    fn decode_message(&self, buf: &[u8]) -> Result<Value, DecodeError> {
+        match std::str::from_utf8(buf) {
+            Ok(s) => Ok(Value::String(s.to_owned())),
+            Err(err) => Err(DecodeError::new(
+                err.valid_up_to(),
+                DecodeErrorReason::InvalidUtf8,
+            )),
         }
     }
 }
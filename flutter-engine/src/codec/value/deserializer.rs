@@ -1,10 +1,8 @@
-use std::collections::hash_map::Keys;
-
 use serde::{de, de::IntoDeserializer, forward_to_deserialize_any};
 
 use crate::error::ValueError;
 
-use super::Value;
+use super::{Iter, Value};
 
 type Result<T> = std::result::Result<T, ValueError>;
 
@@ -37,14 +35,17 @@ impl<'a, 'de> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             Value::Boolean(b) => visitor.visit_bool(*b),
             Value::I32(i) => visitor.visit_i32(*i),
             Value::I64(i) => visitor.visit_i64(*i),
+            Value::LargeInt(s) => visitor.visit_str(s.as_str()),
             Value::F64(f) => visitor.visit_f64(*f),
             Value::String(s) => visitor.visit_str(s.as_str()),
             Value::U8List(_) => visitor.visit_seq(SeqAccess::new(self)),
             Value::I32List(_) => visitor.visit_seq(SeqAccess::new(self)),
             Value::I64List(_) => visitor.visit_seq(SeqAccess::new(self)),
+            Value::F32List(_) => visitor.visit_seq(SeqAccess::new(self)),
             Value::F64List(_) => visitor.visit_seq(SeqAccess::new(self)),
             Value::List(_) => visitor.visit_seq(SeqAccess::new(self)),
             Value::Map(_) => visitor.visit_map(MapAccess::new(self)),
+            Value::Custom(_, value) => (&mut Deserializer::new(value)).deserialize_any(visitor),
         }
     }
 
@@ -142,6 +143,15 @@ impl<'a, 'de> de::SeqAccess<'de> for SeqAccess<'a, 'de> {
                     seed.deserialize(vec[self.index - 1].into_deserializer())?,
                 ))
             }
+            Value::F32List(vec) => {
+                if vec.len() <= self.index {
+                    return Ok(None);
+                }
+                self.index += 1;
+                Ok(Some(
+                    seed.deserialize(vec[self.index - 1].into_deserializer())?,
+                ))
+            }
             Value::F64List(vec) => {
                 if vec.len() <= self.index {
                     return Ok(None);
@@ -165,13 +175,12 @@ impl<'a, 'de> de::SeqAccess<'de> for SeqAccess<'a, 'de> {
     }
 }
 
-struct MapAccess<'a, 'de> {
-    de: &'a mut Deserializer<'de>,
-    key_iter: Keys<'de, String, Value>,
+struct MapAccess<'de> {
+    entry_iter: Iter<'de>,
     next_value: Option<&'de Value>,
 }
 
-impl<'a, 'de> MapAccess<'a, 'de> {
+impl<'a, 'de> MapAccess<'de> {
     pub fn new(de: &'a mut Deserializer<'de>) -> Self {
         let map = if let Value::Map(map) = de.value {
             map
@@ -179,32 +188,26 @@ impl<'a, 'de> MapAccess<'a, 'de> {
             panic!("deserializer must have a map");
         };
         Self {
-            de,
-            key_iter: map.keys(),
+            entry_iter: map.iter(),
             next_value: None,
         }
     }
 }
 
-impl<'a, 'de> de::MapAccess<'de> for MapAccess<'a, 'de> {
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
     type Error = ValueError;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
     where
         K: de::DeserializeSeed<'de>,
     {
-        match self.de.value {
-            Value::Map(map) => {
-                let next = if let Some(k) = self.key_iter.next() {
-                    k
-                } else {
-                    return Ok(None);
-                };
-                self.next_value.replace(map.get(next).unwrap());
-                Ok(Some(seed.deserialize(next.as_str().into_deserializer())?))
-            }
-            _ => Err(ValueError::NoMap),
-        }
+        let (key, value) = if let Some(entry) = self.entry_iter.next() {
+            entry
+        } else {
+            return Ok(None);
+        };
+        self.next_value.replace(value);
+        Ok(Some(seed.deserialize(&mut Deserializer::new(key))?))
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
@@ -216,7 +219,7 @@ impl<'a, 'de> de::MapAccess<'de> for MapAccess<'a, 'de> {
 }
 
 struct EnumAccess<'de> {
-    name: &'de String,
+    name: &'de Value,
     value_deserializer: Deserializer<'de>,
 }
 
@@ -246,7 +249,7 @@ impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        let val = seed.deserialize(self.name.clone().into_deserializer())?;
+        let val = seed.deserialize(&mut Deserializer::new(self.name))?;
         Ok((val, self))
     }
 }
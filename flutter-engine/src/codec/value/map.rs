@@ -0,0 +1,194 @@
+use std::collections::{hash_map::DefaultHasher, HashMap};
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::mem;
+
+use indexmap::{map, Equivalent, IndexMap};
+
+use super::Value;
+
+/// The map of [`Value::Map`].
+///
+/// Keys can be any value, like in the maps of dart, and entries keep the
+/// order they were inserted in. Two maps are equal if they have the same
+/// entries in any order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValueMap(IndexMap<Value, Value>);
+
+impl ValueMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(IndexMap::with_capacity(capacity))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    /// Gets the value of a string key.
+    pub fn get_str(&self, key: &str) -> Option<&Value> {
+        self.0.get(&StrKey(key))
+    }
+
+    pub fn get_mut(&mut self, key: &Value) -> Option<&mut Value> {
+        self.0.get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Inserts an entry and returns the previous value of the key. A new key
+    /// is added at the end, an existing key keeps its position.
+    pub fn insert<K, V>(&mut self, key: K, value: V) -> Option<Value>
+    where
+        K: Into<Value>,
+        V: Into<Value>,
+    {
+        self.0.insert(key.into(), value.into())
+    }
+
+    /// Removes an entry and keeps the order of the others.
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        self.0.shift_remove(key)
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(self.0.iter())
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.0.keys()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.0.values()
+    }
+}
+
+/// A `&str` that looks up a [`Value::String`] key without allocating one.
+struct StrKey<'a>(&'a str);
+
+impl Hash for StrKey<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Must match the hash of `Value::String`.
+        mem::discriminant(&Value::String(String::new())).hash(state);
+        self.0.hash(state);
+    }
+}
+
+impl Equivalent<Value> for StrKey<'_> {
+    fn equivalent(&self, key: &Value) -> bool {
+        matches!(key, Value::String(s) if s == self.0)
+    }
+}
+
+impl Hash for ValueMap {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // the entries are combined independent of their order, like equality
+        let mut entries: u64 = 0;
+        for entry in &self.0 {
+            let mut hasher = DefaultHasher::new();
+            entry.hash(&mut hasher);
+            entries = entries.wrapping_add(hasher.finish());
+        }
+        self.len().hash(state);
+        entries.hash(state);
+    }
+}
+
+impl From<HashMap<String, Value>> for ValueMap {
+    fn from(map: HashMap<String, Value>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<K: Into<Value>> FromIterator<(K, Value)> for ValueMap {
+    fn from_iter<I: IntoIterator<Item = (K, Value)>>(iter: I) -> Self {
+        Self(iter.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+}
+
+impl<K: Into<Value>> Extend<(K, Value)> for ValueMap {
+    fn extend<I: IntoIterator<Item = (K, Value)>>(&mut self, iter: I) {
+        self.0.extend(iter.into_iter().map(|(k, v)| (k.into(), v)));
+    }
+}
+
+impl IntoIterator for ValueMap {
+    type Item = (Value, Value);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter(self.0.into_iter())
+    }
+}
+
+impl<'a> IntoIterator for &'a ValueMap {
+    type Item = (&'a Value, &'a Value);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// The entries of a [`ValueMap`] in order.
+pub struct Iter<'a>(map::Iter<'a, Value, Value>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Value, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// The entries of a [`ValueMap`] in order.
+pub struct IntoIter(map::IntoIter<Value, Value>);
+
+impl Iterator for IntoIter {
+    type Item = (Value, Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_str_finds_only_string_keys() {
+        let mut map = ValueMap::new();
+        map.insert(Value::I32(1), Value::Boolean(false));
+        map.insert("key", Value::Boolean(true));
+        assert_eq!(map.get_str("key"), Some(&Value::Boolean(true)));
+        assert_eq!(map.get_str("1"), None);
+        assert_eq!(map.get_str("other"), None);
+    }
+}
@@ -1,27 +1,134 @@
 use crate::error::MethodCallError;
-use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::hash::{Hash, Hasher};
+use std::mem;
 
 use serde::{de, ser, Deserialize, Serialize};
 
 pub use self::deserializer::{from_value, Deserializer};
+pub use self::map::{IntoIter, Iter, ValueMap};
 
 mod deserializer;
+mod map;
 
-#[derive(Clone, Debug, PartialEq)]
+/// A value sent over a channel.
+///
+/// Floats are canonicalised before they are compared or hashed so values can
+/// be map keys: all NaNs are equal to each other and so are `0.0` and `-0.0`.
+#[derive(Clone, Debug)]
 pub enum Value {
     Null,
     Boolean(bool),
     I32(i32),
     I64(i64),
+    /// An integer that does not fit in 64 bits, as hexadecimal digits.
+    LargeInt(String),
     F64(f64),
     String(String),
     U8List(Vec<u8>),
     I32List(Vec<i32>),
     I64List(Vec<i64>),
+    F32List(Vec<f32>),
     F64List(Vec<f64>),
     List(Vec<Value>),
-    Map(HashMap<String, Value>),
+    Map(ValueMap),
+    /// A value with a custom type code of the standard codec, see
+    /// [`StandardCodecExtension`](super::standard_codec::StandardCodecExtension).
+    Custom(u8, Box<Value>),
+}
+
+/// The bits of a float, the same for all NaNs and both zeros.
+fn f32_bits(f: f32) -> u32 {
+    if f.is_nan() {
+        f32::NAN.to_bits()
+    } else if f == 0.0 {
+        0
+    } else {
+        f.to_bits()
+    }
+}
+
+fn f64_bits(f: f64) -> u64 {
+    if f.is_nan() {
+        f64::NAN.to_bits()
+    } else if f == 0.0 {
+        0
+    } else {
+        f.to_bits()
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::I32(a), Value::I32(b)) => a == b,
+            (Value::I64(a), Value::I64(b)) => a == b,
+            (Value::LargeInt(a), Value::LargeInt(b)) => a == b,
+            (Value::F64(a), Value::F64(b)) => f64_bits(*a) == f64_bits(*b),
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::U8List(a), Value::U8List(b)) => a == b,
+            (Value::I32List(a), Value::I32List(b)) => a == b,
+            (Value::I64List(a), Value::I64List(b)) => a == b,
+            (Value::F32List(a), Value::F32List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| f32_bits(*a) == f32_bits(*b))
+            }
+            (Value::F64List(a), Value::F64List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| f64_bits(*a) == f64_bits(*b))
+            }
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Custom(a_type, a), Value::Custom(b_type, b)) => a_type == b_type && a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Value::Null => {}
+            Value::Boolean(b) => b.hash(state),
+            Value::I32(i) => i.hash(state),
+            Value::I64(i) => i.hash(state),
+            Value::LargeInt(s) => s.hash(state),
+            Value::F64(f) => f64_bits(*f).hash(state),
+            Value::String(s) => s.hash(state),
+            Value::U8List(vec) => vec.hash(state),
+            Value::I32List(vec) => vec.hash(state),
+            Value::I64List(vec) => vec.hash(state),
+            Value::F32List(vec) => {
+                vec.len().hash(state);
+                vec.iter().for_each(|f| f32_bits(*f).hash(state));
+            }
+            Value::F64List(vec) => {
+                vec.len().hash(state);
+                vec.iter().for_each(|f| f64_bits(*f).hash(state));
+            }
+            Value::List(vec) => vec.hash(state),
+            Value::Map(map) => map.hash(state),
+            Value::Custom(type_code, value) => {
+                type_code.hash(state);
+                value.hash(state);
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
 }
 
 impl Serialize for Value {
@@ -35,11 +142,13 @@ impl Serialize for Value {
             Value::Boolean(b) => serializer.serialize_bool(*b),
             Value::I32(i) => serializer.serialize_i64(i64::from(*i)),
             Value::I64(i) => serializer.serialize_i64(*i),
+            Value::LargeInt(s) => serializer.serialize_str(s.as_str()),
             Value::F64(f) => serializer.serialize_f64(*f),
             Value::String(s) => serializer.serialize_str(s.as_str()),
             Value::U8List(vec) => vec.serialize(serializer),
             Value::I32List(vec) => vec.serialize(serializer),
             Value::I64List(vec) => vec.serialize(serializer),
+            Value::F32List(vec) => vec.serialize(serializer),
             Value::F64List(vec) => vec.serialize(serializer),
             Value::List(vec) => vec.serialize(serializer),
             Value::Map(m) => {
@@ -51,6 +160,7 @@ impl Serialize for Value {
                 }
                 map.end()
             }
+            Value::Custom(_, value) => value.serialize(serializer),
         }
     }
 }
@@ -143,8 +253,8 @@ impl<'de> Deserialize<'de> for Value {
             where
                 V: de::MapAccess<'de>,
             {
-                let mut map = HashMap::new();
-                while let Some((k, v)) = visitor.next_entry()? {
+                let mut map = ValueMap::new();
+                while let Some((k, v)) = visitor.next_entry::<Value, Value>()? {
                     map.insert(k, v);
                 }
                 Ok(Value::Map(map))
@@ -209,9 +319,9 @@ impl TryFrom<serde_json::Value> for Value {
                 new_vec
             })),
             serde_json::Value::Object(map) => Ok(Value::Map({
-                let mut new_map = HashMap::new();
+                let mut new_map = ValueMap::with_capacity(map.len());
                 for (k, v) in map {
-                    new_map.insert(k, v.try_into()?);
+                    new_map.insert(k, Value::try_from(v)?);
                 }
                 new_map
             })),
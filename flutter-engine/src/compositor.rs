@@ -0,0 +1,322 @@
+//! Custom composition of flutter layers.
+//!
+//! By default the engine renders everything into the surface provided by the
+//! renderer callbacks. When a [`Compositor`] is set, the engine instead asks
+//! for a backing store per layer and hands the embedder the final list of
+//! layers, interleaved with the platform views of the app.
+
+use crate::ffi::{Rect, RoundedRect, Transformation};
+use flutter_engine_sys::{
+    FlutterBackingStore, FlutterBackingStoreType, FlutterLayer, FlutterLayerContentType,
+    FlutterOpenGLTargetType, FlutterPlatformView, FlutterPlatformViewMutationType,
+};
+use std::os::raw::c_void;
+use std::{mem, ptr, slice};
+
+pub type PlatformViewId = i64;
+
+pub trait Compositor {
+    /// Called by the engine to obtain a render target for a layer. The size is
+    /// in physical pixels.
+    fn create_backing_store(&self, size: (f64, f64)) -> Option<BackingStore>;
+
+    /// Called when the engine no longer needs a backing store.
+    fn collect_backing_store(&self, _backing_store: BackingStore) -> bool {
+        true
+    }
+
+    /// Called with the layers of a frame, ordered from bottom to top.
+    fn present_layers(&self, layers: &[Layer]) -> bool;
+}
+
+/// A render target the engine draws a layer into.
+#[derive(Debug)]
+pub enum BackingStore {
+    OpenGLTexture {
+        target: u32,
+        name: u32,
+        format: u32,
+        width: usize,
+        height: usize,
+    },
+    OpenGLFramebuffer {
+        target: u32,
+        name: u32,
+    },
+    Software {
+        pixels: Vec<u8>,
+        row_bytes: usize,
+        height: usize,
+    },
+}
+
+impl BackingStore {
+    /// Allocates a zeroed software backing store with four bytes per pixel.
+    ///
+    /// The engine draws into it in the native 32-bit color type of Skia, like
+    /// a [`SoftwareFrame`](crate::ffi::SoftwareFrame), so the bytes are BGRA
+    /// on little-endian desktop platforms.
+    pub fn software(width: usize, height: usize) -> Self {
+        let row_bytes = width * 4;
+        BackingStore::Software {
+            pixels: vec![0; row_bytes * height],
+            row_bytes,
+            height,
+        }
+    }
+
+    /// Moves the backing store to the heap and describes it to the engine.
+    ///
+    /// The backing store is owned by `target.user_data` until it is handed
+    /// back by [`from_ffi`](Self::from_ffi).
+    pub(crate) fn into_ffi(self, target: &mut FlutterBackingStore) {
+        let store = Box::into_raw(Box::new(self));
+        target.struct_size = mem::size_of::<FlutterBackingStore>();
+        target.user_data = store as *mut c_void;
+        unsafe {
+            match &mut *store {
+                BackingStore::OpenGLTexture {
+                    target: gl_target,
+                    name,
+                    format,
+                    width,
+                    height,
+                } => {
+                    target.type_ = FlutterBackingStoreType::kFlutterBackingStoreTypeOpenGL;
+                    let open_gl = &mut target.__bindgen_anon_1.open_gl;
+                    open_gl.type_ = FlutterOpenGLTargetType::kFlutterOpenGLTargetTypeTexture;
+                    let texture = &mut open_gl.__bindgen_anon_1.texture;
+                    texture.target = *gl_target;
+                    texture.name = *name;
+                    texture.format = *format;
+                    texture.width = *width;
+                    texture.height = *height;
+                    texture.user_data = ptr::null_mut();
+                    texture.destruction_callback = None;
+                }
+                BackingStore::OpenGLFramebuffer {
+                    target: gl_target,
+                    name,
+                } => {
+                    target.type_ = FlutterBackingStoreType::kFlutterBackingStoreTypeOpenGL;
+                    let open_gl = &mut target.__bindgen_anon_1.open_gl;
+                    open_gl.type_ = FlutterOpenGLTargetType::kFlutterOpenGLTargetTypeFramebuffer;
+                    let framebuffer = &mut open_gl.__bindgen_anon_1.framebuffer;
+                    framebuffer.target = *gl_target;
+                    framebuffer.name = *name;
+                    framebuffer.user_data = ptr::null_mut();
+                    framebuffer.destruction_callback = None;
+                }
+                BackingStore::Software {
+                    pixels,
+                    row_bytes,
+                    height,
+                } => {
+                    target.type_ = FlutterBackingStoreType::kFlutterBackingStoreTypeSoftware;
+                    let software = &mut target.__bindgen_anon_1.software;
+                    software.allocation = pixels.as_mut_ptr() as *const c_void;
+                    software.row_bytes = *row_bytes;
+                    software.height = *height;
+                    software.user_data = ptr::null_mut();
+                    software.destruction_callback = None;
+                }
+            }
+        }
+    }
+
+    /// Takes back ownership of a backing store created by `into_ffi`.
+    pub(crate) unsafe fn from_ffi(backing_store: &FlutterBackingStore) -> Self {
+        *Box::from_raw(backing_store.user_data as *mut BackingStore)
+    }
+
+    unsafe fn from_ffi_ref<'a>(backing_store: &FlutterBackingStore) -> &'a Self {
+        &*(backing_store.user_data as *const BackingStore)
+    }
+}
+
+#[derive(Debug)]
+pub struct Layer<'a> {
+    pub content: LayerContent<'a>,
+    /// Offset of the layer from the top left of the root surface in physical
+    /// pixels.
+    pub offset: (f64, f64),
+    /// Size of the layer in physical pixels.
+    pub size: (f64, f64),
+}
+
+#[derive(Debug)]
+pub enum LayerContent<'a> {
+    /// Contents rendered by flutter.
+    BackingStore {
+        backing_store: &'a BackingStore,
+        /// Whether the backing store changed since it was last presented.
+        did_update: bool,
+    },
+    /// Contents rendered by the embedder.
+    PlatformView(PlatformView),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlatformView {
+    pub identifier: PlatformViewId,
+    /// Mutations to apply, in order, before compositing the view.
+    pub mutations: Vec<PlatformViewMutation>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlatformViewMutation {
+    Opacity(f64),
+    ClipRect(Rect),
+    ClipRoundedRect(RoundedRect),
+    Transformation(Transformation),
+}
+
+impl<'a> Layer<'a> {
+    pub(crate) unsafe fn from_ffi(layer: &FlutterLayer) -> Self {
+        let content = match layer.type_ {
+            FlutterLayerContentType::kFlutterLayerContentTypeBackingStore => {
+                let backing_store = &*layer.__bindgen_anon_1.backing_store;
+                LayerContent::BackingStore {
+                    backing_store: BackingStore::from_ffi_ref(backing_store),
+                    did_update: backing_store.did_update,
+                }
+            }
+            FlutterLayerContentType::kFlutterLayerContentTypePlatformView => {
+                LayerContent::PlatformView(PlatformView::from_ffi(
+                    &*layer.__bindgen_anon_1.platform_view,
+                ))
+            }
+        };
+        Self {
+            content,
+            offset: (layer.offset.x, layer.offset.y),
+            size: (layer.size.width, layer.size.height),
+        }
+    }
+}
+
+impl PlatformView {
+    unsafe fn from_ffi(view: &FlutterPlatformView) -> Self {
+        let mutations = if view.mutations.is_null() {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(view.mutations, view.mutations_count)
+                .iter()
+                .map(|mutation| {
+                    let mutation = &**mutation;
+                    let data = &mutation.__bindgen_anon_1;
+                    match mutation.type_ {
+                        FlutterPlatformViewMutationType::kFlutterPlatformViewMutationTypeOpacity => {
+                            PlatformViewMutation::Opacity(data.opacity)
+                        }
+                        FlutterPlatformViewMutationType::kFlutterPlatformViewMutationTypeClipRect => {
+                            PlatformViewMutation::ClipRect(data.clip_rect.into())
+                        }
+                        FlutterPlatformViewMutationType::kFlutterPlatformViewMutationTypeClipRoundedRect => {
+                            PlatformViewMutation::ClipRoundedRect(data.clip_rounded_rect.into())
+                        }
+                        FlutterPlatformViewMutationType::kFlutterPlatformViewMutationTypeTransformation => {
+                            PlatformViewMutation::Transformation(data.transformation.into())
+                        }
+                    }
+                })
+                .collect()
+        };
+        Self {
+            identifier: view.identifier,
+            mutations,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_engine, MockHandler};
+    use crate::{FlutterEngine, FlutterEngineHandler};
+    use flutter_engine_sys::{FlutterLayer__bindgen_ty_1, FlutterPoint, FlutterSize};
+    use parking_lot::Mutex;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Weak};
+
+    #[derive(Default)]
+    struct TestCompositor {
+        collected: Mutex<usize>,
+        presented: Mutex<Vec<String>>,
+    }
+
+    impl Compositor for TestCompositor {
+        fn create_backing_store(&self, (width, height): (f64, f64)) -> Option<BackingStore> {
+            Some(BackingStore::software(width as usize, height as usize))
+        }
+
+        fn collect_backing_store(&self, _backing_store: BackingStore) -> bool {
+            *self.collected.lock() += 1;
+            true
+        }
+
+        fn present_layers(&self, layers: &[Layer]) -> bool {
+            let mut presented = self.presented.lock();
+            for layer in layers {
+                presented.push(match &layer.content {
+                    LayerContent::BackingStore { backing_store, .. } => match backing_store {
+                        BackingStore::Software { pixels, .. } => format!("store {}", pixels.len()),
+                        _ => "store".to_owned(),
+                    },
+                    LayerContent::PlatformView(view) => format!("view {}", view.identifier),
+                });
+            }
+            true
+        }
+    }
+
+    #[test]
+    fn test_compositor_callbacks() {
+        let handler = MockHandler::new();
+        let compositor = Arc::new(TestCompositor::default());
+        let engine = FlutterEngine::new(
+            Arc::downgrade(&handler) as Weak<dyn FlutterEngineHandler>,
+            PathBuf::from("assets"),
+        );
+        engine.set_compositor(Arc::downgrade(&compositor) as Weak<dyn Compositor>);
+        engine.run(&[]).unwrap();
+        let mock = mock_engine(&engine);
+
+        let backing_store = mock.create_backing_store(2.0, 3.0).unwrap();
+        let platform_view = FlutterPlatformView {
+            struct_size: mem::size_of::<FlutterPlatformView>(),
+            identifier: 7,
+            mutations_count: 0,
+            mutations: ptr::null_mut(),
+        };
+        let layer = |content, type_| FlutterLayer {
+            struct_size: mem::size_of::<FlutterLayer>(),
+            type_,
+            __bindgen_anon_1: content,
+            offset: FlutterPoint { x: 0.0, y: 0.0 },
+            size: FlutterSize {
+                width: 2.0,
+                height: 3.0,
+            },
+        };
+        let layers = [
+            layer(
+                FlutterLayer__bindgen_ty_1 {
+                    backing_store: &backing_store,
+                },
+                FlutterLayerContentType::kFlutterLayerContentTypeBackingStore,
+            ),
+            layer(
+                FlutterLayer__bindgen_ty_1 {
+                    platform_view: &platform_view,
+                },
+                FlutterLayerContentType::kFlutterLayerContentTypePlatformView,
+            ),
+        ];
+        assert!(mock.present_layers(&layers));
+        assert_eq!(*compositor.presented.lock(), vec!["store 24", "view 7"]);
+
+        assert!(mock.collect_backing_store(&backing_store));
+        assert_eq!(*compositor.collected.lock(), 1);
+    }
+}
@@ -120,6 +120,8 @@ pub enum MethodCallError {
         message: String,
         details: Value,
     },
+    /// Dart did not answer a method call in time.
+    Timeout,
     UnspecifiedError,
 }
 
@@ -163,6 +165,7 @@ impl fmt::Display for MethodCallError {
                 message,
                 details,
             } => write!(f, "{} ({})\ndetails: {:?}", message, code, details),
+            MethodCallError::Timeout => write!(f, "method call timed out"),
             MethodCallError::UnspecifiedError => write!(f, "unspecified error"),
         }
     }
@@ -199,6 +202,58 @@ impl Into<MethodCallResult> for MethodCallError {
     }
 }
 
+/// Why a message could not be decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeErrorReason {
+    UnexpectedEnd,
+    InvalidUtf8,
+    UnknownType(u8),
+    InvalidLength(usize),
+    NestingTooDeep,
+    InvalidMethodCall,
+    InvalidEnvelope,
+    Json(String),
+}
+
+impl fmt::Display for DecodeErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeErrorReason::UnexpectedEnd => write!(f, "unexpected end of message"),
+            DecodeErrorReason::InvalidUtf8 => write!(f, "invalid utf-8"),
+            DecodeErrorReason::UnknownType(t) => write!(f, "unknown type {}", t),
+            DecodeErrorReason::InvalidLength(len) => {
+                write!(f, "length {} exceeds the message", len)
+            }
+            DecodeErrorReason::NestingTooDeep => write!(f, "values are nested too deep"),
+            DecodeErrorReason::InvalidMethodCall => write!(f, "invalid method call"),
+            DecodeErrorReason::InvalidEnvelope => write!(f, "invalid envelope"),
+            DecodeErrorReason::Json(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// A message received on a channel could not be decoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DecodeError {
+    /// The byte offset in the message where decoding failed.
+    pub position: usize,
+    pub reason: DecodeErrorReason,
+}
+
+impl DecodeError {
+    pub fn new(position: usize, reason: DecodeErrorReason) -> Self {
+        Self { position, reason }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.reason, self.position)
+    }
+}
+
+impl error::Error for DecodeError {}
+
 #[derive(Debug)]
 pub enum ValueError {
     Message(String),
@@ -1,21 +1,42 @@
 use flutter_engine_sys::{FlutterPlatformMessage, FlutterPlatformMessageResponseHandle};
 use log::error;
 use std::borrow::Cow;
-use std::ffi::{CStr, CString};
+use std::ffi::CStr;
+use std::os::raw::c_void;
 use std::{mem, ptr};
 
 #[derive(Debug)]
 pub struct PlatformMessageResponseHandle {
     handle: *const FlutterPlatformMessageResponseHandle,
+    replayed: bool,
 }
 
 unsafe impl Send for PlatformMessageResponseHandle {}
 
 unsafe impl Sync for PlatformMessageResponseHandle {}
 
+impl PlatformMessageResponseHandle {
+    /// The handle of a message replayed by a
+    /// [`Replayer`](crate::channel::Replayer). Its response only reaches the
+    /// interceptors.
+    pub(crate) fn replayed() -> Self {
+        Self {
+            handle: ptr::null(),
+            replayed: true,
+        }
+    }
+
+    pub(crate) fn is_replayed(&self) -> bool {
+        self.replayed
+    }
+}
+
 impl Into<PlatformMessageResponseHandle> for *const FlutterPlatformMessageResponseHandle {
     fn into(self) -> PlatformMessageResponseHandle {
-        PlatformMessageResponseHandle { handle: self }
+        PlatformMessageResponseHandle {
+            handle: self,
+            replayed: false,
+        }
     }
 }
 
@@ -42,11 +63,14 @@ pub struct PlatformMessage<'a, 'b> {
     pub response_handle: Option<PlatformMessageResponseHandle>,
 }
 
-impl<'a, 'b> Into<FlutterPlatformMessage> for PlatformMessage<'a, 'b> {
-    fn into(mut self) -> FlutterPlatformMessage {
+impl<'a, 'b> PlatformMessage<'a, 'b> {
+    /// Converts the message for the embedder API. The result points into
+    /// `channel`, which has to be the channel of this message and outlive
+    /// the call it is passed to.
+    pub(crate) fn into_ffi(mut self, channel: &CStr) -> FlutterPlatformMessage {
         FlutterPlatformMessage {
             struct_size: mem::size_of::<FlutterPlatformMessage>(),
-            channel: CString::new(&*self.channel).unwrap().into_raw(),
+            channel: channel.as_ptr(),
             message: self.message.as_ptr(),
             message_size: self.message.len(),
             response_handle: self.response_handle.take().map_or(ptr::null(), Into::into),
@@ -78,6 +102,64 @@ impl<'a, 'b> From<FlutterPlatformMessage> for PlatformMessage<'a, 'b> {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FlutterRendererType {
+    OpenGL,
+    Software,
+}
+
+impl From<FlutterRendererType> for flutter_engine_sys::FlutterRendererType {
+    fn from(renderer_type: FlutterRendererType) -> Self {
+        match renderer_type {
+            FlutterRendererType::OpenGL => flutter_engine_sys::FlutterRendererType::kOpenGL,
+            FlutterRendererType::Software => flutter_engine_sys::FlutterRendererType::kSoftware,
+        }
+    }
+}
+
+/// A frame rendered by the software renderer.
+///
+/// The pixels use four bytes each in the native 32-bit color type of Skia
+/// (`kN32_SkColorType`), which is BGRA on little-endian desktop platforms.
+/// They are only valid for the duration of the present callback. Use `to_vec`
+/// to keep them.
+#[derive(Debug)]
+pub struct SoftwareFrame<'a> {
+    pub pixels: &'a [u8],
+    pub row_bytes: usize,
+    pub height: usize,
+}
+
+impl<'a> SoftwareFrame<'a> {
+    pub(crate) unsafe fn from_raw(
+        allocation: *const c_void,
+        row_bytes: usize,
+        height: usize,
+    ) -> Self {
+        let pixels = if allocation.is_null() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(allocation as *const u8, row_bytes * height)
+        };
+        Self {
+            pixels,
+            row_bytes,
+            height,
+        }
+    }
+
+    /// Pixels per row including the padding at the end of a row. The visible
+    /// width is the width of the window metrics sent to the engine.
+    pub fn stride_pixels(&self) -> usize {
+        self.row_bytes / 4
+    }
+
+    /// Copies the pixels out of the engine owned buffer.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.pixels.to_vec()
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum FlutterPointerPhase {
     Cancel,
@@ -171,3 +253,90 @@ impl From<FlutterPointerMouseButtons> for flutter_engine_sys::FlutterPointerMous
         }
     }
 }
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl From<flutter_engine_sys::FlutterRect> for Rect {
+    fn from(rect: flutter_engine_sys::FlutterRect) -> Self {
+        Self {
+            left: rect.left,
+            top: rect.top,
+            right: rect.right,
+            bottom: rect.bottom,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RoundedRect {
+    pub rect: Rect,
+    pub upper_left_corner_radius: (f64, f64),
+    pub upper_right_corner_radius: (f64, f64),
+    pub lower_right_corner_radius: (f64, f64),
+    pub lower_left_corner_radius: (f64, f64),
+}
+
+impl From<flutter_engine_sys::FlutterRoundedRect> for RoundedRect {
+    fn from(rect: flutter_engine_sys::FlutterRoundedRect) -> Self {
+        let radius = |size: flutter_engine_sys::FlutterSize| (size.width, size.height);
+        Self {
+            rect: rect.rect.into(),
+            upper_left_corner_radius: radius(rect.upper_left_corner_radius),
+            upper_right_corner_radius: radius(rect.upper_right_corner_radius),
+            lower_right_corner_radius: radius(rect.lower_right_corner_radius),
+            lower_left_corner_radius: radius(rect.lower_left_corner_radius),
+        }
+    }
+}
+
+/// A 3x3 transformation matrix in the same layout as `SkMatrix`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transformation {
+    pub scale_x: f64,
+    pub skew_x: f64,
+    pub trans_x: f64,
+    pub skew_y: f64,
+    pub scale_y: f64,
+    pub trans_y: f64,
+    pub pers0: f64,
+    pub pers1: f64,
+    pub pers2: f64,
+}
+
+impl Default for Transformation {
+    fn default() -> Self {
+        Self {
+            scale_x: 1.0,
+            skew_x: 0.0,
+            trans_x: 0.0,
+            skew_y: 0.0,
+            scale_y: 1.0,
+            trans_y: 0.0,
+            pers0: 0.0,
+            pers1: 0.0,
+            pers2: 1.0,
+        }
+    }
+}
+
+impl From<flutter_engine_sys::FlutterTransformation> for Transformation {
+    fn from(t: flutter_engine_sys::FlutterTransformation) -> Self {
+        Self {
+            scale_x: t.scaleX,
+            skew_x: t.skewX,
+            trans_x: t.transX,
+            skew_y: t.skewY,
+            scale_y: t.scaleY,
+            trans_y: t.transY,
+            pers0: t.pers0,
+            pers1: t.pers1,
+            pers2: t.pers2,
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use crate::compositor::{BackingStore, Compositor, Layer};
+use crate::ffi::SoftwareFrame;
 use crate::tasks::{TaskRunner, TaskRunnerInner};
+use crate::vsync::VsyncRequest;
 use crate::{FlutterEngineHandler, FlutterEngineInner};
 use log::trace;
 use parking_lot::Mutex;
@@ -11,6 +14,15 @@ unsafe fn get_handler(user_data: *mut c_void) -> Option<Arc<dyn FlutterEngineHan
     engine.handler.upgrade()
 }
 
+#[inline]
+unsafe fn get_compositor(user_data: *mut c_void) -> Option<Arc<dyn Compositor>> {
+    let engine = &*(user_data as *const FlutterEngineInner);
+    let compositor = engine.compositor.read();
+    compositor
+        .as_ref()
+        .and_then(|compositor| compositor.upgrade())
+}
+
 pub extern "C" fn present(user_data: *mut c_void) -> bool {
     trace!("present");
     unsafe {
@@ -77,6 +89,23 @@ pub extern "C" fn gl_proc_resolver(user_data: *mut c_void, proc: *const c_char)
     }
 }
 
+pub extern "C" fn surface_present(
+    user_data: *mut c_void,
+    allocation: *const c_void,
+    row_bytes: usize,
+    height: usize,
+) -> bool {
+    trace!("surface_present");
+    unsafe {
+        if let Some(handler) = get_handler(user_data) {
+            let frame = SoftwareFrame::from_raw(allocation, row_bytes, height);
+            handler.present_software_frame(&frame)
+        } else {
+            false
+        }
+    }
+}
+
 pub extern "C" fn platform_message_callback(
     platform_message: *const flutter_engine_sys::FlutterPlatformMessage,
     user_data: *mut c_void,
@@ -107,6 +136,95 @@ pub extern "C" fn runs_task_on_current_thread(user_data: *mut c_void) -> bool {
     }
 }
 
+pub extern "C" fn create_backing_store(
+    config: *const flutter_engine_sys::FlutterBackingStoreConfig,
+    backing_store_out: *mut flutter_engine_sys::FlutterBackingStore,
+    user_data: *mut c_void,
+) -> bool {
+    trace!("create_backing_store");
+    unsafe {
+        if let Some(compositor) = get_compositor(user_data) {
+            let size = (*config).size;
+            if let Some(backing_store) = compositor.create_backing_store((size.width, size.height))
+            {
+                backing_store.into_ffi(&mut *backing_store_out);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+pub extern "C" fn collect_backing_store(
+    backing_store: *const flutter_engine_sys::FlutterBackingStore,
+    user_data: *mut c_void,
+) -> bool {
+    trace!("collect_backing_store");
+    unsafe {
+        let backing_store = BackingStore::from_ffi(&*backing_store);
+        if let Some(compositor) = get_compositor(user_data) {
+            compositor.collect_backing_store(backing_store)
+        } else {
+            true
+        }
+    }
+}
+
+pub extern "C" fn present_layers(
+    layers: *mut *const flutter_engine_sys::FlutterLayer,
+    layers_count: usize,
+    user_data: *mut c_void,
+) -> bool {
+    trace!("present_layers");
+    unsafe {
+        if let Some(compositor) = get_compositor(user_data) {
+            let layers: Vec<Layer> = std::slice::from_raw_parts(layers, layers_count)
+                .iter()
+                .map(|layer| Layer::from_ffi(&**layer))
+                .collect();
+            compositor.present_layers(&layers)
+        } else {
+            false
+        }
+    }
+}
+
+pub extern "C" fn update_semantics_node(
+    node: *const flutter_engine_sys::FlutterSemanticsNode,
+    user_data: *mut c_void,
+) {
+    trace!("update_semantics_node");
+    unsafe {
+        let engine = &*(user_data as *const FlutterEngineInner);
+        let updated = engine.semantics.write().update_node(&*node);
+        if updated {
+            notify_semantics_updated(engine);
+        }
+    }
+}
+
+pub extern "C" fn update_semantics_custom_action(
+    action: *const flutter_engine_sys::FlutterSemanticsCustomAction,
+    user_data: *mut c_void,
+) {
+    trace!("update_semantics_custom_action");
+    unsafe {
+        let engine = &*(user_data as *const FlutterEngineInner);
+        let updated = engine.semantics.write().update_custom_action(&*action);
+        if updated {
+            notify_semantics_updated(engine);
+        }
+    }
+}
+
+fn notify_semantics_updated(engine: &FlutterEngineInner) {
+    if let Some(handler) = engine.handler.upgrade() {
+        // the handler gets a copy so it can use the tree of the engine
+        let tree = engine.semantics.read().clone();
+        handler.semantics_updated(&tree);
+    }
+}
+
 pub extern "C" fn post_task(
     task: flutter_engine_sys::FlutterTask,
     target_time_nanos: u64,
@@ -140,3 +258,15 @@ pub extern "C" fn gl_external_texture_frame(
         false
     }
 }
+
+pub extern "C" fn vsync_callback(user_data: *mut c_void, baton: isize) {
+    trace!("vsync_callback");
+    unsafe {
+        let inner = &*(user_data as *const FlutterEngineInner);
+        let vsync_source = inner.vsync_source.read().clone();
+        if let Some(vsync_source) = vsync_source {
+            let engine = inner.platform_runner.engine();
+            vsync_source.request_vsync(VsyncRequest::new(engine, baton));
+        }
+    }
+}
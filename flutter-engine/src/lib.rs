@@ -1,30 +1,50 @@
 #[macro_use]
 mod macros;
 
+pub mod accessibility;
+pub mod builder;
 pub mod channel;
 pub mod codec;
+pub mod compositor;
 pub mod error;
 pub mod ffi;
 mod flutter_callbacks;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod plugins;
+pub mod semantics;
+pub mod snapshot;
 pub mod tasks;
 pub mod texture_registry;
 pub mod utils;
+pub mod vsync;
 
-use crate::channel::{Channel, ChannelRegistrar};
+use crate::accessibility::AccessibilityFeatures;
+use crate::builder::ProjectConfig;
+use crate::channel::interceptor::{Intercepted, Interceptors};
+use crate::channel::{
+    BufferConfig, Channel, ChannelInterceptor, ChannelRegistrar, MessageDirection,
+};
+use crate::compositor::Compositor;
 use crate::ffi::{
     FlutterPointerDeviceKind, FlutterPointerMouseButtons, FlutterPointerPhase,
-    FlutterPointerSignalKind, PlatformMessage, PlatformMessageResponseHandle,
+    FlutterPointerSignalKind, FlutterRendererType, PlatformMessage, PlatformMessageResponseHandle,
+    SoftwareFrame,
 };
 use crate::plugins::{Plugin, PluginRegistrar};
-use crate::tasks::{TaskRunner, TaskRunnerHandler};
+use crate::semantics::{SemanticsAction, SemanticsNodeId, SemanticsTree};
+use crate::snapshot::{AotSnapshot, LoadedSnapshot, SnapshotBuffer};
+use crate::tasks::{TaskRunner, TaskRunnerHandler, TaskRunnerInner};
 use crate::texture_registry::{Texture, TextureRegistry};
+use crate::vsync::{VsyncBaton, VsyncSource};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use flutter_engine_sys::FlutterTask;
 use log::trace;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use std::borrow::Cow;
 use std::ffi::CString;
 use std::future::Future;
+use std::io;
 use std::os::raw::{c_char, c_void};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicPtr, Ordering};
@@ -46,12 +66,54 @@ struct FlutterEngineInner {
     handler: Weak<dyn FlutterEngineHandler>,
     engine_ptr: AtomicPtr<flutter_engine_sys::_FlutterEngine>,
     plugins: RwLock<PluginRegistrar>,
+    interceptors: Interceptors,
     platform_runner: TaskRunner,
     _platform_runner_handler: Arc<PlatformRunnerHandler>,
     platform_receiver: Receiver<MainThreadCallback>,
     platform_sender: Sender<MainThreadCallback>,
     texture_registry: TextureRegistry,
     assets: PathBuf,
+    renderer: FlutterRendererType,
+    config: ProjectConfig,
+    compositor: RwLock<Option<Weak<dyn Compositor>>>,
+    semantics: RwLock<SemanticsTree>,
+    vsync_source: RwLock<Option<Arc<dyn VsyncSource>>>,
+    aot_snapshot: RwLock<Option<LoadedSnapshot>>,
+    state: RwLock<EngineState>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum EngineState {
+    Created,
+    Initialized,
+    Running,
+    Deinitialized,
+    ShutDown,
+}
+
+impl FlutterEngineInner {
+    /// Shuts the engine down once and releases the task runner handed to it.
+    fn shutdown(&self) {
+        let engine_ptr = self.engine_ptr.swap(ptr::null_mut(), Ordering::Relaxed);
+        if engine_ptr.is_null() {
+            return;
+        }
+        unsafe {
+            flutter_engine_sys::FlutterEngineShutdown(engine_ptr);
+            // The engine no longer calls into the task runner, so the
+            // reference leaked in `create_engine` can be released.
+            drop(Arc::from_raw(Arc::as_ptr(&self.platform_runner.inner)));
+        }
+        *self.state.write() = EngineState::ShutDown;
+    }
+}
+
+impl Drop for FlutterEngineInner {
+    fn drop(&mut self) {
+        // `FlutterEngine::drop` makes sure this runs on the platform thread
+        // if the engine is running.
+        self.shutdown();
+    }
 }
 
 pub struct FlutterEngineWeakRef {
@@ -93,12 +155,36 @@ impl Clone for FlutterEngineWeakRef {
     }
 }
 
+/// A handle to a flutter engine.
+///
+/// The engine shuts down once the last handle is dropped. If that happens
+/// off the platform thread while the engine is running, the handle is handed
+/// to the platform thread to shut the engine down there. If the platform
+/// thread stops executing its tasks, the engine is leaked instead.
 pub struct FlutterEngine {
     inner: Arc<FlutterEngineInner>,
 }
 
 unsafe impl Send for FlutterEngine {}
 
+impl Drop for FlutterEngine {
+    fn drop(&mut self) {
+        // Other handles keep the engine alive, e.g. after a temporary upgrade
+        // of a weak reference. Two last handles dropped at the same time on
+        // other threads can still shut the engine down off the platform
+        // thread.
+        if Arc::strong_count(&self.inner) == 1
+            && !self.engine_ptr().is_null()
+            && !self.is_platform_thread()
+        {
+            let engine = self.clone();
+            self.post_platform_callback(MainThreadCallback::Engine(Box::new(move |_| {
+                drop(engine)
+            })));
+        }
+    }
+}
+
 unsafe impl Sync for FlutterEngine {}
 
 impl Clone for FlutterEngine {
@@ -122,6 +208,14 @@ pub trait FlutterEngineHandler {
 
     fn gl_proc_resolver(&self, proc: *const c_char) -> *mut c_void;
 
+    /// Called with every frame when the engine uses the software renderer.
+    fn present_software_frame(&self, _frame: &SoftwareFrame) -> bool {
+        false
+    }
+
+    /// Called on the platform thread after the semantics tree changed.
+    fn semantics_updated(&self, _tree: &SemanticsTree) {}
+
     fn wake_platform_thread(&self);
 
     fn run_in_background(&self, func: Box<dyn Future<Output = ()> + Send + 'static>);
@@ -141,17 +235,37 @@ impl TaskRunnerHandler for PlatformRunnerHandler {
 
 impl FlutterEngine {
     pub fn new(handler: Weak<dyn FlutterEngineHandler>, assets: PathBuf) -> Self {
+        Self::with_renderer(handler, assets, FlutterRendererType::OpenGL)
+    }
+
+    pub fn with_renderer(
+        handler: Weak<dyn FlutterEngineHandler>,
+        assets: PathBuf,
+        renderer: FlutterRendererType,
+    ) -> Self {
+        Self::with_config(handler, assets, renderer, Default::default())
+    }
+
+    pub(crate) fn with_config(
+        handler: Weak<dyn FlutterEngineHandler>,
+        assets: PathBuf,
+        renderer: FlutterRendererType,
+        config: ProjectConfig,
+    ) -> Self {
         let platform_handler = Arc::new(PlatformRunnerHandler {
             handler: handler.clone(),
         });
 
         let (main_tx, main_rx) = unbounded();
+        let plugins = PluginRegistrar::new();
+        let interceptors = plugins.interceptors.clone();
 
         let engine = Self {
             inner: Arc::new(FlutterEngineInner {
                 handler,
                 engine_ptr: AtomicPtr::new(ptr::null_mut()),
-                plugins: RwLock::new(PluginRegistrar::new()),
+                plugins: RwLock::new(plugins),
+                interceptors,
                 platform_runner: TaskRunner::new(
                     Arc::downgrade(&platform_handler) as Weak<dyn TaskRunnerHandler>
                 ),
@@ -160,6 +274,13 @@ impl FlutterEngine {
                 platform_sender: main_tx,
                 texture_registry: TextureRegistry::new(),
                 assets,
+                renderer,
+                config,
+                compositor: RwLock::new(None),
+                semantics: RwLock::new(SemanticsTree::new()),
+                vsync_source: RwLock::new(None),
+                aot_snapshot: RwLock::new(None),
+                state: RwLock::new(EngineState::Created),
             }),
         };
 
@@ -183,6 +304,15 @@ impl FlutterEngine {
         self
     }
 
+    /// Adds an interceptor that sees every platform message of this engine.
+    pub fn add_interceptor<I>(&self, interceptor: I) -> &Self
+    where
+        I: ChannelInterceptor + 'static,
+    {
+        self.inner.plugins.write().add_interceptor(interceptor);
+        self
+    }
+
     pub fn with_plugin<F, P>(&self, f: F)
     where
         F: FnOnce(&P),
@@ -207,6 +337,26 @@ impl FlutterEngine {
             .remove_channel(channel_name)
     }
 
+    /// Sets how many messages are kept for channels that are not registered
+    /// yet and have no buffer config of their own.
+    pub fn set_default_channel_buffer(&self, config: BufferConfig) {
+        self.inner
+            .plugins
+            .write()
+            .channel_registry
+            .set_default_buffer_config(config)
+    }
+
+    /// Sets how many messages are kept for `channel_name` until a channel
+    /// with that name is registered.
+    pub fn set_channel_buffer(&self, channel_name: &str, config: BufferConfig) {
+        self.inner
+            .plugins
+            .write()
+            .channel_registry
+            .set_buffer_config(channel_name, config)
+    }
+
     pub fn with_channel<F>(&self, channel_name: &str, f: F)
     where
         F: FnOnce(&dyn Channel),
@@ -239,48 +389,156 @@ impl FlutterEngine {
         &self.inner.assets
     }
 
+    pub fn renderer(&self) -> FlutterRendererType {
+        self.inner.renderer
+    }
+
+    /// Lets `compositor` composite the layers of every frame. Has to be set
+    /// before the engine is run.
+    pub fn set_compositor(&self, compositor: Weak<dyn Compositor>) {
+        self.inner.compositor.write().replace(compositor);
+    }
+
+    /// Lets `vsync_source` decide when frames start. Has to be set before the
+    /// engine is run.
+    pub fn set_vsync_source(&self, vsync_source: Arc<dyn VsyncSource>) {
+        self.inner.vsync_source.write().replace(vsync_source);
+    }
+
+    /// Loads the AOT snapshot a release build of the engine runs instead of
+    /// the `kernel_blob.bin` in the assets. Has to be set before the engine
+    /// is run.
+    pub fn set_aot_snapshot(&self, snapshot: AotSnapshot) -> io::Result<()> {
+        let snapshot = LoadedSnapshot::load(snapshot)?;
+        self.inner.aot_snapshot.write().replace(snapshot);
+        Ok(())
+    }
+
+    /// Creates and runs the engine in one step.
+    ///
+    /// The calling thread becomes the platform thread of the engine, every
+    /// engine in the process can have its own.
     pub fn run(&self, arguments: &[String]) -> Result<(), RunError> {
+        self.create_engine(arguments, flutter_engine_sys::FlutterEngineRun)?;
+        *self.inner.state.write() = EngineState::Running;
+        Ok(())
+    }
+
+    /// Creates the engine without running any Dart code.
+    ///
+    /// Once initialized the engine handle is live, so plugins, textures and
+    /// channel handlers can be registered before the root isolate starts with
+    /// [`run_initialized`](Self::run_initialized) and sends its first
+    /// messages.
+    ///
+    /// Like [`run`](Self::run) this makes the calling thread the platform
+    /// thread of the engine.
+    pub fn initialize(&self, arguments: &[String]) -> Result<(), RunError> {
+        self.create_engine(arguments, flutter_engine_sys::FlutterEngineInitialize)?;
+        *self.inner.state.write() = EngineState::Initialized;
+        Ok(())
+    }
+
+    /// Runs an engine created with [`initialize`](Self::initialize). An
+    /// engine can only be run once.
+    pub fn run_initialized(&self) -> Result<(), RunError> {
         if !self.is_platform_thread() {
             return Err(RunError::NotPlatformThread);
         }
 
-        let mut args = Vec::with_capacity(arguments.len() + 2);
-        args.push(CString::new("flutter-rs").unwrap().into_raw());
-        args.push(
-            CString::new("--icu-symbol-prefix=gIcudtl")
-                .unwrap()
-                .into_raw(),
-        );
+        let mut state = self.inner.state.write();
+        match *state {
+            EngineState::Initialized => {}
+            EngineState::Created | EngineState::Deinitialized | EngineState::ShutDown => {
+                return Err(RunError::NotInitialized)
+            }
+            EngineState::Running => return Err(RunError::AlreadyStarted),
+        }
+        let result = unsafe { flutter_engine_sys::FlutterEngineRunInitialized(self.engine_ptr()) };
+        if result != flutter_engine_sys::FlutterEngineResult::kSuccess {
+            return Err(RunError::EngineResult(result));
+        }
+        *state = EngineState::Running;
+        Ok(())
+    }
+
+    /// Stops an initialized or running engine. Afterwards the engine posts
+    /// no more tasks to the platform thread, but it still has to be shut
+    /// down with [`shutdown`](Self::shutdown) and can't be run again.
+    pub fn deinitialize(&self) {
+        if !self.is_platform_thread() {
+            panic!("Not on platform thread");
+        }
+
+        let mut state = self.inner.state.write();
+        if let EngineState::Initialized | EngineState::Running = *state {
+            unsafe {
+                flutter_engine_sys::FlutterEngineDeinitialize(self.engine_ptr());
+            }
+            *state = EngineState::Deinitialized;
+        }
+    }
+
+    fn create_engine(
+        &self,
+        arguments: &[String],
+        create: unsafe extern "C" fn(
+            usize,
+            *const flutter_engine_sys::FlutterRendererConfig,
+            *const flutter_engine_sys::FlutterProjectArgs,
+            *mut c_void,
+            *mut flutter_engine_sys::FlutterEngine,
+        ) -> flutter_engine_sys::FlutterEngineResult,
+    ) -> Result<(), RunError> {
+        if *self.inner.state.read() != EngineState::Created {
+            return Err(RunError::AlreadyStarted);
+        }
+        self.inner.platform_runner.bind_to_current_thread();
+
+        let aot_snapshot = self.inner.aot_snapshot.read();
+        match (
+            snapshot::runs_aot_compiled_dart_code(),
+            aot_snapshot.is_some(),
+        ) {
+            (true, false) => return Err(RunError::AotSnapshotMissing),
+            (false, true) => return Err(RunError::UnexpectedAotSnapshot),
+            _ => {}
+        }
+        let [vm_snapshot_data, vm_snapshot_instructions, isolate_snapshot_data, isolate_snapshot_instructions] =
+            aot_snapshot
+                .as_ref()
+                .map_or([SnapshotBuffer::NULL; 4], LoadedSnapshot::buffers);
+
+        let config = &self.inner.config;
+        // The engine copies everything it needs from the project args, so
+        // the strings only have to outlive the call that creates it.
+        let mut args = Vec::with_capacity(arguments.len() + config.switches.len() + 2);
+        args.push(CString::new("flutter-rs").unwrap());
+        if config.icu_data_path.is_none() {
+            args.push(CString::new("--icu-symbol-prefix=gIcudtl").unwrap());
+        }
+        for switch in config.switches.iter() {
+            args.push(CString::new(switch.to_arg()).unwrap());
+        }
         for arg in arguments.iter() {
-            args.push(CString::new(arg.as_str()).unwrap().into_raw());
-        }
-
-        let renderer_config = flutter_engine_sys::FlutterRendererConfig {
-            type_: flutter_engine_sys::FlutterRendererType::kOpenGL,
-            __bindgen_anon_1: flutter_engine_sys::FlutterRendererConfig__bindgen_ty_1 {
-                open_gl: flutter_engine_sys::FlutterOpenGLRendererConfig {
-                    struct_size: std::mem::size_of::<flutter_engine_sys::FlutterOpenGLRendererConfig>(
-                    ),
-                    make_current: Some(flutter_callbacks::make_current),
-                    clear_current: Some(flutter_callbacks::clear_current),
-                    present: Some(flutter_callbacks::present),
-                    fbo_callback: Some(flutter_callbacks::fbo_callback),
-                    make_resource_current: Some(flutter_callbacks::make_resource_current),
-                    fbo_reset_after_present: false,
-                    surface_transformation: None,
-                    gl_proc_resolver: Some(flutter_callbacks::gl_proc_resolver),
-                    gl_external_texture_frame_callback: Some(
-                        flutter_callbacks::gl_external_texture_frame,
-                    ),
-                },
-            },
-        };
+            args.push(CString::new(arg.as_str()).unwrap());
+        }
+        let argv: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+        let assets_path = path_to_cstring(self.assets());
 
-        // TODO: Should be downgraded to a weak once weak::into_raw lands in stable
-        let runner_ptr = {
-            let arc = self.inner.platform_runner.clone().inner;
-            Arc::into_raw(arc) as *mut std::ffi::c_void
-        };
+        let icu_data_path = config.icu_data_path.as_deref().map(path_to_cstring);
+        let persistent_cache_path = config.persistent_cache_path.as_deref().map(path_to_cstring);
+        let custom_dart_entrypoint = config
+            .custom_dart_entrypoint
+            .as_deref()
+            .map(|entrypoint| CString::new(entrypoint).unwrap());
+
+        let renderer_config = self.renderer_config();
+
+        // The task runner has to outlive the engine even when the last
+        // handle is dropped, so the engine holds a strong reference that is
+        // released after `FlutterEngineShutdown`.
+        let runner_ptr = Arc::into_raw(self.inner.platform_runner.inner.clone()) as *mut c_void;
 
         let platform_task_runner = flutter_engine_sys::FlutterTaskRunnerDescription {
             struct_size: std::mem::size_of::<flutter_engine_sys::FlutterTaskRunnerDescription>(),
@@ -299,42 +557,61 @@ impl FlutterEngine {
                 as *const flutter_engine_sys::FlutterTaskRunnerDescription,
         };
 
+        // The engine is shut down before the inner state is dropped, so the
+        // callbacks don't need to hold a reference.
+        let inner_ptr = Arc::as_ptr(&self.inner) as *mut c_void;
+
+        let compositor = flutter_engine_sys::FlutterCompositor {
+            struct_size: std::mem::size_of::<flutter_engine_sys::FlutterCompositor>(),
+            user_data: inner_ptr,
+            create_backing_store_callback: Some(flutter_callbacks::create_backing_store),
+            collect_backing_store_callback: Some(flutter_callbacks::collect_backing_store),
+            present_layers_callback: Some(flutter_callbacks::present_layers),
+        };
+
         let project_args = flutter_engine_sys::FlutterProjectArgs {
             struct_size: std::mem::size_of::<flutter_engine_sys::FlutterProjectArgs>(),
-            assets_path: path_to_cstring(self.assets()).into_raw(),
+            assets_path: assets_path.as_ptr(),
             main_path__unused__: std::ptr::null(),
             packages_path__unused__: std::ptr::null(),
-            icu_data_path: std::ptr::null(),
-            command_line_argc: args.len() as i32,
-            command_line_argv: args.as_mut_ptr() as _,
+            icu_data_path: optional_ptr(&icu_data_path),
+            command_line_argc: argv.len() as i32,
+            command_line_argv: argv.as_ptr(),
             platform_message_callback: Some(flutter_callbacks::platform_message_callback),
-            vm_snapshot_data: std::ptr::null(),
-            vm_snapshot_data_size: 0,
-            vm_snapshot_instructions: std::ptr::null(),
-            vm_snapshot_instructions_size: 0,
-            isolate_snapshot_data: std::ptr::null(),
-            isolate_snapshot_data_size: 0,
-            isolate_snapshot_instructions: std::ptr::null(),
-            isolate_snapshot_instructions_size: 0,
+            vm_snapshot_data: vm_snapshot_data.ptr,
+            vm_snapshot_data_size: vm_snapshot_data.size,
+            vm_snapshot_instructions: vm_snapshot_instructions.ptr,
+            vm_snapshot_instructions_size: vm_snapshot_instructions.size,
+            isolate_snapshot_data: isolate_snapshot_data.ptr,
+            isolate_snapshot_data_size: isolate_snapshot_data.size,
+            isolate_snapshot_instructions: isolate_snapshot_instructions.ptr,
+            isolate_snapshot_instructions_size: isolate_snapshot_instructions.size,
             root_isolate_create_callback: Some(flutter_callbacks::root_isolate_create_callback),
-            update_semantics_node_callback: None,
-            update_semantics_custom_action_callback: None,
-            persistent_cache_path: std::ptr::null(),
-            is_persistent_cache_read_only: false,
-            vsync_callback: None,
-            custom_dart_entrypoint: std::ptr::null(),
+            update_semantics_node_callback: Some(flutter_callbacks::update_semantics_node),
+            update_semantics_custom_action_callback: Some(
+                flutter_callbacks::update_semantics_custom_action,
+            ),
+            persistent_cache_path: optional_ptr(&persistent_cache_path),
+            is_persistent_cache_read_only: config.is_persistent_cache_read_only,
+            vsync_callback: if self.inner.vsync_source.read().is_some() {
+                Some(flutter_callbacks::vsync_callback)
+            } else {
+                None
+            },
+            custom_dart_entrypoint: optional_ptr(&custom_dart_entrypoint),
             custom_task_runners: &custom_task_runners
                 as *const flutter_engine_sys::FlutterCustomTaskRunners,
-            shutdown_dart_vm_when_done: true,
-            compositor: std::ptr::null(),
+            shutdown_dart_vm_when_done: config.shutdown_dart_vm_when_done,
+            compositor: if self.inner.compositor.read().is_some() {
+                &compositor as *const flutter_engine_sys::FlutterCompositor
+            } else {
+                std::ptr::null()
+            },
         };
 
         unsafe {
-            // TODO: Should be downgraded to a weak once weak::into_raw lands in stable
-            let inner_ptr = Arc::into_raw(self.inner.clone()) as *mut std::ffi::c_void;
-
             let engine_ptr: flutter_engine_sys::FlutterEngine = std::ptr::null_mut();
-            if flutter_engine_sys::FlutterEngineRun(
+            if create(
                 1,
                 &renderer_config,
                 &project_args,
@@ -344,6 +621,7 @@ impl FlutterEngine {
             ) != flutter_engine_sys::FlutterEngineResult::kSuccess
                 || engine_ptr.is_null()
             {
+                drop(Arc::from_raw(runner_ptr as *const Mutex<TaskRunnerInner>));
                 Err(RunError::EnginePtrNull)
             } else {
                 self.inner.engine_ptr.store(engine_ptr, Ordering::Relaxed);
@@ -352,6 +630,43 @@ impl FlutterEngine {
         }
     }
 
+    fn renderer_config(&self) -> flutter_engine_sys::FlutterRendererConfig {
+        match self.inner.renderer {
+            FlutterRendererType::OpenGL => flutter_engine_sys::FlutterRendererConfig {
+                type_: self.inner.renderer.into(),
+                __bindgen_anon_1: flutter_engine_sys::FlutterRendererConfig__bindgen_ty_1 {
+                    open_gl: flutter_engine_sys::FlutterOpenGLRendererConfig {
+                        struct_size: std::mem::size_of::<
+                            flutter_engine_sys::FlutterOpenGLRendererConfig,
+                        >(),
+                        make_current: Some(flutter_callbacks::make_current),
+                        clear_current: Some(flutter_callbacks::clear_current),
+                        present: Some(flutter_callbacks::present),
+                        fbo_callback: Some(flutter_callbacks::fbo_callback),
+                        make_resource_current: Some(flutter_callbacks::make_resource_current),
+                        fbo_reset_after_present: false,
+                        surface_transformation: None,
+                        gl_proc_resolver: Some(flutter_callbacks::gl_proc_resolver),
+                        gl_external_texture_frame_callback: Some(
+                            flutter_callbacks::gl_external_texture_frame,
+                        ),
+                    },
+                },
+            },
+            FlutterRendererType::Software => flutter_engine_sys::FlutterRendererConfig {
+                type_: self.inner.renderer.into(),
+                __bindgen_anon_1: flutter_engine_sys::FlutterRendererConfig__bindgen_ty_1 {
+                    software: flutter_engine_sys::FlutterSoftwareRendererConfig {
+                        struct_size: std::mem::size_of::<
+                            flutter_engine_sys::FlutterSoftwareRendererConfig,
+                        >(),
+                        surface_present_callback: Some(flutter_callbacks::surface_present),
+                    },
+                },
+            },
+        }
+    }
+
     pub(crate) fn post_platform_callback(&self, callback: MainThreadCallback) {
         self.inner.platform_sender.send(callback).unwrap();
         self.inner.platform_runner.wake();
@@ -447,22 +762,100 @@ impl FlutterEngine {
         }
     }
 
+    /// Sends a message to dart after passing it to the interceptors. Nothing
+    /// waits for an answer if an interceptor drops or answers it; calls with
+    /// a reply are intercepted by `MethodCallReply` before their response
+    /// handle is created.
     pub(crate) fn send_platform_message(&self, message: PlatformMessage) {
+        if !self.is_platform_thread() {
+            panic!("Not on platform thread");
+        }
+
+        if let Intercepted::Pass(bytes) = self.intercept_outbound(&message.channel, message.message)
+        {
+            self.send_intercepted_platform_message(PlatformMessage {
+                channel: message.channel,
+                message: &bytes,
+                response_handle: message.response_handle,
+            });
+        }
+    }
+
+    /// Passes a message to dart to the interceptors.
+    pub(crate) fn intercept_outbound<'a>(
+        &self,
+        channel: &str,
+        message: &'a [u8],
+    ) -> Intercepted<'a> {
+        self.inner
+            .interceptors
+            .intercept(channel, MessageDirection::Outbound, message)
+    }
+
+    /// Sends a message to dart that already passed the interceptors.
+    pub(crate) fn send_intercepted_platform_message(&self, message: PlatformMessage) {
         trace!("Sending message on channel {}", message.channel);
         if !self.is_platform_thread() {
             panic!("Not on platform thread");
         }
 
+        let channel = CString::new(&*message.channel).unwrap();
         unsafe {
             flutter_engine_sys::FlutterEngineSendPlatformMessage(
                 self.engine_ptr(),
-                &message.into(),
+                &message.into_ffi(&channel),
+            );
+        }
+    }
+
+    /// Creates a response handle for a message sent to dart. The engine calls
+    /// `callback` with the reply and `user_data` once dart answers.
+    pub(crate) fn create_platform_message_response_handle(
+        &self,
+        callback: flutter_engine_sys::FlutterDataCallback,
+        user_data: *mut c_void,
+    ) -> Option<*mut flutter_engine_sys::FlutterPlatformMessageResponseHandle> {
+        if !self.is_platform_thread() {
+            panic!("Not on platform thread");
+        }
+
+        let mut handle = ptr::null_mut();
+        let result = unsafe {
+            flutter_engine_sys::FlutterPlatformMessageCreateResponseHandle(
+                self.engine_ptr(),
+                callback,
+                user_data,
+                &mut handle,
+            )
+        };
+        if result == flutter_engine_sys::FlutterEngineResult::kSuccess {
+            Some(handle)
+        } else {
+            None
+        }
+    }
+
+    /// Releases a handle created with `create_platform_message_response_handle`.
+    /// A reply to a message that was already sent still reaches its callback.
+    pub(crate) fn release_platform_message_response_handle(
+        &self,
+        handle: *mut flutter_engine_sys::FlutterPlatformMessageResponseHandle,
+    ) {
+        if !self.is_platform_thread() {
+            panic!("Not on platform thread");
+        }
+
+        unsafe {
+            flutter_engine_sys::FlutterPlatformMessageReleaseResponseHandle(
+                self.engine_ptr(),
+                handle,
             );
         }
     }
 
     pub(crate) fn send_platform_message_response(
         &self,
+        channel: &str,
         response_handle: PlatformMessageResponseHandle,
         bytes: &[u8],
     ) {
@@ -471,6 +864,11 @@ impl FlutterEngine {
             panic!("Not on platform thread");
         }
 
+        let bytes = self.intercept_reply(channel, MessageDirection::Response, bytes);
+        if response_handle.is_replayed() {
+            // the message came from a `Replayer`, not from dart
+            return;
+        }
         unsafe {
             flutter_engine_sys::FlutterEngineSendPlatformMessageResponse(
                 self.engine_ptr(),
@@ -481,13 +879,110 @@ impl FlutterEngine {
         }
     }
 
-    pub fn shutdown(&self) {
+    /// Passes the answer to a message, a `Response` of the embedder or a
+    /// `Reply` of dart, to the interceptors.
+    pub(crate) fn intercept_reply<'a>(
+        &self,
+        channel: &str,
+        direction: MessageDirection,
+        bytes: &'a [u8],
+    ) -> Cow<'a, [u8]> {
+        match self.inner.interceptors.intercept(channel, direction, bytes) {
+            Intercepted::Pass(bytes) => bytes,
+            Intercepted::Drop => Cow::Borrowed(&[][..]),
+            Intercepted::Reply(reply) => Cow::Owned(reply),
+        }
+    }
+
+    /// Enables or disables the generation of the semantics tree.
+    pub fn update_semantics_enabled(&self, enabled: bool) {
         if !self.is_platform_thread() {
-            panic!("Not on platform thread")
+            panic!("Not on platform thread");
+        }
+
+        if !enabled {
+            self.inner.semantics.write().clear();
+        }
+        unsafe {
+            flutter_engine_sys::FlutterEngineUpdateSemanticsEnabled(self.engine_ptr(), enabled);
         }
+    }
+
+    /// Tells the app which accessibility features the platform requests.
+    pub fn update_accessibility_features(&self, features: AccessibilityFeatures) {
+        if !self.is_platform_thread() {
+            panic!("Not on platform thread");
+        }
+
+        unsafe {
+            flutter_engine_sys::FlutterEngineUpdateAccessibilityFeatures(
+                self.engine_ptr(),
+                features.into(),
+            );
+        }
+    }
+
+    /// Hands a vsync baton back to the engine, see [`VsyncRequest::fire`].
+    /// The engine forwards the vsync to its UI thread, so this can be called
+    /// from any thread.
+    ///
+    /// [`VsyncRequest::fire`]: vsync::VsyncRequest::fire
+    pub fn on_vsync(
+        &self,
+        baton: VsyncBaton,
+        frame_start_time_nanos: u64,
+        frame_target_time_nanos: u64,
+    ) {
+        unsafe {
+            flutter_engine_sys::FlutterEngineOnVsync(
+                self.engine_ptr(),
+                baton,
+                frame_start_time_nanos,
+                frame_target_time_nanos,
+            );
+        }
+    }
+
+    pub fn with_semantics_tree<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&SemanticsTree) -> R,
+    {
+        f(&self.inner.semantics.read())
+    }
 
+    /// Performs an action on a semantics node.
+    pub fn dispatch_semantics_action(&self, id: SemanticsNodeId, action: SemanticsAction) {
+        if !self.is_platform_thread() {
+            panic!("Not on platform thread");
+        }
+
+        let args = action.encode_args();
         unsafe {
-            flutter_engine_sys::FlutterEngineShutdown(self.engine_ptr());
+            flutter_engine_sys::FlutterEngineDispatchSemanticsAction(
+                self.engine_ptr(),
+                id as u64,
+                action.flag().into(),
+                if args.is_empty() {
+                    ptr::null()
+                } else {
+                    args.as_ptr()
+                },
+                args.len(),
+            );
+        }
+    }
+
+    /// Shuts the engine down. Can be called from any thread and more than
+    /// once, off the platform thread the shutdown is posted to it.
+    ///
+    /// Dropping the last handle of an engine shuts it down as well.
+    pub fn shutdown(&self) {
+        if self.is_platform_thread() {
+            self.inner.shutdown();
+        } else {
+            self.post_platform_callback(MainThreadCallback::Engine(Box::new(|engine| {
+                engine.inner.shutdown()
+            })));
         }
     }
 
@@ -575,20 +1070,361 @@ fn path_to_cstring(path: &Path) -> CString {
     CString::new(path.to_string_lossy().to_string()).unwrap()
 }
 
+fn optional_ptr(string: &Option<CString>) -> *const std::os::raw::c_char {
+    string
+        .as_ref()
+        .map_or(std::ptr::null(), |string| string.as_ptr())
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum RunError {
     NotPlatformThread,
     EnginePtrNull,
+    /// The engine is a release build and needs an AOT snapshot.
+    AotSnapshotMissing,
+    /// An AOT snapshot was set, but the engine is a debug build.
+    UnexpectedAotSnapshot,
+    /// The engine was already initialized or run.
+    AlreadyStarted,
+    /// `run_initialized` was called before `initialize`.
+    NotInitialized,
+    EngineResult(flutter_engine_sys::FlutterEngineResult),
 }
 
 impl core::fmt::Display for RunError {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         let msg = match self {
+            RunError::EngineResult(result) => {
+                return writeln!(f, "The engine failed to start: {:?}.", result)
+            }
             RunError::NotPlatformThread => "Not on platform thread.",
             RunError::EnginePtrNull => "Engine ptr is null.",
+            RunError::AotSnapshotMissing => {
+                "The engine runs AOT compiled Dart code, but no AOT snapshot was set."
+            }
+            RunError::UnexpectedAotSnapshot => {
+                "An AOT snapshot was set, but the engine runs JIT compiled Dart code from kernel_blob.bin."
+            }
+            RunError::AlreadyStarted => "The engine was already started.",
+            RunError::NotInitialized => "The engine was not initialized.",
         };
         writeln!(f, "{}", msg)
     }
 }
 
 impl std::error::Error for RunError {}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::builder::FlutterEngineBuilder;
+    use crate::channel::{JsonMethodChannel, MethodCallHandler};
+    use crate::codec::{json_codec, MethodCall, MethodCallResult, MethodCodec, Value};
+    use crate::error::MethodCallError;
+    use crate::mock::{mock_engine, Call, MockEngine, MockHandler};
+    use std::thread;
+
+    fn engine(handler: &Arc<MockHandler>) -> FlutterEngine {
+        FlutterEngine::new(
+            Arc::downgrade(handler) as Weak<dyn FlutterEngineHandler>,
+            PathBuf::from("assets"),
+        )
+    }
+
+    #[test]
+    fn test_two_phase_startup() {
+        let handler = MockHandler::new();
+        let engine = engine(&handler);
+        engine.initialize(&[]).unwrap();
+        let mock = mock_engine(&engine);
+
+        // The engine handle is live before any Dart code runs.
+        let texture = engine.create_texture();
+        engine.run_initialized().unwrap();
+        assert_eq!(engine.run_initialized(), Err(RunError::AlreadyStarted));
+        assert_eq!(engine.run(&[]), Err(RunError::AlreadyStarted));
+        engine.deinitialize();
+        engine.deinitialize();
+
+        let calls: Vec<_> = mock
+            .calls()
+            .into_iter()
+            .map(|call| match call {
+                Call::Initialize { .. } => "initialize".to_owned(),
+                Call::RegisterExternalTexture(id) if id == texture.id() => "texture".to_owned(),
+                Call::RunInitialized => "run".to_owned(),
+                Call::Deinitialize => "deinitialize".to_owned(),
+                call => format!("{:?}", call),
+            })
+            .collect();
+        assert_eq!(calls, vec!["initialize", "texture", "run", "deinitialize"]);
+    }
+
+    #[test]
+    fn test_run_initialized_requires_initialize() {
+        let handler = MockHandler::new();
+        let engine = engine(&handler);
+        assert_eq!(engine.run_initialized(), Err(RunError::NotInitialized));
+
+        engine.initialize(&[]).unwrap();
+        assert_eq!(engine.initialize(&[]), Err(RunError::AlreadyStarted));
+        engine.deinitialize();
+        assert_eq!(engine.run_initialized(), Err(RunError::NotInitialized));
+    }
+
+    #[test]
+    fn test_software_renderer_presents_frames() {
+        let handler = MockHandler::new();
+        let engine = FlutterEngine::with_renderer(
+            Arc::downgrade(&handler) as Weak<dyn FlutterEngineHandler>,
+            PathBuf::from("assets"),
+            FlutterRendererType::Software,
+        );
+        engine.run(&[]).unwrap();
+        let mock = mock_engine(&engine);
+
+        // two rows of one pixel, each padded to eight bytes
+        let pixels = [1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8, 0, 0, 0, 0];
+        assert!(mock.present_software_surface(&pixels, 8, 2));
+        assert_eq!(handler.software_frames(), vec![pixels.to_vec()]);
+
+        let frame = unsafe { SoftwareFrame::from_raw(pixels.as_ptr() as *const c_void, 8, 2) };
+        assert_eq!(frame.stride_pixels(), 2);
+        assert_eq!(frame.height, 2);
+
+        // an OpenGL engine never gets software frames
+        let gl_handler = MockHandler::new();
+        let (_gl_engine, gl_mock) = crate::mock::run_engine(&gl_handler);
+        assert!(!gl_mock.present_software_surface(&pixels, 8, 2));
+        assert!(gl_handler.software_frames().is_empty());
+        assert!(gl_mock.present_gl_surface());
+        assert!(!mock.present_gl_surface());
+    }
+
+    fn shutdowns(mock: &MockEngine) -> usize {
+        mock.calls()
+            .into_iter()
+            .filter(|call| matches!(call, Call::Shutdown))
+            .count()
+    }
+
+    #[test]
+    fn test_drop_shuts_down_and_releases_engine() {
+        let handler = MockHandler::new();
+        for _ in 0..3 {
+            let engine = engine(&handler);
+            engine.run(&[]).unwrap();
+            let mock = mock_engine(&engine);
+            let weak = engine.downgrade();
+            let runner = Arc::downgrade(&engine.inner.platform_runner.inner);
+
+            drop(engine);
+            assert!(!weak.is_valid());
+            assert!(runner.upgrade().is_none());
+            assert_eq!(shutdowns(&mock), 1);
+        }
+    }
+
+    #[test]
+    fn test_shutdown_is_idempotent() {
+        let handler = MockHandler::new();
+        let engine = engine(&handler);
+        engine.run(&[]).unwrap();
+        let mock = mock_engine(&engine);
+
+        let other = engine.clone();
+        thread::spawn(move || other.shutdown()).join().unwrap();
+        assert_eq!(shutdowns(&mock), 0);
+        engine.execute_platform_tasks();
+        assert_eq!(shutdowns(&mock), 1);
+        assert!(engine.engine_ptr().is_null());
+
+        engine.shutdown();
+        assert_eq!(engine.run(&[]), Err(RunError::AlreadyStarted));
+        drop(engine);
+        assert_eq!(shutdowns(&mock), 1);
+    }
+
+    #[test]
+    fn test_drop_off_platform_thread() {
+        let handler = MockHandler::new();
+        let (engine, mock) = crate::mock::run_engine(&handler);
+        let weak = engine.downgrade();
+
+        // The last handle is dropped on another thread, the engine waits for
+        // the platform thread.
+        let other = engine.clone();
+        drop(engine);
+        thread::spawn(move || drop(other)).join().unwrap();
+        assert!(weak.is_valid());
+        assert_eq!(shutdowns(&mock), 0);
+
+        let engine = weak.upgrade().unwrap();
+        engine.execute_platform_tasks();
+        assert_eq!(shutdowns(&mock), 0);
+        drop(engine);
+        assert!(!weak.is_valid());
+        assert_eq!(shutdowns(&mock), 1);
+    }
+
+    #[test]
+    fn test_drop_of_other_handle_off_platform_thread() {
+        let handler = MockHandler::new();
+        let (engine, _mock) = crate::mock::run_engine(&handler);
+        let weak = engine.downgrade();
+        let wakes = handler.wake_count();
+
+        // only the last handle is handed to the platform thread
+        thread::spawn(move || assert!(weak.is_valid()))
+            .join()
+            .unwrap();
+        let other = engine.clone();
+        thread::spawn(move || drop(other)).join().unwrap();
+        assert_eq!(handler.wake_count(), wakes);
+        assert!(engine.inner.platform_receiver.is_empty());
+    }
+
+    struct EngineName(&'static str);
+
+    impl MethodCallHandler for EngineName {
+        fn on_method_call(
+            &mut self,
+            _: MethodCall,
+            _: FlutterEngine,
+        ) -> Result<Value, MethodCallError> {
+            Ok(Value::String(self.0.into()))
+        }
+    }
+
+    type SharedHandler = Arc<std::sync::RwLock<dyn MethodCallHandler + Send + Sync>>;
+
+    /// Answers calls on the same channel name with the name of the engine.
+    fn register_name(engine: &FlutterEngine, name: &'static str) -> SharedHandler {
+        let handler: SharedHandler = Arc::new(std::sync::RwLock::new(EngineName(name)));
+        engine.with_channel_registrar("name", |registrar| {
+            registrar.register_channel(JsonMethodChannel::new(
+                "flutter-rs/name",
+                Arc::downgrade(&handler),
+            ));
+        });
+        handler
+    }
+
+    #[test]
+    fn test_engines_are_isolated() {
+        let handlers = [MockHandler::new(), MockHandler::new()];
+        let engines: Vec<_> = handlers
+            .iter()
+            .zip(["mainA", "mainB"])
+            .map(|(handler, entrypoint)| {
+                let engine = FlutterEngineBuilder::new(
+                    Arc::downgrade(handler) as Weak<dyn FlutterEngineHandler>,
+                    PathBuf::from("assets"),
+                )
+                .with_dart_entrypoint(entrypoint)
+                .build()
+                .unwrap();
+                engine.run(&[]).unwrap();
+                engine
+            })
+            .collect();
+        let mocks: Vec<_> = engines.iter().map(mock_engine).collect();
+        let _a = register_name(&engines[0], "a");
+        let _b = register_name(&engines[1], "b");
+
+        let call = MethodCall {
+            method: "name".into(),
+            args: Value::Null,
+        };
+        let message = json_codec::CODEC.encode_method_call(&call);
+        // Only the second engine gets a message, twice.
+        mocks[1].send_platform_message("flutter-rs/name", &message);
+        mocks[1].send_platform_message("flutter-rs/name", &message);
+        handlers[0].pump(&engines[0]);
+        handlers[1].pump(&engines[1]);
+
+        let responses = |mock: &MockEngine| {
+            mock.calls()
+                .into_iter()
+                .filter_map(|call| match call {
+                    Call::SendPlatformMessageResponse { data, .. } => {
+                        match json_codec::CODEC.decode_envelope(&data) {
+                            Ok(MethodCallResult::Ok(value)) => Some(value),
+                            _ => panic!("Expected a success envelope"),
+                        }
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(responses(&mocks[0]), vec![]);
+        assert_eq!(
+            responses(&mocks[1]),
+            vec![Value::String("b".into()), Value::String("b".into())]
+        );
+        assert_eq!(
+            mocks[0].project_args().custom_dart_entrypoint.as_deref(),
+            Some("mainA")
+        );
+        assert_eq!(
+            mocks[1].project_args().custom_dart_entrypoint.as_deref(),
+            Some("mainB")
+        );
+    }
+
+    #[test]
+    fn test_run_binds_calling_thread() {
+        let handler = MockHandler::new();
+        let engine = {
+            let handler = handler.clone();
+            thread::spawn(move || engine(&handler)).join().unwrap()
+        };
+        assert!(!engine.is_platform_thread());
+
+        engine.initialize(&[]).unwrap();
+        assert!(engine.is_platform_thread());
+        let other = engine.clone();
+        let result = thread::spawn(move || other.run_initialized())
+            .join()
+            .unwrap();
+        assert_eq!(result, Err(RunError::NotPlatformThread));
+        engine.execute_platform_tasks();
+        engine.run_initialized().unwrap();
+    }
+
+    #[test]
+    fn test_engines_on_separate_threads() {
+        let main_handler = MockHandler::new();
+        let main_engine = engine(&main_handler);
+        main_engine.run(&[]).unwrap();
+        let main_mock = mock_engine(&main_engine);
+
+        let handler = MockHandler::new();
+        let engine = engine(&handler);
+        let (mock, tasks) = {
+            let handler = handler.clone();
+            thread::spawn(move || {
+                engine.run(&[]).unwrap();
+                let mock = mock_engine(&engine);
+                assert!(mock.runs_task_on_current_thread());
+                mock.post_task(1, 0);
+                engine.execute_platform_tasks();
+                let tasks = mock
+                    .calls()
+                    .into_iter()
+                    .filter(|call| matches!(call, Call::RunTask(1)))
+                    .count();
+                drop(handler);
+                (mock, tasks)
+            })
+            .join()
+            .unwrap()
+        };
+        assert_eq!(tasks, 1);
+        assert!(main_mock.runs_task_on_current_thread());
+        assert_eq!(handler.wake_count(), 1);
+        assert_eq!(main_handler.wake_count(), 0);
+        // The engine was dropped with its thread.
+        assert!(matches!(mock.calls().last(), Some(Call::Shutdown)));
+    }
+}
@@ -0,0 +1,204 @@
+//! Helpers for testing against the mock embedder API.
+//!
+//! Enabled with the `mock` feature, which replaces `libflutter_engine` with
+//! the fake implementation in `flutter_engine_sys::mock`.
+
+use crate::channel::{JsonMethodChannel, MethodCallHandler};
+use crate::codec::{json_codec::CODEC, MethodCall, MethodCallResult, MethodCodec, Value};
+use crate::error::MethodCallError;
+use crate::ffi::SoftwareFrame;
+use crate::semantics::SemanticsTree;
+use crate::{FlutterEngine, FlutterEngineHandler};
+pub use flutter_engine_sys::mock::{
+    set_runs_aot_compiled_dart_code, Call, MockEngine, MockProjectArgs,
+};
+use parking_lot::Mutex;
+use std::future::Future;
+use std::os::raw::{c_char, c_void};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::{mem, ptr};
+
+type BackgroundFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+type SemanticsListener = Box<dyn Fn(&SemanticsTree) + Send>;
+
+/// A [`FlutterEngineHandler`] without a window.
+///
+/// Background futures are queued and only run by [`pump`](Self::pump) so
+/// tests stay deterministic.
+#[derive(Default)]
+pub struct MockHandler {
+    wakes: AtomicUsize,
+    background: Mutex<Vec<BackgroundFuture>>,
+    software_frames: Mutex<Vec<Vec<u8>>>,
+    semantics_listener: Mutex<Option<SemanticsListener>>,
+}
+
+impl MockHandler {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Default::default())
+    }
+
+    /// How often the engine asked to wake up the platform thread.
+    pub fn wake_count(&self) -> usize {
+        self.wakes.load(Ordering::SeqCst)
+    }
+
+    /// The pixels of the frames presented by the software renderer.
+    pub fn software_frames(&self) -> Vec<Vec<u8>> {
+        self.software_frames.lock().clone()
+    }
+
+    /// Calls `listener` every time the semantics tree changed.
+    pub fn on_semantics_updated<F>(&self, listener: F)
+    where
+        F: Fn(&SemanticsTree) + Send + 'static,
+    {
+        *self.semantics_listener.lock() = Some(Box::new(listener));
+    }
+
+    /// Removes the queued background futures so a test can run them in any
+    /// order.
+    pub fn take_background(&self) -> Vec<Pin<Box<dyn Future<Output = ()> + Send + 'static>>> {
+        mem::take(&mut *self.background.lock())
+    }
+
+    /// Runs queued background futures and platform tasks until both are
+    /// drained.
+    pub fn pump(&self, engine: &FlutterEngine) {
+        loop {
+            let futures = mem::take(&mut *self.background.lock());
+            for future in futures {
+                block_on(future);
+            }
+            engine.execute_platform_tasks();
+            if self.background.lock().is_empty() {
+                break;
+            }
+        }
+    }
+}
+
+impl FlutterEngineHandler for MockHandler {
+    fn swap_buffers(&self) -> bool {
+        true
+    }
+
+    fn make_current(&self) -> bool {
+        true
+    }
+
+    fn clear_current(&self) -> bool {
+        true
+    }
+
+    fn fbo_callback(&self) -> u32 {
+        0
+    }
+
+    fn make_resource_current(&self) -> bool {
+        true
+    }
+
+    fn gl_proc_resolver(&self, _proc: *const c_char) -> *mut c_void {
+        ptr::null_mut()
+    }
+
+    fn present_software_frame(&self, frame: &SoftwareFrame) -> bool {
+        self.software_frames.lock().push(frame.to_vec());
+        true
+    }
+
+    fn semantics_updated(&self, tree: &SemanticsTree) {
+        if let Some(listener) = &*self.semantics_listener.lock() {
+            listener(tree);
+        }
+    }
+
+    fn wake_platform_thread(&self) {
+        self.wakes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn run_in_background(&self, func: Box<dyn Future<Output = ()> + Send + 'static>) {
+        self.background.lock().push(Pin::from(func));
+    }
+}
+
+/// Creates and runs an engine on the current thread backed by the mock
+/// embedder API.
+///
+/// The engine only holds a weak reference to `handler`, so the caller has to
+/// keep it alive.
+pub fn run_engine(handler: &Arc<MockHandler>) -> (FlutterEngine, Arc<MockEngine>) {
+    let handler = Arc::downgrade(handler) as Weak<dyn FlutterEngineHandler>;
+    let engine = FlutterEngine::new(handler, PathBuf::from("assets"));
+    engine.run(&[]).unwrap();
+    let mock = mock_engine(&engine);
+    (engine, mock)
+}
+
+/// Returns the mock behind a running engine.
+pub fn mock_engine(engine: &FlutterEngine) -> Arc<MockEngine> {
+    let engine_ptr = engine.engine_ptr();
+    assert!(!engine_ptr.is_null(), "Engine is not running");
+    unsafe { MockEngine::from_engine(engine_ptr) }
+}
+
+/// Polls `future` on the current thread until it completes.
+pub fn block_on<F: Future + ?Sized>(future: Pin<Box<F>>) -> F::Output {
+    crate::utils::block_on(future)
+}
+
+/// The json method channel [`register_echo`] registers.
+pub const ECHO_CHANNEL: &str = "flutter-rs/echo";
+
+/// Answers the method `echo` with its arguments.
+pub struct EchoHandler;
+
+impl MethodCallHandler for EchoHandler {
+    fn on_method_call(
+        &mut self,
+        call: MethodCall,
+        _: FlutterEngine,
+    ) -> Result<Value, MethodCallError> {
+        match call.method.as_str() {
+            "echo" => Ok(call.args),
+            _ => Err(MethodCallError::NotImplemented),
+        }
+    }
+}
+
+/// Registers an [`EchoHandler`] on [`ECHO_CHANNEL`].
+///
+/// The channel only holds a weak reference to the handler, so the caller has
+/// to keep it alive.
+pub fn register_echo(
+    engine: &FlutterEngine,
+) -> (Arc<RwLock<EchoHandler>>, Weak<JsonMethodChannel>) {
+    let handler = Arc::new(RwLock::new(EchoHandler));
+    let weak = Arc::downgrade(&handler);
+    let mut channel = Weak::new();
+    engine.with_channel_registrar("echo", |registrar| {
+        channel = registrar.register_channel(JsonMethodChannel::new(ECHO_CHANNEL, weak));
+    });
+    (handler, channel)
+}
+
+/// A json encoded call of the method `echo`.
+pub fn echo_call(value: &str) -> Vec<u8> {
+    CODEC.encode_method_call(&MethodCall {
+        method: "echo".into(),
+        args: Value::String(value.into()),
+    })
+}
+
+/// The value of a json encoded success envelope.
+pub fn echoed(response: Option<Vec<u8>>) -> Value {
+    match CODEC.decode_envelope(&response.expect("No response")) {
+        Ok(MethodCallResult::Ok(value)) => value,
+        _ => panic!("Expected a success envelope"),
+    }
+}
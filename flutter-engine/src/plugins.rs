@@ -8,7 +8,10 @@ use std::{
 };
 
 use crate::{
-    channel::{ChannelRegistrar, ChannelRegistry},
+    channel::{
+        interceptor::{Intercepted, Interceptors},
+        ChannelInterceptor, ChannelRegistrar, ChannelRegistry, MessageDirection,
+    },
     FlutterEngineWeakRef, PlatformMessage,
 };
 
@@ -16,6 +19,8 @@ use crate::{
 pub struct PluginRegistrar {
     plugins: HashMap<String, Arc<RwLock<dyn Any>>>,
     pub channel_registry: ChannelRegistry,
+    pub(crate) interceptors: Interceptors,
+    engine: FlutterEngineWeakRef,
 }
 
 impl PluginRegistrar {
@@ -24,7 +29,8 @@ impl PluginRegistrar {
     }
 
     pub fn init(&mut self, engine: FlutterEngineWeakRef) {
-        self.channel_registry.init(engine);
+        self.channel_registry.init(engine.clone());
+        self.engine = engine;
     }
 
     pub fn add_plugin<P>(&mut self, plugin: P) -> &mut Self
@@ -43,8 +49,38 @@ impl PluginRegistrar {
         self
     }
 
-    pub fn handle(&mut self, message: PlatformMessage) {
-        self.channel_registry.handle(message);
+    /// Adds an interceptor that sees every inbound and outbound platform
+    /// message. Interceptors run in the order they were added.
+    pub fn add_interceptor<I>(&mut self, interceptor: I) -> &mut Self
+    where
+        I: ChannelInterceptor + 'static,
+    {
+        self.interceptors.add(Arc::new(interceptor));
+        self
+    }
+
+    pub fn handle(&mut self, mut message: PlatformMessage) {
+        let reply = match self.interceptors.intercept(
+            &message.channel,
+            MessageDirection::Inbound,
+            message.message,
+        ) {
+            Intercepted::Pass(bytes) => {
+                self.channel_registry.handle(PlatformMessage {
+                    channel: message.channel,
+                    message: &bytes,
+                    response_handle: message.response_handle,
+                });
+                return;
+            }
+            Intercepted::Drop => Vec::new(),
+            Intercepted::Reply(reply) => reply,
+        };
+        if let (Some(handle), Some(engine)) =
+            (message.response_handle.take(), self.engine.upgrade())
+        {
+            engine.send_platform_message_response(&message.channel, handle, &reply);
+        }
     }
 
     pub fn with_plugin<F, P>(&self, f: F)
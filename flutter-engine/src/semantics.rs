@@ -0,0 +1,584 @@
+//! The semantics tree of the flutter app.
+//!
+//! Once semantics are enabled with `FlutterEngine::update_semantics_enabled`,
+//! the engine sends batches of updated nodes which are applied to the
+//! [`SemanticsTree`] of the engine. Nodes can be looked up by id or label and
+//! driven with [`SemanticsAction`]s.
+
+use crate::codec::{standard_codec::CODEC, MessageCodec, Value, ValueMap};
+use crate::ffi::{Rect, Transformation};
+use bitflags::bitflags;
+use flutter_engine_sys::{
+    FlutterSemanticsAction, FlutterSemanticsCustomAction, FlutterSemanticsFlag,
+    FlutterSemanticsNode, FlutterTextDirection,
+};
+use std::collections::{HashMap, HashSet};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::slice;
+
+pub type SemanticsNodeId = i32;
+
+pub type CustomSemanticsActionId = i32;
+
+/// The id of the root node of the tree.
+pub const ROOT_NODE_ID: SemanticsNodeId = 0;
+
+bitflags! {
+    /// The properties of a semantics node.
+    #[derive(Default)]
+    pub struct SemanticsFlags: u32 {
+        const HAS_CHECKED_STATE = FlutterSemanticsFlag::kFlutterSemanticsFlagHasCheckedState.0;
+        const IS_CHECKED = FlutterSemanticsFlag::kFlutterSemanticsFlagIsChecked.0;
+        const IS_SELECTED = FlutterSemanticsFlag::kFlutterSemanticsFlagIsSelected.0;
+        const IS_BUTTON = FlutterSemanticsFlag::kFlutterSemanticsFlagIsButton.0;
+        const IS_TEXT_FIELD = FlutterSemanticsFlag::kFlutterSemanticsFlagIsTextField.0;
+        const IS_FOCUSED = FlutterSemanticsFlag::kFlutterSemanticsFlagIsFocused.0;
+        const HAS_ENABLED_STATE = FlutterSemanticsFlag::kFlutterSemanticsFlagHasEnabledState.0;
+        const IS_ENABLED = FlutterSemanticsFlag::kFlutterSemanticsFlagIsEnabled.0;
+        const IS_IN_MUTUALLY_EXCLUSIVE_GROUP = FlutterSemanticsFlag::kFlutterSemanticsFlagIsInMutuallyExclusiveGroup.0;
+        const IS_HEADER = FlutterSemanticsFlag::kFlutterSemanticsFlagIsHeader.0;
+        const IS_OBSCURED = FlutterSemanticsFlag::kFlutterSemanticsFlagIsObscured.0;
+        const SCOPES_ROUTE = FlutterSemanticsFlag::kFlutterSemanticsFlagScopesRoute.0;
+        const NAMES_ROUTE = FlutterSemanticsFlag::kFlutterSemanticsFlagNamesRoute.0;
+        const IS_HIDDEN = FlutterSemanticsFlag::kFlutterSemanticsFlagIsHidden.0;
+        const IS_IMAGE = FlutterSemanticsFlag::kFlutterSemanticsFlagIsImage.0;
+        const IS_LIVE_REGION = FlutterSemanticsFlag::kFlutterSemanticsFlagIsLiveRegion.0;
+        const HAS_TOGGLED_STATE = FlutterSemanticsFlag::kFlutterSemanticsFlagHasToggledState.0;
+        const IS_TOGGLED = FlutterSemanticsFlag::kFlutterSemanticsFlagIsToggled.0;
+        const HAS_IMPLICIT_SCROLLING = FlutterSemanticsFlag::kFlutterSemanticsFlagHasImplicitScrolling.0;
+        const IS_READ_ONLY = FlutterSemanticsFlag::kFlutterSemanticsFlagIsReadOnly.0;
+        const IS_FOCUSABLE = FlutterSemanticsFlag::kFlutterSemanticsFlagIsFocusable.0;
+        const IS_LINK = FlutterSemanticsFlag::kFlutterSemanticsFlagIsLink.0;
+    }
+}
+
+impl From<FlutterSemanticsFlag> for SemanticsFlags {
+    fn from(flags: FlutterSemanticsFlag) -> Self {
+        Self::from_bits_truncate(flags.0)
+    }
+}
+
+impl From<SemanticsFlags> for FlutterSemanticsFlag {
+    fn from(flags: SemanticsFlags) -> Self {
+        FlutterSemanticsFlag(flags.bits())
+    }
+}
+
+bitflags! {
+    /// The set of actions a semantics node supports.
+    #[derive(Default)]
+    pub struct SemanticsActions: u32 {
+        const TAP = FlutterSemanticsAction::kFlutterSemanticsActionTap.0;
+        const LONG_PRESS = FlutterSemanticsAction::kFlutterSemanticsActionLongPress.0;
+        const SCROLL_LEFT = FlutterSemanticsAction::kFlutterSemanticsActionScrollLeft.0;
+        const SCROLL_RIGHT = FlutterSemanticsAction::kFlutterSemanticsActionScrollRight.0;
+        const SCROLL_UP = FlutterSemanticsAction::kFlutterSemanticsActionScrollUp.0;
+        const SCROLL_DOWN = FlutterSemanticsAction::kFlutterSemanticsActionScrollDown.0;
+        const INCREASE = FlutterSemanticsAction::kFlutterSemanticsActionIncrease.0;
+        const DECREASE = FlutterSemanticsAction::kFlutterSemanticsActionDecrease.0;
+        const SHOW_ON_SCREEN = FlutterSemanticsAction::kFlutterSemanticsActionShowOnScreen.0;
+        const MOVE_CURSOR_FORWARD_BY_CHARACTER = FlutterSemanticsAction::kFlutterSemanticsActionMoveCursorForwardByCharacter.0;
+        const MOVE_CURSOR_BACKWARD_BY_CHARACTER = FlutterSemanticsAction::kFlutterSemanticsActionMoveCursorBackwardByCharacter.0;
+        const SET_SELECTION = FlutterSemanticsAction::kFlutterSemanticsActionSetSelection.0;
+        const COPY = FlutterSemanticsAction::kFlutterSemanticsActionCopy.0;
+        const CUT = FlutterSemanticsAction::kFlutterSemanticsActionCut.0;
+        const PASTE = FlutterSemanticsAction::kFlutterSemanticsActionPaste.0;
+        const DID_GAIN_ACCESSIBILITY_FOCUS = FlutterSemanticsAction::kFlutterSemanticsActionDidGainAccessibilityFocus.0;
+        const DID_LOSE_ACCESSIBILITY_FOCUS = FlutterSemanticsAction::kFlutterSemanticsActionDidLoseAccessibilityFocus.0;
+        const CUSTOM_ACTION = FlutterSemanticsAction::kFlutterSemanticsActionCustomAction.0;
+        const DISMISS = FlutterSemanticsAction::kFlutterSemanticsActionDismiss.0;
+        const MOVE_CURSOR_FORWARD_BY_WORD = FlutterSemanticsAction::kFlutterSemanticsActionMoveCursorForwardByWord.0;
+        const MOVE_CURSOR_BACKWARD_BY_WORD = FlutterSemanticsAction::kFlutterSemanticsActionMoveCursorBackwardByWord.0;
+        /// Replaces the text of a text field. Not part of the embedder API of
+        /// the engine version these bindings are generated from, newer engines
+        /// use this value.
+        const SET_TEXT = 1 << 21;
+    }
+}
+
+impl From<FlutterSemanticsAction> for SemanticsActions {
+    fn from(flags: FlutterSemanticsAction) -> Self {
+        Self::from_bits_truncate(flags.0)
+    }
+}
+
+impl From<SemanticsActions> for FlutterSemanticsAction {
+    fn from(flags: SemanticsActions) -> Self {
+        FlutterSemanticsAction(flags.bits())
+    }
+}
+
+/// An action that can be performed on a semantics node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SemanticsAction {
+    Tap,
+    LongPress,
+    ScrollLeft,
+    ScrollRight,
+    ScrollUp,
+    ScrollDown,
+    Increase,
+    Decrease,
+    ShowOnScreen,
+    MoveCursorForwardByCharacter {
+        extend_selection: bool,
+    },
+    MoveCursorBackwardByCharacter {
+        extend_selection: bool,
+    },
+    SetSelection {
+        base: i32,
+        extent: i32,
+    },
+    Copy,
+    Cut,
+    Paste,
+    DidGainAccessibilityFocus,
+    DidLoseAccessibilityFocus,
+    CustomAction(CustomSemanticsActionId),
+    Dismiss,
+    MoveCursorForwardByWord {
+        extend_selection: bool,
+    },
+    MoveCursorBackwardByWord {
+        extend_selection: bool,
+    },
+    /// Replaces the text of a text field.
+    SetText(String),
+}
+
+impl SemanticsAction {
+    /// The flag of this action in [`SemanticsActions`].
+    pub fn flag(&self) -> SemanticsActions {
+        match self {
+            SemanticsAction::Tap => SemanticsActions::TAP,
+            SemanticsAction::LongPress => SemanticsActions::LONG_PRESS,
+            SemanticsAction::ScrollLeft => SemanticsActions::SCROLL_LEFT,
+            SemanticsAction::ScrollRight => SemanticsActions::SCROLL_RIGHT,
+            SemanticsAction::ScrollUp => SemanticsActions::SCROLL_UP,
+            SemanticsAction::ScrollDown => SemanticsActions::SCROLL_DOWN,
+            SemanticsAction::Increase => SemanticsActions::INCREASE,
+            SemanticsAction::Decrease => SemanticsActions::DECREASE,
+            SemanticsAction::ShowOnScreen => SemanticsActions::SHOW_ON_SCREEN,
+            SemanticsAction::MoveCursorForwardByCharacter { .. } => {
+                SemanticsActions::MOVE_CURSOR_FORWARD_BY_CHARACTER
+            }
+            SemanticsAction::MoveCursorBackwardByCharacter { .. } => {
+                SemanticsActions::MOVE_CURSOR_BACKWARD_BY_CHARACTER
+            }
+            SemanticsAction::SetSelection { .. } => SemanticsActions::SET_SELECTION,
+            SemanticsAction::Copy => SemanticsActions::COPY,
+            SemanticsAction::Cut => SemanticsActions::CUT,
+            SemanticsAction::Paste => SemanticsActions::PASTE,
+            SemanticsAction::DidGainAccessibilityFocus => {
+                SemanticsActions::DID_GAIN_ACCESSIBILITY_FOCUS
+            }
+            SemanticsAction::DidLoseAccessibilityFocus => {
+                SemanticsActions::DID_LOSE_ACCESSIBILITY_FOCUS
+            }
+            SemanticsAction::CustomAction(_) => SemanticsActions::CUSTOM_ACTION,
+            SemanticsAction::Dismiss => SemanticsActions::DISMISS,
+            SemanticsAction::MoveCursorForwardByWord { .. } => {
+                SemanticsActions::MOVE_CURSOR_FORWARD_BY_WORD
+            }
+            SemanticsAction::MoveCursorBackwardByWord { .. } => {
+                SemanticsActions::MOVE_CURSOR_BACKWARD_BY_WORD
+            }
+            SemanticsAction::SetText(_) => SemanticsActions::SET_TEXT,
+        }
+    }
+
+    /// The arguments of the action, encoded like the framework expects them.
+    pub(crate) fn encode_args(&self) -> Vec<u8> {
+        let args = match self {
+            SemanticsAction::MoveCursorForwardByCharacter { extend_selection }
+            | SemanticsAction::MoveCursorBackwardByCharacter { extend_selection }
+            | SemanticsAction::MoveCursorForwardByWord { extend_selection }
+            | SemanticsAction::MoveCursorBackwardByWord { extend_selection } => {
+                Value::Boolean(*extend_selection)
+            }
+            SemanticsAction::SetSelection { base, extent } => {
+                let mut map = ValueMap::new();
+                map.insert("base", Value::I32(*base));
+                map.insert("extent", Value::I32(*extent));
+                Value::Map(map)
+            }
+            SemanticsAction::CustomAction(id) => Value::I32(*id),
+            SemanticsAction::SetText(text) => Value::String(text.clone()),
+            _ => return Vec::new(),
+        };
+        CODEC.encode_message(&args)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextDirection {
+    Unknown,
+    RTL,
+    LTR,
+}
+
+impl From<FlutterTextDirection> for TextDirection {
+    fn from(direction: FlutterTextDirection) -> Self {
+        match direction {
+            FlutterTextDirection::kFlutterTextDirectionUnknown => TextDirection::Unknown,
+            FlutterTextDirection::kFlutterTextDirectionRTL => TextDirection::RTL,
+            FlutterTextDirection::kFlutterTextDirectionLTR => TextDirection::LTR,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SemanticsNode {
+    pub id: SemanticsNodeId,
+    pub flags: SemanticsFlags,
+    pub actions: SemanticsActions,
+    pub text_selection_base: i32,
+    pub text_selection_extent: i32,
+    pub scroll_child_count: i32,
+    pub scroll_index: i32,
+    pub scroll_position: f64,
+    pub scroll_extent_max: f64,
+    pub scroll_extent_min: f64,
+    pub elevation: f64,
+    pub thickness: f64,
+    pub label: String,
+    pub hint: String,
+    pub value: String,
+    pub increased_value: String,
+    pub decreased_value: String,
+    pub text_direction: TextDirection,
+    /// The bounding box of the node in its own coordinate system.
+    pub rect: Rect,
+    /// The transform from the coordinate system of the node to the one of its
+    /// parent.
+    pub transform: Transformation,
+    pub children_in_traversal_order: Vec<SemanticsNodeId>,
+    pub children_in_hit_test_order: Vec<SemanticsNodeId>,
+    pub custom_accessibility_actions: Vec<CustomSemanticsActionId>,
+    /// The platform view of this node, or 0.
+    pub platform_view_id: i64,
+}
+
+impl SemanticsNode {
+    pub fn has_flag(&self, flag: SemanticsFlags) -> bool {
+        self.flags.contains(flag)
+    }
+
+    pub fn supports(&self, action: &SemanticsAction) -> bool {
+        self.actions.contains(action.flag())
+    }
+
+    pub(crate) unsafe fn from_ffi(node: &FlutterSemanticsNode) -> Self {
+        Self {
+            id: node.id,
+            flags: node.flags.into(),
+            actions: node.actions.into(),
+            text_selection_base: node.text_selection_base,
+            text_selection_extent: node.text_selection_extent,
+            scroll_child_count: node.scroll_child_count,
+            scroll_index: node.scroll_index,
+            scroll_position: node.scroll_position,
+            scroll_extent_max: node.scroll_extent_max,
+            scroll_extent_min: node.scroll_extent_min,
+            elevation: node.elevation,
+            thickness: node.thickness,
+            label: to_string(node.label),
+            hint: to_string(node.hint),
+            value: to_string(node.value),
+            increased_value: to_string(node.increased_value),
+            decreased_value: to_string(node.decreased_value),
+            text_direction: node.text_direction.into(),
+            rect: node.rect.into(),
+            transform: node.transform.into(),
+            children_in_traversal_order: to_vec(node.children_in_traversal_order, node.child_count),
+            children_in_hit_test_order: to_vec(node.children_in_hit_test_order, node.child_count),
+            custom_accessibility_actions: to_vec(
+                node.custom_accessibility_actions,
+                node.custom_accessibility_actions_count,
+            ),
+            platform_view_id: node.platform_view_id,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomSemanticsAction {
+    pub id: CustomSemanticsActionId,
+    /// The standard action this action overrides, if any.
+    pub override_action: SemanticsActions,
+    pub label: String,
+    pub hint: String,
+}
+
+impl CustomSemanticsAction {
+    pub(crate) unsafe fn from_ffi(action: &FlutterSemanticsCustomAction) -> Self {
+        Self {
+            id: action.id,
+            override_action: action.override_action.into(),
+            label: to_string(action.label),
+            hint: to_string(action.hint),
+        }
+    }
+}
+
+unsafe fn to_string(s: *const c_char) -> String {
+    if s.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(s).to_string_lossy().into_owned()
+    }
+}
+
+unsafe fn to_vec(ptr: *const i32, len: usize) -> Vec<i32> {
+    if ptr.is_null() {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(ptr, len).to_vec()
+    }
+}
+
+/// The semantics nodes of the app, kept in sync with the engine.
+#[derive(Clone, Default)]
+pub struct SemanticsTree {
+    nodes: HashMap<SemanticsNodeId, SemanticsNode>,
+    custom_actions: HashMap<CustomSemanticsActionId, CustomSemanticsAction>,
+    pending_nodes: Vec<SemanticsNode>,
+    pending_custom_actions: Vec<CustomSemanticsAction>,
+}
+
+impl SemanticsTree {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn root(&self) -> Option<&SemanticsNode> {
+        self.node(ROOT_NODE_ID)
+    }
+
+    pub fn node(&self, id: SemanticsNodeId) -> Option<&SemanticsNode> {
+        self.nodes.get(&id)
+    }
+
+    pub fn custom_action(&self, id: CustomSemanticsActionId) -> Option<&CustomSemanticsAction> {
+        self.custom_actions.get(&id)
+    }
+
+    /// The children of a node in traversal order.
+    pub fn children<'a>(
+        &'a self,
+        node: &'a SemanticsNode,
+    ) -> impl Iterator<Item = &'a SemanticsNode> {
+        node.children_in_traversal_order
+            .iter()
+            .filter_map(move |id| self.nodes.get(id))
+    }
+
+    /// All nodes reachable from the root, in depth first traversal order.
+    pub fn nodes(&self) -> Vec<&SemanticsNode> {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        let mut stack: Vec<&SemanticsNode> = self.root().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            nodes.push(node);
+            stack.extend(self.children(node).collect::<Vec<_>>().into_iter().rev());
+        }
+        nodes
+    }
+
+    /// The first node in traversal order with the given label.
+    pub fn find_by_label(&self, label: &str) -> Option<&SemanticsNode> {
+        self.nodes().into_iter().find(|node| node.label == label)
+    }
+
+    /// All nodes in traversal order with the given label.
+    pub fn find_all_by_label(&self, label: &str) -> Vec<&SemanticsNode> {
+        self.nodes()
+            .into_iter()
+            .filter(|node| node.label == label)
+            .collect()
+    }
+
+    /// Adds or replaces a node right away, to build a tree by hand.
+    pub fn insert_node(&mut self, node: SemanticsNode) {
+        self.nodes.insert(node.id, node);
+    }
+
+    /// Adds or replaces a custom action right away, to build a tree by hand.
+    pub fn insert_custom_action(&mut self, action: CustomSemanticsAction) {
+        self.custom_actions.insert(action.id, action);
+    }
+
+    /// Queues a node update. Returns true if the update completed a batch and
+    /// the tree changed.
+    pub(crate) fn update_node(&mut self, node: &FlutterSemanticsNode) -> bool {
+        if node.id == unsafe { flutter_engine_sys::kFlutterSemanticsNodeIdBatchEnd } {
+            for node in self.pending_nodes.drain(..) {
+                self.nodes.insert(node.id, node);
+            }
+            self.remove_detached_nodes();
+            true
+        } else {
+            let node = unsafe { SemanticsNode::from_ffi(node) };
+            self.pending_nodes.push(node);
+            false
+        }
+    }
+
+    /// Queues a custom action update. Returns true if the update completed a
+    /// batch.
+    pub(crate) fn update_custom_action(&mut self, action: &FlutterSemanticsCustomAction) -> bool {
+        if action.id == unsafe { flutter_engine_sys::kFlutterSemanticsCustomActionIdBatchEnd } {
+            for action in self.pending_custom_actions.drain(..) {
+                self.custom_actions.insert(action.id, action);
+            }
+            true
+        } else {
+            let action = unsafe { CustomSemanticsAction::from_ffi(action) };
+            self.pending_custom_actions.push(action);
+            false
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.nodes.clear();
+        self.custom_actions.clear();
+        self.pending_nodes.clear();
+        self.pending_custom_actions.clear();
+    }
+
+    /// The engine only sends nodes that changed, nodes which are no longer
+    /// reachable from the root have been removed by the framework.
+    fn remove_detached_nodes(&mut self) {
+        let reachable: HashSet<SemanticsNodeId> = self.nodes().iter().map(|node| node.id).collect();
+        self.nodes.retain(|id, _| reachable.contains(id));
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{run_engine, Call, MockHandler};
+    use std::ffi::CString;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::{mem, ptr};
+
+    fn node(id: i32, label: &CString, children: &[i32]) -> FlutterSemanticsNode {
+        let mut node: FlutterSemanticsNode = unsafe { mem::zeroed() };
+        node.struct_size = mem::size_of::<FlutterSemanticsNode>();
+        node.id = id;
+        node.actions = SemanticsActions::TAP.into();
+        node.label = label.as_ptr();
+        node.text_direction = FlutterTextDirection::kFlutterTextDirectionLTR;
+        node.child_count = children.len();
+        node.children_in_traversal_order = children.as_ptr();
+        node.children_in_hit_test_order = children.as_ptr();
+        node.custom_accessibility_actions = ptr::null();
+        node
+    }
+
+    fn batch_end() -> FlutterSemanticsNode {
+        let mut node: FlutterSemanticsNode = unsafe { mem::zeroed() };
+        node.id = unsafe { flutter_engine_sys::kFlutterSemanticsNodeIdBatchEnd };
+        node
+    }
+
+    #[test]
+    fn test_semantics_tree_updates() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.update_semantics_enabled(true);
+
+        let (root, ok, cancel) = (
+            CString::new("").unwrap(),
+            CString::new("OK").unwrap(),
+            CString::new("Cancel").unwrap(),
+        );
+        mock.update_semantics_node(&node(0, &root, &[1, 2]));
+        mock.update_semantics_node(&node(1, &ok, &[]));
+        mock.update_semantics_node(&node(2, &cancel, &[]));
+        assert!(engine.with_semantics_tree(|tree| tree.is_empty()));
+
+        mock.update_semantics_node(&batch_end());
+        let ids = engine.with_semantics_tree(|tree| {
+            tree.nodes().iter().map(|node| node.id).collect::<Vec<_>>()
+        });
+        assert_eq!(ids, vec![0, 1, 2]);
+
+        // detaching a node removes it from the tree
+        mock.update_semantics_node(&node(0, &root, &[2]));
+        mock.update_semantics_node(&batch_end());
+        let cancel_id = engine.with_semantics_tree(|tree| {
+            assert_eq!(tree.len(), 2);
+            assert!(tree.find_by_label("OK").is_none());
+            let cancel = tree.find_by_label("Cancel").unwrap();
+            assert!(cancel.supports(&SemanticsAction::Tap));
+            cancel.id
+        });
+
+        engine.dispatch_semantics_action(cancel_id, SemanticsAction::Tap);
+        engine.dispatch_semantics_action(
+            cancel_id,
+            SemanticsAction::MoveCursorForwardByWord {
+                extend_selection: true,
+            },
+        );
+        engine.dispatch_semantics_action(cancel_id, SemanticsAction::SetText("Abort".into()));
+        let actions: Vec<_> = mock
+            .calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                Call::DispatchSemanticsAction { id, action, data } => Some((id, action, data)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (2, SemanticsActions::TAP.bits(), vec![]),
+                (
+                    2,
+                    SemanticsActions::MOVE_CURSOR_FORWARD_BY_WORD.bits(),
+                    CODEC.encode_message(&Value::Boolean(true))
+                ),
+                (
+                    2,
+                    SemanticsActions::SET_TEXT.bits(),
+                    CODEC.encode_message(&Value::String("Abort".into()))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantics_handler_can_use_the_engine() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.update_semantics_enabled(true);
+
+        // the tree of the engine must not be locked while the handler runs
+        let updates = Arc::new(AtomicUsize::new(0));
+        let (weak, counter) = (engine.downgrade(), updates.clone());
+        handler.on_semantics_updated(move |tree| {
+            let engine = weak.upgrade().unwrap();
+            assert_eq!(engine.with_semantics_tree(|own| own.len()), tree.len());
+            engine.update_semantics_enabled(false);
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let label = CString::new("OK").unwrap();
+        mock.update_semantics_node(&node(0, &label, &[]));
+        mock.update_semantics_node(&batch_end());
+        assert_eq!(updates.load(Ordering::SeqCst), 1);
+        assert!(engine.with_semantics_tree(|tree| tree.is_empty()));
+    }
+}
@@ -0,0 +1,320 @@
+//! Ahead of time compiled Dart code for release builds.
+//!
+//! Debug builds of the engine run the `kernel_blob.bin` from the assets
+//! directory. Release builds can only run the AOT snapshots produced by
+//! `gen_snapshot`, which have to be loaded with
+//! `FlutterEngine::set_aot_snapshot` before the engine is run.
+
+use std::io;
+use std::path::PathBuf;
+use std::ptr;
+
+const VM_SNAPSHOT_DATA: &str = "vm_snapshot_data";
+const VM_SNAPSHOT_INSTRUCTIONS: &str = "vm_snapshot_instr";
+const ISOLATE_SNAPSHOT_DATA: &str = "isolate_snapshot_data";
+const ISOLATE_SNAPSHOT_INSTRUCTIONS: &str = "isolate_snapshot_instr";
+
+/// Where to find the AOT snapshot of an app.
+#[derive(Clone, Debug)]
+pub enum AotSnapshot {
+    /// An ELF library exporting the snapshot symbols, usually `app.so`.
+    Elf(PathBuf),
+    /// A directory containing the `vm_snapshot_data`, `vm_snapshot_instr`,
+    /// `isolate_snapshot_data` and `isolate_snapshot_instr` blobs.
+    Blobs(PathBuf),
+    /// Snapshots that are already in memory, e.g. linked into the executable.
+    /// The instruction buffers have to be mapped executable.
+    Memory {
+        vm_snapshot_data: &'static [u8],
+        vm_snapshot_instructions: &'static [u8],
+        isolate_snapshot_data: &'static [u8],
+        isolate_snapshot_instructions: &'static [u8],
+    },
+}
+
+/// Whether the linked engine is a release build, which requires an
+/// [`AotSnapshot`].
+pub fn runs_aot_compiled_dart_code() -> bool {
+    unsafe { flutter_engine_sys::FlutterEngineRunsAOTCompiledDartCode() }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SnapshotBuffer {
+    pub ptr: *const u8,
+    /// May be 0 when the buffer is a symbol in a loaded library.
+    pub size: usize,
+}
+
+impl SnapshotBuffer {
+    pub const NULL: SnapshotBuffer = SnapshotBuffer {
+        ptr: ptr::null(),
+        size: 0,
+    };
+
+    fn from_slice(slice: &'static [u8]) -> Self {
+        Self {
+            ptr: slice.as_ptr(),
+            size: slice.len(),
+        }
+    }
+}
+
+/// A snapshot kept in memory for as long as the engine runs.
+pub(crate) struct LoadedSnapshot {
+    vm_snapshot_data: SnapshotBuffer,
+    vm_snapshot_instructions: SnapshotBuffer,
+    isolate_snapshot_data: SnapshotBuffer,
+    isolate_snapshot_instructions: SnapshotBuffer,
+    _storage: Storage,
+}
+
+unsafe impl Send for LoadedSnapshot {}
+
+unsafe impl Sync for LoadedSnapshot {}
+
+/// Owns the memory of the snapshot buffers until dropped.
+#[allow(dead_code)]
+enum Storage {
+    Static,
+    #[cfg(unix)]
+    Library(unix::Library),
+    #[cfg(unix)]
+    Mappings(Vec<unix::Mapping>),
+}
+
+impl LoadedSnapshot {
+    pub fn load(snapshot: AotSnapshot) -> io::Result<Self> {
+        match snapshot {
+            AotSnapshot::Memory {
+                vm_snapshot_data,
+                vm_snapshot_instructions,
+                isolate_snapshot_data,
+                isolate_snapshot_instructions,
+            } => Ok(Self {
+                vm_snapshot_data: SnapshotBuffer::from_slice(vm_snapshot_data),
+                vm_snapshot_instructions: SnapshotBuffer::from_slice(vm_snapshot_instructions),
+                isolate_snapshot_data: SnapshotBuffer::from_slice(isolate_snapshot_data),
+                isolate_snapshot_instructions: SnapshotBuffer::from_slice(
+                    isolate_snapshot_instructions,
+                ),
+                _storage: Storage::Static,
+            }),
+            #[cfg(unix)]
+            AotSnapshot::Elf(path) => {
+                let library = unix::Library::open(&path)?;
+                let symbol = |name| {
+                    library.symbol(name).map(|ptr| SnapshotBuffer {
+                        ptr: ptr as *const u8,
+                        size: 0,
+                    })
+                };
+                Ok(Self {
+                    vm_snapshot_data: symbol("_kDartVmSnapshotData")?,
+                    vm_snapshot_instructions: symbol("_kDartVmSnapshotInstructions")?,
+                    isolate_snapshot_data: symbol("_kDartIsolateSnapshotData")?,
+                    isolate_snapshot_instructions: symbol("_kDartIsolateSnapshotInstructions")?,
+                    _storage: Storage::Library(library),
+                })
+            }
+            #[cfg(unix)]
+            AotSnapshot::Blobs(dir) => {
+                let mappings = vec![
+                    unix::Mapping::map(&dir.join(VM_SNAPSHOT_DATA), false)?,
+                    unix::Mapping::map(&dir.join(VM_SNAPSHOT_INSTRUCTIONS), true)?,
+                    unix::Mapping::map(&dir.join(ISOLATE_SNAPSHOT_DATA), false)?,
+                    unix::Mapping::map(&dir.join(ISOLATE_SNAPSHOT_INSTRUCTIONS), true)?,
+                ];
+                Ok(Self {
+                    vm_snapshot_data: mappings[0].buffer(),
+                    vm_snapshot_instructions: mappings[1].buffer(),
+                    isolate_snapshot_data: mappings[2].buffer(),
+                    isolate_snapshot_instructions: mappings[3].buffer(),
+                    _storage: Storage::Mappings(mappings),
+                })
+            }
+            #[cfg(not(unix))]
+            AotSnapshot::Elf(_) | AotSnapshot::Blobs(_) => Err(io::Error::other(
+                "loading AOT snapshots from disk is not supported on this platform",
+            )),
+        }
+    }
+
+    /// The VM data, VM instructions, isolate data and isolate instructions
+    /// buffers.
+    pub fn buffers(&self) -> [SnapshotBuffer; 4] {
+        [
+            self.vm_snapshot_data,
+            self.vm_snapshot_instructions,
+            self.isolate_snapshot_data,
+            self.isolate_snapshot_instructions,
+        ]
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::SnapshotBuffer;
+    use std::ffi::{CStr, CString};
+    use std::fs::File;
+    use std::io;
+    use std::os::raw::c_void;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+    use std::ptr;
+
+    fn dl_error() -> io::Error {
+        let message = unsafe {
+            let error = libc::dlerror();
+            if error.is_null() {
+                "unknown error".into()
+            } else {
+                CStr::from_ptr(error).to_string_lossy().into_owned()
+            }
+        };
+        io::Error::other(message)
+    }
+
+    pub struct Library {
+        handle: *mut c_void,
+    }
+
+    impl Library {
+        pub fn open(path: &Path) -> io::Result<Self> {
+            let path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+            if handle.is_null() {
+                return Err(dl_error());
+            }
+            Ok(Self { handle })
+        }
+
+        pub fn symbol(&self, name: &str) -> io::Result<*mut c_void> {
+            let name = CString::new(name).unwrap();
+            let symbol = unsafe { libc::dlsym(self.handle, name.as_ptr()) };
+            if symbol.is_null() {
+                return Err(dl_error());
+            }
+            Ok(symbol)
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            unsafe {
+                libc::dlclose(self.handle);
+            }
+        }
+    }
+
+    /// A read-only memory mapping of a whole file.
+    pub struct Mapping {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    impl Mapping {
+        pub fn map(path: &Path, executable: bool) -> io::Result<Self> {
+            let file = File::open(path)?;
+            let len = file.metadata()?.len() as usize;
+            if len == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is empty", path.display()),
+                ));
+            }
+            let prot = if executable {
+                libc::PROT_READ | libc::PROT_EXEC
+            } else {
+                libc::PROT_READ
+            };
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    prot,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { ptr, len })
+        }
+
+        pub fn buffer(&self) -> SnapshotBuffer {
+            SnapshotBuffer {
+                ptr: self.ptr as *const u8,
+                size: self.len,
+            }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_engine, set_runs_aot_compiled_dart_code, MockHandler};
+    use crate::{FlutterEngine, FlutterEngineHandler, RunError};
+    use std::sync::{Arc, Weak};
+
+    static SNAPSHOT: [u8; 4] = [1, 2, 3, 4];
+
+    fn memory_snapshot() -> AotSnapshot {
+        AotSnapshot::Memory {
+            vm_snapshot_data: &SNAPSHOT[0..1],
+            vm_snapshot_instructions: &SNAPSHOT[1..2],
+            isolate_snapshot_data: &SNAPSHOT[2..3],
+            isolate_snapshot_instructions: &SNAPSHOT[3..4],
+        }
+    }
+
+    fn engine(handler: &Arc<MockHandler>) -> FlutterEngine {
+        FlutterEngine::new(
+            Arc::downgrade(handler) as Weak<dyn FlutterEngineHandler>,
+            PathBuf::from("assets"),
+        )
+    }
+
+    #[test]
+    fn test_aot_snapshot_is_passed_to_engine() {
+        set_runs_aot_compiled_dart_code(true);
+        let handler = MockHandler::new();
+        let engine = engine(&handler);
+        engine.set_aot_snapshot(memory_snapshot()).unwrap();
+        engine.run(&[]).unwrap();
+        let expected: Vec<_> = SNAPSHOT.iter().map(|byte| byte as *const u8).collect();
+        assert_eq!(mock_engine(&engine).aot_snapshot().to_vec(), expected);
+        set_runs_aot_compiled_dart_code(false);
+    }
+
+    #[test]
+    fn test_snapshot_mismatch() {
+        let handler = MockHandler::new();
+
+        set_runs_aot_compiled_dart_code(true);
+        assert_eq!(engine(&handler).run(&[]), Err(RunError::AotSnapshotMissing));
+
+        set_runs_aot_compiled_dart_code(false);
+        let jit_engine = engine(&handler);
+        jit_engine.set_aot_snapshot(memory_snapshot()).unwrap();
+        assert_eq!(jit_engine.run(&[]), Err(RunError::UnexpectedAotSnapshot));
+    }
+
+    #[test]
+    fn test_missing_blobs() {
+        let result = LoadedSnapshot::load(AotSnapshot::Blobs(PathBuf::from("missing")));
+        assert!(result.is_err());
+    }
+}
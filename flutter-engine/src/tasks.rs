@@ -5,7 +5,6 @@ use parking_lot::{Mutex, MutexGuard};
 use priority_queue::PriorityQueue;
 use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
-use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, Weak};
 use std::thread;
 use std::thread::ThreadId;
@@ -20,6 +19,7 @@ pub(crate) struct TaskRunnerInner {
     pub(crate) handler: Weak<dyn TaskRunnerHandler>,
     thread_id: ThreadId,
     tasks: PriorityQueue<Task, TaskPriority>,
+    next_order: u64,
 }
 
 pub struct TaskRunner {
@@ -50,6 +50,7 @@ impl TaskRunner {
                 handler,
                 thread_id,
                 tasks: PriorityQueue::new(),
+                next_order: 0,
             })),
         }
     }
@@ -59,6 +60,17 @@ impl TaskRunner {
         inner.engine = engine;
     }
 
+    /// Makes the current thread the one tasks run on.
+    pub(crate) fn bind_to_current_thread(&self) {
+        let thread_id = thread::current().id();
+        debug!("task runner bound to thread {:?}", thread_id);
+        self.inner.lock().thread_id = thread_id;
+    }
+
+    pub(crate) fn engine(&self) -> FlutterEngineWeakRef {
+        self.inner.lock().engine.clone()
+    }
+
     pub fn execute_tasks(&self) -> Option<Instant> {
         let now = Instant::now();
         let mut expired_tasks = Vec::new();
@@ -108,11 +120,11 @@ impl TaskRunner {
         task: FlutterTask,
         target_time_nanos: u64,
     ) {
-        static GLOBAL_ORDER: AtomicU64 = AtomicU64::new(0);
         let task_priority = TaskPriority {
             time: Self::flutter_time_to_instant(target_time_nanos),
-            order: GLOBAL_ORDER.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            order: guard.next_order,
         };
+        guard.next_order += 1;
         let task = Task { task };
         let tasks = &mut guard.tasks;
         tasks.push(task, task_priority);
@@ -147,10 +159,12 @@ struct Task {
     task: FlutterTask,
 }
 
+// PriorityQueue pops the greatest priority first, so the earliest task has
+// to compare as the greatest.
 impl Ord for TaskPriority {
     fn cmp(&self, other: &Self) -> Ordering {
-        match self.time.cmp(&other.time) {
-            Ordering::Equal => self.order.cmp(&other.order),
+        match other.time.cmp(&self.time) {
+            Ordering::Equal => other.order.cmp(&self.order),
             ord => ord,
         }
     }
@@ -176,3 +190,72 @@ impl Hash for Task {
         std::ptr::hash(self.task.runner, state);
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{run_engine, Call, MockHandler};
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct CountingHandler(AtomicUsize);
+
+    impl TaskRunnerHandler for CountingHandler {
+        fn wake(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_task_runner_pops_earliest_task_first() {
+        let handler = Arc::new(CountingHandler::default());
+        let runner = TaskRunner::new(Arc::downgrade(&handler) as Weak<dyn TaskRunnerHandler>);
+
+        let now = unsafe { FlutterEngineGetCurrentTime() };
+        let mut inner = runner.inner.lock();
+        for (task, target_time) in &[
+            (1, now + 2_000_000_000),
+            (2, now),
+            (3, now + 1_000_000_000),
+            (4, now),
+        ] {
+            let task = FlutterTask {
+                runner: ptr::null_mut(),
+                task: *task,
+            };
+            TaskRunner::post_task(&mut inner, task, *target_time);
+        }
+        assert_eq!(handler.0.load(Ordering::SeqCst), 4);
+
+        let mut tasks = Vec::new();
+        while let Some((task, _)) = inner.tasks.pop() {
+            tasks.push(task.task.task);
+        }
+        assert_eq!(tasks, vec![2, 4, 3, 1]);
+    }
+
+    #[test]
+    fn test_tasks_run_in_order() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        assert!(mock.runs_task_on_current_thread());
+
+        let now = unsafe { FlutterEngineGetCurrentTime() };
+        mock.post_task(2, now);
+        mock.post_task(1, now);
+        mock.post_task(3, now + 60_000_000_000);
+        assert_eq!(handler.wake_count(), 3);
+
+        assert!(engine.execute_platform_tasks().is_some());
+        let tasks: Vec<u64> = mock
+            .calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                Call::RunTask(task) => Some(task),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tasks, vec![2, 1]);
+    }
+}
@@ -187,3 +187,50 @@ unsafe extern "C" fn texture_destruction_callback(user_data: *mut c_void) {
     let user_data = Box::from_raw(user_data);
     user_data();
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::TextureFrame;
+    use crate::mock::{run_engine, Call, MockHandler};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_texture_lifecycle() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        mock.take_calls();
+
+        let texture = engine.create_texture();
+        let id = texture.id();
+        let destroyed = Arc::new(AtomicBool::new(false));
+        let flag = destroyed.clone();
+        texture.post_frame(TextureFrame::new(0, 0, 0, move || {
+            flag.store(true, Ordering::SeqCst)
+        }));
+        texture.post_frame(TextureFrame::new(0, 0, 0, || {}));
+        handler.pump(&engine);
+        assert!(destroyed.load(Ordering::SeqCst));
+        drop(texture);
+
+        let calls: Vec<_> = mock
+            .take_calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                Call::RegisterExternalTexture(id) => Some(("register", id)),
+                Call::MarkExternalTextureFrameAvailable(id) => Some(("frame", id)),
+                Call::UnregisterExternalTexture(id) => Some(("unregister", id)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                ("register", id),
+                ("frame", id),
+                ("frame", id),
+                ("unregister", id)
+            ]
+        );
+    }
+}
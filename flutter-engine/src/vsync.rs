@@ -0,0 +1,261 @@
+//! Pacing of frames.
+//!
+//! Without a [`VsyncSource`] the engine schedules frames on its own. With one,
+//! every frame waits for a [`VsyncRequest`] to be fired, which allows aligning
+//! frames with the refresh of the display.
+
+use crate::FlutterEngineWeakRef;
+use crossbeam_channel::{unbounded, Sender};
+use flutter_engine_sys::FlutterEngineGetCurrentTime;
+use parking_lot::Mutex;
+use std::time::Duration;
+use std::{mem, thread};
+
+pub type VsyncBaton = isize;
+
+pub trait VsyncSource: Send + Sync {
+    /// Called from an engine managed thread when the engine waits for the
+    /// next vsync. Every request has to be fired exactly once.
+    fn request_vsync(&self, request: VsyncRequest);
+}
+
+/// A pending request of the engine for the next vsync.
+///
+/// A request that is dropped without being fired, e.g. because its source
+/// went away, fires right away so the engine does not wait for it forever.
+pub struct VsyncRequest {
+    engine: FlutterEngineWeakRef,
+    baton: VsyncBaton,
+    fired: bool,
+}
+
+impl VsyncRequest {
+    pub(crate) fn new(engine: FlutterEngineWeakRef, baton: VsyncBaton) -> Self {
+        Self {
+            engine,
+            baton,
+            fired: false,
+        }
+    }
+
+    pub fn baton(&self) -> VsyncBaton {
+        self.baton
+    }
+
+    /// Tells the engine that a vsync occurred at `frame_start_time_nanos` and
+    /// the frame has to be ready by `frame_target_time_nanos`. Both use the
+    /// clock of [`current_time_nanos`].
+    ///
+    /// Can be called from any thread.
+    pub fn fire(mut self, frame_start_time_nanos: u64, frame_target_time_nanos: u64) {
+        self.fired = true;
+        if let Some(engine) = self.engine.upgrade() {
+            engine.on_vsync(self.baton, frame_start_time_nanos, frame_target_time_nanos);
+        }
+    }
+}
+
+impl Drop for VsyncRequest {
+    fn drop(&mut self) {
+        if self.fired {
+            return;
+        }
+        if let Some(engine) = self.engine.upgrade() {
+            // the refresh rate of the source is not known here
+            let now = current_time_nanos();
+            engine.on_vsync(self.baton, now, now + frame_interval_nanos(60.0));
+        }
+    }
+}
+
+/// The current time of the clock used by the engine in nanoseconds.
+pub fn current_time_nanos() -> u64 {
+    unsafe { FlutterEngineGetCurrentTime() }
+}
+
+fn frame_interval_nanos(refresh_rate: f64) -> u64 {
+    assert!(
+        refresh_rate.is_finite() && refresh_rate > 0.0,
+        "Refresh rate must be positive and finite"
+    );
+    (Duration::from_secs_f64(1.0 / refresh_rate).as_nanos() as u64).max(1)
+}
+
+/// Fires requests on the ticks of a fixed refresh rate.
+pub struct TimerVsync {
+    sender: Sender<VsyncRequest>,
+}
+
+impl TimerVsync {
+    /// Starts a timer thread ticking `refresh_rate` times per second. The
+    /// thread exits once the source is dropped.
+    pub fn new(refresh_rate: f64) -> Self {
+        let interval = frame_interval_nanos(refresh_rate);
+        let (sender, receiver) = unbounded::<VsyncRequest>();
+        thread::Builder::new()
+            .name("vsync timer".into())
+            .spawn(move || {
+                let origin = current_time_nanos();
+                for request in receiver.iter() {
+                    let now = current_time_nanos();
+                    let frame_start = origin + ((now - origin) / interval + 1) * interval;
+                    thread::sleep(Duration::from_nanos(frame_start - now));
+                    request.fire(frame_start, frame_start + interval);
+                }
+            })
+            .expect("Failed to spawn vsync timer thread");
+        Self { sender }
+    }
+}
+
+impl VsyncSource for TimerVsync {
+    fn request_vsync(&self, request: VsyncRequest) {
+        self.sender.send(request).ok();
+    }
+}
+
+/// Holds requests until the next frame is started explicitly, e.g. to step
+/// through frames in tests.
+pub struct ManualVsync {
+    interval: u64,
+    state: Mutex<ManualVsyncState>,
+}
+
+struct ManualVsyncState {
+    frame_start: u64,
+    pending: Vec<VsyncRequest>,
+}
+
+impl ManualVsync {
+    /// Creates a source whose frames are `1 / refresh_rate` seconds apart,
+    /// starting at the current time.
+    pub fn new(refresh_rate: f64) -> Self {
+        Self {
+            interval: frame_interval_nanos(refresh_rate),
+            state: Mutex::new(ManualVsyncState {
+                frame_start: current_time_nanos(),
+                pending: Vec::new(),
+            }),
+        }
+    }
+
+    /// The start time of the next frame.
+    pub fn frame_start_time_nanos(&self) -> u64 {
+        self.state.lock().frame_start
+    }
+
+    pub fn pending(&self) -> usize {
+        self.state.lock().pending.len()
+    }
+
+    /// Fires all pending requests and advances the clock by one frame.
+    /// Returns the number of fired requests.
+    pub fn advance_frame(&self) -> usize {
+        let (requests, frame_start) = {
+            let mut state = self.state.lock();
+            let frame_start = state.frame_start;
+            state.frame_start += self.interval;
+            (mem::take(&mut state.pending), frame_start)
+        };
+        let count = requests.len();
+        for request in requests {
+            request.fire(frame_start, frame_start + self.interval);
+        }
+        count
+    }
+}
+
+impl VsyncSource for ManualVsync {
+    fn request_vsync(&self, request: VsyncRequest) {
+        self.state.lock().pending.push(request);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_engine, run_engine, Call, MockHandler};
+    use crate::{FlutterEngine, FlutterEngineHandler};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Weak};
+
+    #[test]
+    fn test_manual_vsync() {
+        let handler = MockHandler::new();
+        let vsync = Arc::new(ManualVsync::new(60.0));
+        let engine = FlutterEngine::new(
+            Arc::downgrade(&handler) as Weak<dyn FlutterEngineHandler>,
+            PathBuf::from("assets"),
+        );
+        engine.set_vsync_source(vsync.clone());
+        engine.run(&[]).unwrap();
+        let mock = mock_engine(&engine);
+
+        mock.request_vsync(7);
+        assert_eq!(vsync.pending(), 1);
+        let frame_start = vsync.frame_start_time_nanos();
+        assert_eq!(vsync.advance_frame(), 1);
+        assert_eq!(vsync.pending(), 0);
+        assert_eq!(vsync.advance_frame(), 0);
+
+        let interval = frame_interval_nanos(60.0);
+        let vsyncs: Vec<_> = mock
+            .calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                Call::OnVsync {
+                    baton,
+                    frame_start_time_nanos,
+                    frame_target_time_nanos,
+                } => Some((baton, frame_start_time_nanos, frame_target_time_nanos)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vsyncs, vec![(7, frame_start, frame_start + interval)]);
+    }
+
+    #[test]
+    fn test_dropped_requests_fire() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let vsync = ManualVsync::new(60.0);
+        vsync.request_vsync(VsyncRequest::new(engine.downgrade(), 5));
+
+        drop(vsync);
+        let batons: Vec<_> = mock
+            .calls()
+            .into_iter()
+            .filter_map(|call| match call {
+                Call::OnVsync { baton, .. } => Some(baton),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(batons, vec![5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Refresh rate must be positive and finite")]
+    fn test_infinite_refresh_rate() {
+        ManualVsync::new(f64::INFINITY);
+    }
+
+    #[test]
+    fn test_fire_off_platform_thread() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let request = VsyncRequest::new(engine.downgrade(), 3);
+
+        // The vsync reaches the engine without waiting for the platform thread
+        // to run its tasks.
+        thread::spawn(move || request.fire(10, 20)).join().unwrap();
+        let fired = mock.calls().into_iter().any(|call| match call {
+            Call::OnVsync {
+                baton,
+                frame_start_time_nanos,
+                frame_target_time_nanos,
+            } => (baton, frame_start_time_nanos, frame_target_time_nanos) == (3, 10, 20),
+            _ => false,
+        });
+        assert!(fired);
+    }
+}
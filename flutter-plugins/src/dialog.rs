@@ -106,3 +106,36 @@ pub enum MessageBoxIcon {
     Error,
     Question,
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::{json_codec::CODEC, MethodCodec};
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[test]
+    fn test_invalid_calls_open_no_dialog() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.add_plugin(DialogPlugin::default());
+
+        let call = MethodCall {
+            method: "message_box_ok".into(),
+            args: json_value!({ "title": "missing message" }),
+        };
+        let handle = mock.send_platform_message(CHANNEL_NAME, &CODEC.encode_method_call(&call));
+        handler.pump(&engine);
+        match CODEC.decode_envelope(&mock.response(handle).unwrap()) {
+            Ok(MethodCallResult::Err { .. }) => {}
+            _ => panic!("Expected an error envelope"),
+        }
+
+        let call = MethodCall {
+            method: "unknown".into(),
+            args: Value::Null,
+        };
+        let handle = mock.send_platform_message(CHANNEL_NAME, &CODEC.encode_method_call(&call));
+        handler.pump(&engine);
+        assert_eq!(mock.response(handle), Some(vec![]));
+    }
+}
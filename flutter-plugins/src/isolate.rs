@@ -55,3 +55,28 @@ impl MessageHandler for Handler {
         Ok(Value::Null)
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::mock::{run_engine, MockHandler};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_callback_runs_once() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        engine.add_plugin(IsolatePlugin::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        for _ in 0..2 {
+            let handle = mock.send_platform_message(CHANNEL_NAME, b"isolate");
+            handler.pump(&engine);
+            assert!(mock.response(handle).is_some());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
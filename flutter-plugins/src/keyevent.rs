@@ -77,3 +77,42 @@ impl MessageHandler for Handler {
         Ok(Value::Null)
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::MessageCodec;
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[test]
+    fn test_key_action() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.add_plugin(KeyEventPlugin::default());
+
+        engine.with_plugin(|keyevent: &KeyEventPlugin| {
+            keyevent.key_action(KeyAction {
+                toolkit: "glfw".into(),
+                key_code: 65,
+                scan_code: 38,
+                modifiers: 1,
+                keymap: "linux".into(),
+                _type: KeyActionType::Keydown,
+            })
+        });
+
+        let messages = mock.sent_messages(CHANNEL_NAME);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            json_codec::CODEC.decode_message(&messages[0]),
+            Ok(json_value!({
+                "toolkit": "glfw",
+                "keyCode": 65,
+                "scanCode": 38,
+                "modifiers": 1,
+                "keymap": "linux",
+                "type": "keydown",
+            }))
+        );
+    }
+}
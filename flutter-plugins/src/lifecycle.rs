@@ -67,3 +67,37 @@ impl MessageHandler for Handler {
         Ok(Value::Null)
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::MessageCodec;
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[test]
+    fn test_send_lifecycle_states() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.add_plugin(LifecyclePlugin::default());
+
+        engine.with_plugin(|lifecycle: &LifecyclePlugin| {
+            lifecycle.send_app_is_inactive();
+            lifecycle.send_app_is_paused();
+            lifecycle.send_app_is_resumed();
+        });
+
+        let states: Vec<_> = mock
+            .sent_messages(CHANNEL_NAME)
+            .iter()
+            .map(|message| string_codec::CODEC.decode_message(message).unwrap())
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                Value::String("AppLifecycleState.inactive".into()),
+                Value::String("AppLifecycleState.paused".into()),
+                Value::String("AppLifecycleState.resumed".into()),
+            ]
+        );
+    }
+}
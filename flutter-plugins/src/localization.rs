@@ -78,3 +78,26 @@ impl MethodCallHandler for Handler {
         Err(MethodCallError::NotImplemented)
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::{json_codec::CODEC, MethodCodec};
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[test]
+    fn test_send_locale() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.add_plugin(LocalizationPlugin::default());
+
+        let locale = locale_config::Locale::new("en-US").unwrap();
+        engine.with_plugin(|localization: &LocalizationPlugin| localization.send_locale(locale));
+
+        let messages = mock.sent_messages(CHANNEL_NAME);
+        assert_eq!(messages.len(), 1);
+        let call = CODEC.decode_method_call(&messages[0]).unwrap();
+        assert_eq!(call.method, "setLocale");
+        assert_eq!(call.args, json_value!(["en", "US", "", ""]));
+    }
+}
@@ -84,3 +84,40 @@ impl MethodCallHandler for Handler {
         Err(MethodCallError::NotImplemented)
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::{json_codec::CODEC, MethodCodec};
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[test]
+    fn test_push_route() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.add_plugin(NavigationPlugin::default());
+
+        engine.with_plugin(|navigation: &NavigationPlugin| navigation.push_route("/settings"));
+
+        let messages = mock.sent_messages(CHANNEL_NAME);
+        assert_eq!(messages.len(), 1);
+        let call = CODEC.decode_method_call(&messages[0]).unwrap();
+        assert_eq!(call.method, "pushRoute");
+        assert_eq!(call.args, Value::String("/settings".into()));
+    }
+
+    #[test]
+    fn test_unknown_method_is_not_implemented() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.add_plugin(NavigationPlugin::default());
+
+        let call = MethodCall {
+            method: "unknown".into(),
+            args: Value::Null,
+        };
+        let handle = mock.send_platform_message(CHANNEL_NAME, &CODEC.encode_method_call(&call));
+        handler.pump(&engine);
+        assert_eq!(mock.response(handle), Some(vec![]));
+    }
+}
@@ -73,7 +73,7 @@ impl MethodCallHandler for Handler {
             }
             "Clipboard.setData" => {
                 if let Value::Map(v) = &call.args {
-                    if let Some(v) = &v.get("text") {
+                    if let Some(v) = &v.get_str("text") {
                         if let Value::String(text) = v {
                             let text = text.clone();
                             self.handler.lock().set_clipboard_data(text);
@@ -104,3 +104,76 @@ pub struct AppSwitcherDescription {
     pub primary_color: i64,
     pub label: String,
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::{json_codec::CODEC, MethodCodec};
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[derive(Default)]
+    struct Clipboard {
+        text: Option<String>,
+        label: Option<String>,
+    }
+
+    impl PlatformHandler for Clipboard {
+        fn set_application_switcher_description(&mut self, description: AppSwitcherDescription) {
+            self.label = Some(description.label);
+        }
+
+        fn set_clipboard_data(&mut self, text: String) {
+            self.text = Some(text);
+        }
+
+        fn get_clipboard_data(&mut self, mime: &str) -> Result<String, MimeError> {
+            match (&self.text, mime) {
+                (Some(text), "text/plain") => Ok(text.clone()),
+                _ => Err(MimeError),
+            }
+        }
+    }
+
+    #[test]
+    fn test_platform_calls() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let clipboard = Arc::new(Mutex::new(Clipboard::default()));
+        engine.add_plugin(PlatformPlugin::new(clipboard.clone()));
+
+        let invoke = |method: &str, args: Value| {
+            let call = MethodCall {
+                method: method.into(),
+                args,
+            };
+            let handle = mock.send_platform_message(CHANNEL_NAME, &CODEC.encode_method_call(&call));
+            handler.pump(&engine);
+            CODEC.decode_envelope(&mock.response(handle).unwrap())
+        };
+
+        match invoke("Clipboard.setData", json_value!({ "text": "copied" })) {
+            Ok(MethodCallResult::Ok(Value::Null)) => {}
+            _ => panic!("Expected a success envelope"),
+        }
+        assert_eq!(clipboard.lock().text.as_deref(), Some("copied"));
+
+        match invoke("Clipboard.getData", json_value!("text/plain")) {
+            Ok(MethodCallResult::Ok(value)) => assert_eq!(value, json_value!({ "text": "copied" })),
+            _ => panic!("Expected a success envelope"),
+        }
+        match invoke("Clipboard.getData", json_value!("text/html")) {
+            Ok(MethodCallResult::Err { .. }) => {}
+            _ => panic!("Expected an error envelope"),
+        }
+
+        let description = json_value!({ "primaryColor": 0xff00_00ff_u32, "label": "app" });
+        match invoke(
+            "SystemChrome.setApplicationSwitcherDescription",
+            description,
+        ) {
+            Ok(MethodCallResult::Ok(Value::Null)) => {}
+            _ => panic!("Expected a success envelope"),
+        }
+        assert_eq!(clipboard.lock().label.as_deref(), Some("app"));
+    }
+}
@@ -7,12 +7,15 @@ pub use serde::{Deserialize, Serialize};
 
 pub use flutter_engine::{
     channel::{
-        BasicMessageChannel, ChannelRegistrar, EventChannel, EventHandler, JsonMethodChannel,
-        MessageChannel, MessageHandler, MethodCallHandler, MethodChannel, StandardMethodChannel,
+        AsyncMessageHandler, AsyncMethodCallHandler, BasicMessageChannel, ChannelInterceptor,
+        ChannelRegistrar, EventChannel, EventHandler, ExecutionPolicy, HandlerFuture, Interception,
+        JsonMethodChannel, MessageChannel, MessageDirection, MessageHandler, MethodCallHandler,
+        MethodChannel, StandardMethodChannel,
     },
-    codec::value::{from_value, to_value, Error, Value},
+    codec::standard_codec::{StandardCodecExtension, StandardMethodCodec},
+    codec::value::{from_value, to_value, Error, Value, ValueMap},
     codec::{json_codec, standard_codec, string_codec, MethodCall, MethodCallResult},
-    error::{MessageError, MethodArgsError, MethodCallError},
+    error::{DecodeError, MessageError, MethodArgsError, MethodCallError},
     ffi::PlatformMessageResponseHandle,
     json_value,
     plugins::{Plugin, PluginRegistrar},
@@ -1,8 +1,6 @@
 //! Plugin to work with settings.
 //! It handles flutter/settings type message.
 
-use std::collections::HashMap;
-
 use log::debug;
 use serde::{Deserialize, Serialize};
 
@@ -18,7 +16,7 @@ pub struct SettingsPlugin {
 
 pub struct SettingsMessage<'a> {
     plugin: &'a SettingsPlugin,
-    settings: HashMap<String, Value>,
+    settings: ValueMap,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -54,22 +52,19 @@ impl Plugin for SettingsPlugin {
 
 impl SettingsMessage<'_> {
     pub fn set_text_scale_factor(mut self, factor: f64) -> Self {
-        self.settings
-            .insert("textScaleFactor".into(), Value::F64(factor));
+        self.settings.insert("textScaleFactor", Value::F64(factor));
         self
     }
 
     pub fn set_use_24_hour_format(mut self, use_24_hour_format: bool) -> Self {
-        self.settings.insert(
-            "alwaysUse24HourFormat".into(),
-            Value::Boolean(use_24_hour_format),
-        );
+        self.settings
+            .insert("alwaysUse24HourFormat", Value::Boolean(use_24_hour_format));
         self
     }
 
     pub fn set_platform_brightness(mut self, brightness: PlatformBrightness) -> Self {
         self.settings
-            .insert("platformBrightness".into(), json_value!(brightness));
+            .insert("platformBrightness", json_value!(brightness));
         self
     }
 
@@ -86,7 +81,7 @@ impl SettingsPlugin {
         debug!("Starting to build message");
         SettingsMessage {
             plugin: self,
-            settings: HashMap::new(),
+            settings: ValueMap::new(),
         }
     }
 }
@@ -98,3 +93,37 @@ impl MessageHandler for Handler {
         Ok(Value::Null)
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::MessageCodec;
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[test]
+    fn test_send_settings() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.add_plugin(SettingsPlugin::default());
+
+        engine.with_plugin(|settings: &SettingsPlugin| {
+            settings
+                .start_message()
+                .set_text_scale_factor(1.5)
+                .set_use_24_hour_format(true)
+                .set_platform_brightness(PlatformBrightness::Dark)
+                .send()
+        });
+
+        let messages = mock.sent_messages(CHANNEL_NAME);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            json_codec::CODEC.decode_message(&messages[0]),
+            Ok(json_value!({
+                "textScaleFactor": 1.5,
+                "alwaysUse24HourFormat": true,
+                "platformBrightness": "dark",
+            }))
+        );
+    }
+}
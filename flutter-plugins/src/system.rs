@@ -55,3 +55,26 @@ impl MessageHandler for Handler {
         Ok(Value::Null)
     }
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::MessageCodec;
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[test]
+    fn test_memory_pressure_warning() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        engine.add_plugin(SystemPlugin::default());
+
+        engine.with_plugin(|system: &SystemPlugin| system.send_memory_pressure_warning());
+
+        let messages = mock.sent_messages(CHANNEL_NAME);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            json_codec::CODEC.decode_message(&messages[0]),
+            Ok(json_value!({ "type": "memoryPressure" }))
+        );
+    }
+}
@@ -163,3 +163,103 @@ struct SetClientArgsInputType {
     name: String,
     decimal: Option<bool>,
 }
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::{json_codec::CODEC, MethodCodec};
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[derive(Default)]
+    struct Keyboard {
+        visible: bool,
+    }
+
+    impl TextInputHandler for Keyboard {
+        fn show(&mut self) {
+            self.visible = true;
+        }
+
+        fn hide(&mut self) {
+            self.visible = false;
+        }
+    }
+
+    #[test]
+    fn test_text_input_client() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let keyboard = Arc::new(Mutex::new(Keyboard::default()));
+        engine.add_plugin(TextInputPlugin::new(keyboard.clone()));
+
+        let invoke = |method: &str, args: Value| {
+            let call = MethodCall {
+                method: method.into(),
+                args,
+            };
+            let handle = mock.send_platform_message(CHANNEL_NAME, &CODEC.encode_method_call(&call));
+            handler.pump(&engine);
+            match CODEC.decode_envelope(&mock.response(handle).unwrap()) {
+                Ok(MethodCallResult::Ok(Value::Null)) => {}
+                _ => panic!("Expected a success envelope for {}", method),
+            }
+        };
+        invoke(
+            "TextInput.setClient",
+            json_value!([3, {
+                "autocorrect": true,
+                "inputAction": "TextInputAction.done",
+                "obscureText": false,
+                "keyboardAppearance": "Brightness.light",
+                "actionLabel": null,
+                "textCapitalization": "TextCapitalization.none",
+                "inputType": { "signed": null, "name": "TextInputType.text", "decimal": null },
+            }]),
+        );
+        invoke(
+            "TextInput.setEditingState",
+            json_value!({
+                "composingBase": -1,
+                "composingExtent": -1,
+                "selectionAffinity": "TextAffinity.downstream",
+                "selectionBase": 2,
+                "selectionExtent": 2,
+                "selectionIsDirectional": false,
+                "text": "hi",
+            }),
+        );
+        invoke("TextInput.show", Value::Null);
+        assert!(keyboard.lock().visible);
+
+        engine.with_plugin_mut(|text_input: &mut TextInputPlugin| {
+            text_input.with_state(|state| state.move_to(0));
+            text_input.notify_changes();
+            text_input.perform_action("done");
+        });
+        let calls: Vec<_> = mock
+            .sent_messages(CHANNEL_NAME)
+            .iter()
+            .map(|message| CODEC.decode_method_call(message).unwrap())
+            .collect();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].method, "TextInputClient.updateEditingState");
+        assert_eq!(
+            calls[0].args,
+            json_value!([3, {
+                "composingBase": -1,
+                "composingExtent": -1,
+                "selectionAffinity": "TextAffinity.downstream",
+                "selectionBase": 0,
+                "selectionExtent": 0,
+                "selectionIsDirectional": false,
+                "text": "hi",
+            }])
+        );
+        assert_eq!(calls[1].method, "TextInputClient.performAction");
+        assert_eq!(calls[1].args, json_value!([3, "TextInputAction.done"]));
+
+        invoke("TextInput.hide", Value::Null);
+        invoke("TextInput.clearClient", Value::Null);
+        assert!(!keyboard.lock().visible);
+    }
+}
@@ -32,6 +32,12 @@ pub trait WindowHandler {
     fn start_drag(&mut self);
 
     fn end_drag(&mut self);
+
+    /// Opens another window with its own engine. Returns `false` if the
+    /// embedder only supports a single window.
+    fn open_window(&mut self, _params: OpenWindowParams) -> bool {
+        false
+    }
 }
 
 pub struct WindowPlugin {
@@ -112,6 +118,17 @@ impl MethodCallHandler for Handler {
                 self.handler.lock().end_drag();
                 Ok(Value::Null)
             }
+            "openWindow" => {
+                let params = match call.args {
+                    Value::Null => OpenWindowParams::default(),
+                    args => from_value(&args)?,
+                };
+                if self.handler.lock().open_window(params) {
+                    Ok(Value::Null)
+                } else {
+                    Err(MethodCallError::NotImplemented)
+                }
+            }
             _ => Err(MethodCallError::NotImplemented),
         }
     }
@@ -122,3 +139,101 @@ pub struct PositionParams {
     pub x: f32,
     pub y: f32,
 }
+
+/// Arguments of `openWindow`. Every field is optional.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpenWindowParams {
+    /// The Dart function the engine of the new window runs instead of
+    /// `main`.
+    pub entrypoint: Option<String>,
+    /// The initial route of the new window.
+    pub route: Option<String>,
+    pub title: Option<String>,
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use flutter_engine::codec::{json_codec::CODEC, MethodCodec};
+    use flutter_engine::mock::{run_engine, MockHandler};
+
+    #[derive(Default)]
+    struct OpenWindowHandler {
+        opened: Vec<OpenWindowParams>,
+    }
+
+    impl WindowHandler for OpenWindowHandler {
+        fn close(&mut self) {}
+
+        fn show(&mut self) {}
+
+        fn hide(&mut self) {}
+
+        fn maximize(&mut self) {}
+
+        fn iconify(&mut self) {}
+
+        fn restore(&mut self) {}
+
+        fn is_maximized(&mut self) -> bool {
+            false
+        }
+
+        fn is_iconified(&mut self) -> bool {
+            false
+        }
+
+        fn is_visible(&mut self) -> bool {
+            true
+        }
+
+        fn set_pos(&mut self, _pos: PositionParams) {}
+
+        fn get_pos(&mut self) -> PositionParams {
+            PositionParams { x: 0.0, y: 0.0 }
+        }
+
+        fn start_drag(&mut self) {}
+
+        fn end_drag(&mut self) {}
+
+        fn open_window(&mut self, params: OpenWindowParams) -> bool {
+            self.opened.push(params);
+            true
+        }
+    }
+
+    #[test]
+    fn test_open_window() {
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let window_handler = Arc::new(Mutex::new(OpenWindowHandler::default()));
+        engine.add_plugin(WindowPlugin::new(window_handler.clone()));
+
+        let call = MethodCall {
+            method: "openWindow".into(),
+            args: json_value!({
+                "entrypoint": "settingsMain",
+                "route": "/settings",
+            }),
+        };
+        let handle = mock.send_platform_message(CHANNEL_NAME, &CODEC.encode_method_call(&call));
+        handler.pump(&engine);
+
+        let response = mock.response(handle).unwrap();
+        match CODEC.decode_envelope(&response) {
+            Ok(MethodCallResult::Ok(Value::Null)) => {}
+            _ => panic!("Expected a success envelope"),
+        }
+        assert_eq!(
+            window_handler.lock().opened,
+            vec![OpenWindowParams {
+                entrypoint: Some("settingsMain".into()),
+                route: Some("/settings".into()),
+                ..Default::default()
+            }]
+        );
+    }
+}
@@ -0,0 +1,503 @@
+//! Exposes the semantics tree of the engine to assistive technologies.
+//!
+//! The tree is translated into an AccessKit tree, which `accesskit_unix`
+//! publishes over AT-SPI. Actions requested by a screen reader are sent back
+//! to the engine as semantics actions.
+
+use accesskit::{
+    Action, ActionData, ActionHandler, ActionRequest, ActivationHandler, Affine, CustomAction,
+    DeactivationHandler, Node, NodeId, Rect, Role, Toggled, Tree, TreeUpdate,
+};
+use accesskit_unix::Adapter;
+use flutter_engine::semantics::{
+    SemanticsAction, SemanticsActions, SemanticsFlags, SemanticsNode, SemanticsNodeId,
+    SemanticsTree, ROOT_NODE_ID,
+};
+use flutter_engine::{FlutterEngine, FlutterEngineWeakRef};
+use glutin::event::WindowEvent;
+use glutin::window::Window;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const ACTIONS: &[(SemanticsActions, Action)] = &[
+    (SemanticsActions::TAP, Action::Click),
+    (SemanticsActions::INCREASE, Action::Increment),
+    (SemanticsActions::DECREASE, Action::Decrement),
+    (SemanticsActions::SCROLL_LEFT, Action::ScrollLeft),
+    (SemanticsActions::SCROLL_RIGHT, Action::ScrollRight),
+    (SemanticsActions::SCROLL_UP, Action::ScrollUp),
+    (SemanticsActions::SCROLL_DOWN, Action::ScrollDown),
+    (SemanticsActions::SHOW_ON_SCREEN, Action::ScrollIntoView),
+    (
+        SemanticsActions::DID_GAIN_ACCESSIBILITY_FOCUS,
+        Action::Focus,
+    ),
+    (SemanticsActions::DID_LOSE_ACCESSIBILITY_FOCUS, Action::Blur),
+    (SemanticsActions::SET_TEXT, Action::SetValue),
+];
+
+pub struct AccessibilityBridge {
+    adapter: Mutex<Adapter>,
+    engine: Arc<Mutex<FlutterEngineWeakRef>>,
+}
+
+impl Default for AccessibilityBridge {
+    fn default() -> Self {
+        let engine = Arc::new(Mutex::new(FlutterEngineWeakRef::default()));
+        let adapter = Adapter::new(
+            EngineActivationHandler {
+                engine: engine.clone(),
+            },
+            EngineActionHandler {
+                engine: engine.clone(),
+            },
+            EngineDeactivationHandler {
+                engine: engine.clone(),
+            },
+        );
+        Self {
+            adapter: Mutex::new(adapter),
+            engine,
+        }
+    }
+}
+
+impl AccessibilityBridge {
+    pub fn init(&self, engine: FlutterEngineWeakRef) {
+        *self.engine.lock() = engine;
+    }
+
+    /// Publishes the current semantics tree if an assistive technology is
+    /// listening.
+    pub fn update(&self, tree: &SemanticsTree) {
+        self.adapter.lock().update_if_active(|| tree_update(tree));
+    }
+
+    /// Keeps the adapter informed about the focus and position of the window.
+    pub fn handle_event(&self, window: &Window, event: &WindowEvent) {
+        match event {
+            WindowEvent::Focused(focused) => {
+                self.adapter.lock().update_window_focus_state(*focused);
+            }
+            WindowEvent::Moved(_) | WindowEvent::Resized(_) => self.update_window_bounds(window),
+            _ => {}
+        }
+    }
+
+    /// Tells the adapter where the window is on screen, so node bounds can be
+    /// mapped to screen coordinates.
+    pub fn update_window_bounds(&self, window: &Window) {
+        let dpi = window.hidpi_factor();
+        let (outer, inner) = match (window.outer_position(), window.inner_position()) {
+            (Ok(outer), Ok(inner)) => (outer.to_physical(dpi), inner.to_physical(dpi)),
+            _ => return,
+        };
+        let outer_size = window.outer_size().to_physical(dpi);
+        let inner_size = window.inner_size().to_physical(dpi);
+        self.adapter.lock().set_root_window_bounds(
+            Rect::new(
+                outer.x,
+                outer.y,
+                outer.x + outer_size.width,
+                outer.y + outer_size.height,
+            ),
+            Rect::new(
+                inner.x,
+                inner.y,
+                inner.x + inner_size.width,
+                inner.y + inner_size.height,
+            ),
+        );
+    }
+}
+
+/// Runs `f` on the platform thread of the engine, if it is still alive.
+fn with_engine<F>(engine: &Mutex<FlutterEngineWeakRef>, f: F)
+where
+    F: FnOnce(&FlutterEngine) + Send + 'static,
+{
+    if let Some(engine) = engine.lock().upgrade() {
+        engine.run_on_platform_thread(f);
+    }
+}
+
+struct EngineActivationHandler {
+    engine: Arc<Mutex<FlutterEngineWeakRef>>,
+}
+
+impl ActivationHandler for EngineActivationHandler {
+    fn request_initial_tree(&mut self) -> Option<TreeUpdate> {
+        with_engine(&self.engine, |engine| engine.update_semantics_enabled(true));
+        // The engine sends the full tree once semantics are enabled, until
+        // then the adapter waits for the first update.
+        let engine = self.engine.lock().upgrade()?;
+        engine.with_semantics_tree(|tree| {
+            if tree.is_empty() {
+                None
+            } else {
+                Some(tree_update(tree))
+            }
+        })
+    }
+}
+
+struct EngineActionHandler {
+    engine: Arc<Mutex<FlutterEngineWeakRef>>,
+}
+
+impl ActionHandler for EngineActionHandler {
+    fn do_action(&mut self, request: ActionRequest) {
+        if let Some(action) = semantics_action(&request) {
+            let id = request.target.0 as SemanticsNodeId;
+            with_engine(&self.engine, move |engine| {
+                engine.dispatch_semantics_action(id, action)
+            });
+        }
+    }
+}
+
+struct EngineDeactivationHandler {
+    engine: Arc<Mutex<FlutterEngineWeakRef>>,
+}
+
+impl DeactivationHandler for EngineDeactivationHandler {
+    fn deactivate_accessibility(&mut self) {
+        with_engine(&self.engine, |engine| {
+            engine.update_semantics_enabled(false)
+        });
+    }
+}
+
+fn node_id(id: SemanticsNodeId) -> NodeId {
+    NodeId(id as u64)
+}
+
+fn tree_update(tree: &SemanticsTree) -> TreeUpdate {
+    let root = node_id(ROOT_NODE_ID);
+    let mut focus = root;
+    let mut nodes: Vec<_> = tree
+        .nodes()
+        .into_iter()
+        .map(|node| {
+            if node.has_flag(SemanticsFlags::IS_FOCUSED) {
+                focus = node_id(node.id);
+            }
+            (node_id(node.id), accesskit_node(tree, node))
+        })
+        .collect();
+    if nodes.is_empty() {
+        // AccessKit requires the root to exist even before the first frame.
+        nodes.push((root, Node::new(Role::Window)));
+    }
+
+    let mut accesskit_tree = Tree::new(root);
+    accesskit_tree.toolkit_name = Some("flutter-rs".to_owned());
+    accesskit_tree.toolkit_version = Some(env!("CARGO_PKG_VERSION").to_owned());
+    TreeUpdate {
+        nodes,
+        tree: Some(accesskit_tree),
+        focus,
+    }
+}
+
+fn role(node: &SemanticsNode) -> Role {
+    if node.has_flag(SemanticsFlags::IS_TEXT_FIELD) {
+        if node.has_flag(SemanticsFlags::IS_OBSCURED) {
+            Role::PasswordInput
+        } else {
+            Role::TextInput
+        }
+    } else if node.has_flag(SemanticsFlags::IS_BUTTON) {
+        Role::Button
+    } else if node.has_flag(SemanticsFlags::IS_LINK) {
+        Role::Link
+    } else if node.has_flag(SemanticsFlags::IS_HEADER) {
+        Role::Heading
+    } else if node.has_flag(SemanticsFlags::IS_IMAGE) {
+        Role::Image
+    } else if node.has_flag(SemanticsFlags::HAS_TOGGLED_STATE) {
+        Role::Switch
+    } else if node.has_flag(SemanticsFlags::HAS_CHECKED_STATE) {
+        if node.has_flag(SemanticsFlags::IS_IN_MUTUALLY_EXCLUSIVE_GROUP) {
+            Role::RadioButton
+        } else {
+            Role::CheckBox
+        }
+    } else if node
+        .actions
+        .contains(SemanticsActions::INCREASE | SemanticsActions::DECREASE)
+    {
+        Role::Slider
+    } else if node.id == ROOT_NODE_ID {
+        Role::Window
+    } else if !node.label.is_empty() && node.children_in_traversal_order.is_empty() {
+        Role::Label
+    } else {
+        Role::GenericContainer
+    }
+}
+
+fn accesskit_node(tree: &SemanticsTree, node: &SemanticsNode) -> Node {
+    let mut result = Node::new(role(node));
+    if !node.label.is_empty() {
+        result.set_label(node.label.as_str());
+    }
+    if !node.value.is_empty() {
+        result.set_value(node.value.as_str());
+    }
+    if !node.hint.is_empty() {
+        result.set_description(node.hint.as_str());
+    }
+
+    let rect = &node.rect;
+    result.set_bounds(Rect::new(rect.left, rect.top, rect.right, rect.bottom));
+    let t = &node.transform;
+    result.set_transform(Affine::new([
+        t.scale_x, t.skew_y, t.skew_x, t.scale_y, t.trans_x, t.trans_y,
+    ]));
+    result.set_children(
+        node.children_in_traversal_order
+            .iter()
+            .map(|id| node_id(*id))
+            .collect::<Vec<_>>(),
+    );
+
+    if node.has_flag(SemanticsFlags::HAS_ENABLED_STATE)
+        && !node.has_flag(SemanticsFlags::IS_ENABLED)
+    {
+        result.set_disabled();
+    }
+    if node.has_flag(SemanticsFlags::IS_HIDDEN) {
+        result.set_hidden();
+    }
+    if node.has_flag(SemanticsFlags::HAS_TOGGLED_STATE) {
+        result.set_toggled(toggled(node.has_flag(SemanticsFlags::IS_TOGGLED)));
+    } else if node.has_flag(SemanticsFlags::HAS_CHECKED_STATE) {
+        result.set_toggled(toggled(node.has_flag(SemanticsFlags::IS_CHECKED)));
+    }
+    if node.has_flag(SemanticsFlags::IS_SELECTED) {
+        result.set_selected(true);
+    }
+
+    for (flag, action) in ACTIONS {
+        if node.actions.contains(*flag) {
+            result.add_action(*action);
+        }
+    }
+    // Custom actions overriding a standard action only relabel it.
+    let custom_actions: Vec<_> = node
+        .custom_accessibility_actions
+        .iter()
+        .filter_map(|id| tree.custom_action(*id))
+        .filter(|action| action.override_action.is_empty())
+        .map(|action| CustomAction {
+            id: action.id,
+            description: action.label.as_str().into(),
+        })
+        .collect();
+    if !custom_actions.is_empty() {
+        result.set_custom_actions(custom_actions);
+        result.add_action(Action::CustomAction);
+    }
+    result
+}
+
+fn toggled(on: bool) -> Toggled {
+    if on {
+        Toggled::True
+    } else {
+        Toggled::False
+    }
+}
+
+fn semantics_action(request: &ActionRequest) -> Option<SemanticsAction> {
+    let action = match request.action {
+        Action::Click => SemanticsAction::Tap,
+        Action::Increment => SemanticsAction::Increase,
+        Action::Decrement => SemanticsAction::Decrease,
+        Action::ScrollLeft => SemanticsAction::ScrollLeft,
+        Action::ScrollRight => SemanticsAction::ScrollRight,
+        Action::ScrollUp => SemanticsAction::ScrollUp,
+        Action::ScrollDown => SemanticsAction::ScrollDown,
+        Action::ScrollIntoView => SemanticsAction::ShowOnScreen,
+        Action::Focus => SemanticsAction::DidGainAccessibilityFocus,
+        Action::Blur => SemanticsAction::DidLoseAccessibilityFocus,
+        Action::CustomAction => match request.data {
+            Some(ActionData::CustomAction(id)) => SemanticsAction::CustomAction(id),
+            _ => return None,
+        },
+        Action::SetValue => match &request.data {
+            Some(ActionData::Value(text)) => SemanticsAction::SetText(text.to_string()),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flutter_engine::ffi::{Rect as FlutterRect, Transformation};
+    use flutter_engine::semantics::{CustomSemanticsAction, TextDirection};
+
+    fn node(
+        id: SemanticsNodeId,
+        flags: SemanticsFlags,
+        actions: SemanticsActions,
+        label: &str,
+        children: &[SemanticsNodeId],
+    ) -> SemanticsNode {
+        SemanticsNode {
+            id,
+            flags,
+            actions,
+            text_selection_base: -1,
+            text_selection_extent: -1,
+            scroll_child_count: 0,
+            scroll_index: 0,
+            scroll_position: 0.0,
+            scroll_extent_max: 0.0,
+            scroll_extent_min: 0.0,
+            elevation: 0.0,
+            thickness: 0.0,
+            label: label.into(),
+            hint: String::new(),
+            value: String::new(),
+            increased_value: String::new(),
+            decreased_value: String::new(),
+            text_direction: TextDirection::LTR,
+            rect: FlutterRect {
+                left: 0.0,
+                top: 0.0,
+                right: 100.0,
+                bottom: 20.0,
+            },
+            transform: Transformation::default(),
+            children_in_traversal_order: children.to_vec(),
+            children_in_hit_test_order: children.to_vec(),
+            custom_accessibility_actions: Vec::new(),
+            platform_view_id: 0,
+        }
+    }
+
+    fn tree() -> SemanticsTree {
+        let mut tree = SemanticsTree::new();
+        tree.insert_node(node(
+            ROOT_NODE_ID,
+            SemanticsFlags::empty(),
+            SemanticsActions::empty(),
+            "",
+            &[1, 2, 3],
+        ));
+        let mut ok = node(
+            1,
+            SemanticsFlags::IS_BUTTON | SemanticsFlags::IS_FOCUSED,
+            SemanticsActions::TAP | SemanticsActions::CUSTOM_ACTION,
+            "OK",
+            &[],
+        );
+        ok.custom_accessibility_actions = vec![7, 8];
+        tree.insert_node(ok);
+        let mut name = node(
+            2,
+            SemanticsFlags::IS_TEXT_FIELD,
+            SemanticsActions::SET_TEXT,
+            "Name",
+            &[],
+        );
+        name.value = "Jane".into();
+        tree.insert_node(name);
+        tree.insert_node(node(
+            3,
+            SemanticsFlags::HAS_TOGGLED_STATE | SemanticsFlags::IS_TOGGLED,
+            SemanticsActions::TAP,
+            "Dark mode",
+            &[],
+        ));
+        tree.insert_custom_action(CustomSemanticsAction {
+            id: 7,
+            override_action: SemanticsActions::empty(),
+            label: "Details".into(),
+            hint: String::new(),
+        });
+        // only relabels the tap action
+        tree.insert_custom_action(CustomSemanticsAction {
+            id: 8,
+            override_action: SemanticsActions::TAP,
+            label: "Confirm".into(),
+            hint: String::new(),
+        });
+        tree
+    }
+
+    #[test]
+    fn test_tree_update() {
+        let update = tree_update(&tree());
+        assert_eq!(update.focus, NodeId(1));
+        assert_eq!(update.tree.unwrap().root, NodeId(0));
+
+        let ids: Vec<_> = update.nodes.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![NodeId(0), NodeId(1), NodeId(2), NodeId(3)]);
+        let nodes: Vec<_> = update.nodes.iter().map(|(_, node)| node).collect();
+
+        assert_eq!(nodes[0].role(), Role::Window);
+        assert_eq!(nodes[0].children(), &[NodeId(1), NodeId(2), NodeId(3)]);
+
+        assert_eq!(nodes[1].role(), Role::Button);
+        assert_eq!(nodes[1].label(), Some("OK"));
+        assert!(nodes[1].supports_action(Action::Click));
+        assert!(nodes[1].supports_action(Action::CustomAction));
+        let custom_actions: Vec<_> = nodes[1]
+            .custom_actions()
+            .iter()
+            .map(|action| (action.id, &*action.description))
+            .collect();
+        assert_eq!(custom_actions, vec![(7, "Details")]);
+
+        assert_eq!(nodes[2].role(), Role::TextInput);
+        assert_eq!(nodes[2].value(), Some("Jane"));
+        assert!(nodes[2].supports_action(Action::SetValue));
+        assert!(!nodes[2].supports_action(Action::Click));
+
+        assert_eq!(nodes[3].role(), Role::Switch);
+        assert_eq!(nodes[3].toggled(), Some(Toggled::True));
+    }
+
+    #[test]
+    fn test_empty_tree_has_root() {
+        let update = tree_update(&SemanticsTree::new());
+        assert_eq!(update.nodes.len(), 1);
+        assert_eq!(update.nodes[0].0, NodeId(0));
+        assert_eq!(update.focus, NodeId(0));
+    }
+
+    #[test]
+    fn test_semantics_action() {
+        let request = |action, data| ActionRequest {
+            action,
+            target: NodeId(2),
+            data,
+        };
+        assert_eq!(
+            semantics_action(&request(Action::Click, None)),
+            Some(SemanticsAction::Tap)
+        );
+        assert_eq!(
+            semantics_action(&request(
+                Action::SetValue,
+                Some(ActionData::Value("Joe".into()))
+            )),
+            Some(SemanticsAction::SetText("Joe".into()))
+        );
+        assert_eq!(
+            semantics_action(&request(
+                Action::CustomAction,
+                Some(ActionData::CustomAction(7))
+            )),
+            Some(SemanticsAction::CustomAction(7))
+        );
+        assert_eq!(semantics_action(&request(Action::CustomAction, None)), None);
+        assert_eq!(semantics_action(&request(Action::Expand, None)), None);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::plugins::DefaultPlugins;
+use crate::window::{FlutterEvent, FlutterEventProxy, FlutterWindow};
+use flutter_plugins::window::OpenWindowParams;
+use glutin::dpi::LogicalSize;
+use glutin::event::Event;
+use glutin::event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget};
+use glutin::window::{WindowBuilder, WindowId};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+
+type WindowOpenedCallback = Box<dyn FnMut(&FlutterWindow)>;
+
+/// Drives any number of Flutter windows on one event loop. Every window has
+/// its own engine; input, resize and lifecycle events go to the window they
+/// belong to. The application exits once the last window is closed.
+pub struct FlutterApplication {
+    event_loop: EventLoop<FlutterEvent>,
+    windows: HashMap<WindowId, FlutterWindow>,
+    arguments: Vec<String>,
+    plugins: DefaultPlugins,
+    on_window_opened: Option<WindowOpenedCallback>,
+}
+
+impl Default for FlutterApplication {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlutterApplication {
+    pub fn new() -> Self {
+        Self::with_event_loop(EventLoop::with_user_event())
+    }
+
+    fn with_event_loop(event_loop: EventLoop<FlutterEvent>) -> Self {
+        Self {
+            event_loop,
+            windows: HashMap::new(),
+            arguments: Vec::new(),
+            plugins: DefaultPlugins::default(),
+            on_window_opened: None,
+        }
+    }
+
+    pub(crate) fn from_window(mut window: FlutterWindow) -> Self {
+        let (event_loop, plugins) = window
+            .event_loop
+            .take()
+            .expect("Window is already part of an application");
+        let mut app = Self::with_event_loop(event_loop).with_default_plugins(plugins);
+        app.windows.insert(window.id(), window);
+        app
+    }
+
+    /// Engine arguments for windows opened from Dart.
+    pub fn with_engine_arguments(mut self, arguments: Vec<String>) -> Self {
+        self.arguments = arguments;
+        self
+    }
+
+    /// The built-in plugins of windows opened by the application.
+    pub fn with_default_plugins(mut self, plugins: DefaultPlugins) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Called for every window opened from Dart before its engine is
+    /// started, so plugins can be added to it.
+    pub fn on_window_opened<F>(&mut self, f: F)
+    where
+        F: FnMut(&FlutterWindow) + 'static,
+    {
+        self.on_window_opened = Some(Box::new(f));
+    }
+
+    /// Opens a window. Its engine has to be started with
+    /// `FlutterWindow::start_engine` before the application runs.
+    pub fn open_window(
+        &mut self,
+        window: WindowBuilder,
+        assets_path: PathBuf,
+    ) -> Result<&FlutterWindow, Box<dyn Error>> {
+        let proxy = FlutterEventProxy::new(self.event_loop.create_proxy());
+        let window = FlutterWindow::create(
+            &self.event_loop,
+            proxy,
+            window,
+            assets_path,
+            None,
+            &self.plugins,
+            true,
+        )?;
+        window.create_resource_context(&self.event_loop)?;
+        Ok(self.windows.entry(window.id()).or_insert(window))
+    }
+
+    pub fn window(&self, id: WindowId) -> Option<&FlutterWindow> {
+        self.windows.get(&id)
+    }
+
+    pub fn windows(&self) -> impl Iterator<Item = &FlutterWindow> {
+        self.windows.values()
+    }
+
+    pub fn run(self) -> ! {
+        let Self {
+            event_loop,
+            mut windows,
+            arguments,
+            plugins,
+            mut on_window_opened,
+        } = self;
+        let proxy = FlutterEventProxy::new(event_loop.create_proxy());
+
+        event_loop.run(move |event, target, control_flow| match event {
+            Event::WindowEvent { window_id, .. } => {
+                if let Some(window) = windows.get_mut(&window_id) {
+                    window.handle_event(&event, control_flow);
+                }
+                if close_windows(&mut windows) {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            Event::UserEvent(FlutterEvent::OpenWindow {
+                assets_path,
+                params,
+            }) => {
+                let window =
+                    match open_requested_window(target, &proxy, assets_path, &params, &plugins) {
+                        Ok(window) => window,
+                        Err(err) => {
+                            log::error!("Failed to open window: {}", err);
+                            return;
+                        }
+                    };
+                if let Some(on_window_opened) = &mut on_window_opened {
+                    on_window_opened(&window);
+                }
+                let result = match &params.route {
+                    Some(route) => window.start_engine_with_route(&arguments, route),
+                    None => window.start_engine(&arguments),
+                };
+                match result {
+                    Ok(()) => {
+                        windows.insert(window.id(), window);
+                    }
+                    Err(err) => log::error!("Failed to start engine: {}", err),
+                }
+            }
+            Event::LoopDestroyed => {
+                for window in windows.values_mut() {
+                    window.handle_event(&event, control_flow);
+                }
+            }
+            _ => {
+                if close_windows(&mut windows) {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+
+                *control_flow = ControlFlow::Wait;
+                for window in windows.values_mut() {
+                    window.handle_event(&event, control_flow);
+                }
+            }
+        });
+    }
+}
+
+fn open_requested_window(
+    target: &EventLoopWindowTarget<FlutterEvent>,
+    proxy: &FlutterEventProxy,
+    assets_path: PathBuf,
+    params: &OpenWindowParams,
+    plugins: &DefaultPlugins,
+) -> Result<FlutterWindow, Box<dyn Error>> {
+    let mut builder = WindowBuilder::new().with_title(params.title.as_deref().unwrap_or("Flutter"));
+    if let (Some(width), Some(height)) = (params.width, params.height) {
+        builder = builder.with_inner_size(LogicalSize::new(width, height));
+    }
+    let window = FlutterWindow::create(
+        target,
+        proxy.clone(),
+        builder,
+        assets_path,
+        params.entrypoint.as_deref(),
+        plugins,
+        true,
+    )?;
+    window.create_resource_context(target)?;
+    Ok(window)
+}
+
+/// Shuts down and drops closed windows. Returns `true` once no window is
+/// left.
+fn close_windows(windows: &mut HashMap<WindowId, FlutterWindow>) -> bool {
+    windows.retain(|_, window| {
+        if window.is_closed() {
+            window.shutdown();
+            false
+        } else {
+            true
+        }
+    });
+    windows.is_empty()
+}
@@ -1,30 +1,35 @@
+#[cfg(all(feature = "accessibility", target_os = "linux"))]
+use crate::accessibility::AccessibilityBridge;
 use crate::context::Context;
-use crate::window::FlutterEvent;
+use crate::window::{CloseHandle, FlutterEvent, FlutterEventProxy};
 use async_std::task;
 use copypasta::{ClipboardContext, ClipboardProvider};
+#[cfg(all(feature = "accessibility", target_os = "linux"))]
+use flutter_engine::semantics::SemanticsTree;
 use flutter_engine::FlutterEngineHandler;
 use flutter_plugins::platform::{AppSwitcherDescription, MimeError, PlatformHandler};
 use flutter_plugins::textinput::TextInputHandler;
-use flutter_plugins::window::{PositionParams, WindowHandler};
+use flutter_plugins::window::{OpenWindowParams, PositionParams, WindowHandler};
 use futures_task::FutureObj;
-use glutin::event_loop::EventLoopProxy;
 use parking_lot::Mutex;
 use std::error::Error;
 use std::ffi::CStr;
 use std::future::Future;
 use std::os::raw::{c_char, c_void};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::PathBuf;
 use std::sync::Arc;
 
 pub struct WinitFlutterEngineHandler {
-    proxy: EventLoopProxy<FlutterEvent>,
+    proxy: FlutterEventProxy,
     context: Arc<Mutex<Context>>,
     resource_context: Arc<Mutex<Context>>,
+    #[cfg(all(feature = "accessibility", target_os = "linux"))]
+    accessibility: AccessibilityBridge,
 }
 
 impl WinitFlutterEngineHandler {
-    pub fn new(
-        proxy: EventLoopProxy<FlutterEvent>,
+    pub(crate) fn new(
+        proxy: FlutterEventProxy,
         context: Arc<Mutex<Context>>,
         resource_context: Arc<Mutex<Context>>,
     ) -> Self {
@@ -32,8 +37,15 @@ impl WinitFlutterEngineHandler {
             proxy,
             context,
             resource_context,
+            #[cfg(all(feature = "accessibility", target_os = "linux"))]
+            accessibility: Default::default(),
         }
     }
+
+    #[cfg(all(feature = "accessibility", target_os = "linux"))]
+    pub fn accessibility(&self) -> &AccessibilityBridge {
+        &self.accessibility
+    }
 }
 
 impl FlutterEngineHandler for WinitFlutterEngineHandler {
@@ -66,8 +78,13 @@ impl FlutterEngineHandler for WinitFlutterEngineHandler {
         }
     }
 
+    #[cfg(all(feature = "accessibility", target_os = "linux"))]
+    fn semantics_updated(&self, tree: &SemanticsTree) {
+        self.accessibility.update(tree);
+    }
+
     fn wake_platform_thread(&self) {
-        self.proxy.send_event(FlutterEvent::WakePlatformThread).ok();
+        self.proxy.send_event(FlutterEvent::WakePlatformThread);
     }
 
     fn run_in_background(&self, future: Box<dyn Future<Output = ()> + Send + 'static>) {
@@ -116,23 +133,42 @@ pub struct WinitWindowHandler {
     context: Arc<Mutex<Context>>,
     maximized: bool,
     visible: bool,
-    close: Arc<AtomicBool>,
+    close: CloseHandle,
+    proxy: FlutterEventProxy,
+    assets_path: PathBuf,
+    /// Whether the event loop belongs to a `FlutterApplication`, which opens
+    /// the windows.
+    opens_windows: bool,
+    /// Whether the windows opened by the application have the navigation
+    /// plugin.
+    navigation: bool,
 }
 
 impl WinitWindowHandler {
-    pub fn new(context: Arc<Mutex<Context>>, close: Arc<AtomicBool>) -> Self {
+    pub(crate) fn new(
+        context: Arc<Mutex<Context>>,
+        close: CloseHandle,
+        proxy: FlutterEventProxy,
+        assets_path: PathBuf,
+        opens_windows: bool,
+        navigation: bool,
+    ) -> Self {
         Self {
             context,
             maximized: false,
             visible: false,
             close,
+            proxy,
+            assets_path,
+            opens_windows,
+            navigation,
         }
     }
 }
 
 impl WindowHandler for WinitWindowHandler {
     fn close(&mut self) {
-        self.close.store(true, Ordering::Relaxed);
+        self.close.close();
     }
 
     fn show(&mut self) {
@@ -178,6 +214,24 @@ impl WindowHandler for WinitWindowHandler {
     fn start_drag(&mut self) {}
 
     fn end_drag(&mut self) {}
+
+    /// Fails for routes the new window could not start with. Errors while
+    /// creating the window or starting its engine are only logged.
+    fn open_window(&mut self, params: OpenWindowParams) -> bool {
+        if !self.opens_windows {
+            return false;
+        }
+        if let Some(route) = &params.route {
+            if !self.navigation || !route.starts_with('/') {
+                log::error!("Cannot open a window at route {}", route);
+                return false;
+            }
+        }
+        self.proxy.send_event(FlutterEvent::OpenWindow {
+            assets_path: self.assets_path.clone(),
+            params,
+        })
+    }
 }
 
 pub struct WinitTextInputHandler {}
@@ -193,3 +247,37 @@ impl TextInputHandler for WinitTextInputHandler {
 
     fn hide(&mut self) {}
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use glutin::event_loop::EventLoop;
+    use glutin::platform::unix::EventLoopExtUnix;
+
+    /// Needs a display for the event loop, CI runs it under `xvfb-run`.
+    #[test]
+    fn test_open_window_checks_route() {
+        let event_loop = EventLoop::<FlutterEvent>::new_any_thread();
+        let handler = |opens_windows, navigation| {
+            WinitWindowHandler::new(
+                Arc::new(Mutex::new(Context::empty())),
+                CloseHandle::default(),
+                FlutterEventProxy::new(event_loop.create_proxy()),
+                PathBuf::from("assets"),
+                opens_windows,
+                navigation,
+            )
+        };
+        let route = |route: &str| OpenWindowParams {
+            route: Some(route.into()),
+            ..Default::default()
+        };
+
+        assert!(handler(true, true).open_window(route("/settings")));
+        assert!(handler(true, false).open_window(OpenWindowParams::default()));
+        assert!(!handler(true, false).open_window(route("/settings")));
+        assert!(!handler(true, true).open_window(route("settings")));
+        // nothing opens windows for a window on an event loop of the caller
+        assert!(!handler(false, true).open_window(OpenWindowParams::default()));
+    }
+}
@@ -1,13 +1,19 @@
 //#![deny(missing_docs)]
 #![deny(warnings)]
 
+#[cfg(all(feature = "accessibility", target_os = "linux"))]
+mod accessibility;
+mod application;
 mod context;
 mod handler;
 mod keyboard;
+mod plugins;
 mod pointer;
 mod window;
 
-pub use window::FlutterWindow;
+pub use application::FlutterApplication;
+pub use plugins::{DefaultPlugin, DefaultPlugins};
+pub use window::{CloseHandle, FlutterEvent, FlutterWindow, MissingNavigationPlugin};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,314 @@
+use crate::context::Context;
+use crate::handler::{WinitPlatformHandler, WinitTextInputHandler, WinitWindowHandler};
+use crate::window::{CloseHandle, FlutterEvent, FlutterEventProxy};
+use flutter_engine::FlutterEngine;
+use flutter_plugins::dialog::DialogPlugin;
+use flutter_plugins::isolate::IsolatePlugin;
+use flutter_plugins::keyevent::KeyEventPlugin;
+use flutter_plugins::lifecycle::LifecyclePlugin;
+use flutter_plugins::localization::LocalizationPlugin;
+use flutter_plugins::navigation::NavigationPlugin;
+use flutter_plugins::platform::{PlatformHandler, PlatformPlugin};
+use flutter_plugins::settings::SettingsPlugin;
+use flutter_plugins::system::SystemPlugin;
+use flutter_plugins::textinput::{TextInputHandler, TextInputPlugin};
+use flutter_plugins::window::{WindowHandler, WindowPlugin};
+use parking_lot::Mutex;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The plugins every `FlutterWindow` registers unless told otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefaultPlugin {
+    Dialog,
+    Isolate,
+    KeyEvent,
+    Lifecycle,
+    Localization,
+    Navigation,
+    Platform,
+    Settings,
+    System,
+    TextInput,
+    Window,
+}
+
+type WindowHandlerFactory = Arc<dyn Fn(CloseHandle) -> Arc<Mutex<dyn WindowHandler + Send>>>;
+
+/// Selects the built-in plugins of a window and the handlers they use.
+///
+/// Plugins whose handler is not replaced use the winit implementation. A
+/// custom `WindowHandler` is responsible for closing its window and opening
+/// new ones.
+#[derive(Clone, Default)]
+pub struct DefaultPlugins {
+    disabled: Vec<DefaultPlugin>,
+    platform_handler: Option<Arc<Mutex<dyn PlatformHandler + Send>>>,
+    window_handler: Option<WindowHandlerFactory>,
+    text_input_handler: Option<Arc<Mutex<dyn TextInputHandler + Send>>>,
+}
+
+impl DefaultPlugins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn without(mut self, plugin: DefaultPlugin) -> Self {
+        if !self.disabled.contains(&plugin) {
+            self.disabled.push(plugin);
+        }
+        self
+    }
+
+    pub fn with_platform_handler(
+        mut self,
+        handler: Arc<Mutex<dyn PlatformHandler + Send>>,
+    ) -> Self {
+        self.platform_handler = Some(handler);
+        self
+    }
+
+    /// Replaces the window handler. `factory` is called for every window
+    /// with the handle that closes it.
+    pub fn with_window_handler<F>(mut self, factory: F) -> Self
+    where
+        F: Fn(CloseHandle) -> Arc<Mutex<dyn WindowHandler + Send>> + 'static,
+    {
+        self.window_handler = Some(Arc::new(factory));
+        self
+    }
+
+    pub fn with_text_input_handler(
+        mut self,
+        handler: Arc<Mutex<dyn TextInputHandler + Send>>,
+    ) -> Self {
+        self.text_input_handler = Some(handler);
+        self
+    }
+
+    pub fn is_enabled(&self, plugin: DefaultPlugin) -> bool {
+        !self.disabled.contains(&plugin)
+    }
+
+    pub(crate) fn register(
+        &self,
+        engine: &FlutterEngine,
+        context: &Arc<Mutex<Context>>,
+        close: &CloseHandle,
+        proxy: FlutterEventProxy,
+        assets_path: PathBuf,
+        opens_windows: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.is_enabled(DefaultPlugin::Dialog) {
+            engine.add_plugin(DialogPlugin::default());
+        }
+        if self.is_enabled(DefaultPlugin::Isolate) {
+            let proxy = proxy.clone();
+            let isolate_cb = move || {
+                proxy.send_event(FlutterEvent::IsolateCreated);
+            };
+            engine.add_plugin(IsolatePlugin::new(isolate_cb));
+        }
+        if self.is_enabled(DefaultPlugin::KeyEvent) {
+            engine.add_plugin(KeyEventPlugin::default());
+        }
+        if self.is_enabled(DefaultPlugin::Lifecycle) {
+            engine.add_plugin(LifecyclePlugin::default());
+        }
+        if self.is_enabled(DefaultPlugin::Localization) {
+            engine.add_plugin(LocalizationPlugin::default());
+        }
+        if self.is_enabled(DefaultPlugin::Navigation) {
+            engine.add_plugin(NavigationPlugin::default());
+        }
+        if self.is_enabled(DefaultPlugin::Platform) {
+            let handler: Arc<Mutex<dyn PlatformHandler + Send>> = match &self.platform_handler {
+                Some(handler) => handler.clone(),
+                None => Arc::new(Mutex::new(WinitPlatformHandler::new(context.clone())?)),
+            };
+            engine.add_plugin(PlatformPlugin::new(handler));
+        }
+        if self.is_enabled(DefaultPlugin::Settings) {
+            engine.add_plugin(SettingsPlugin::default());
+        }
+        if self.is_enabled(DefaultPlugin::System) {
+            engine.add_plugin(SystemPlugin::default());
+        }
+        if self.is_enabled(DefaultPlugin::TextInput) {
+            let handler: Arc<Mutex<dyn TextInputHandler + Send>> = match &self.text_input_handler {
+                Some(handler) => handler.clone(),
+                None => Arc::new(Mutex::new(WinitTextInputHandler::default())),
+            };
+            engine.add_plugin(TextInputPlugin::new(handler));
+        }
+        if self.is_enabled(DefaultPlugin::Window) {
+            let handler: Arc<Mutex<dyn WindowHandler + Send>> = match &self.window_handler {
+                Some(factory) => factory(close.clone()),
+                None => Arc::new(Mutex::new(WinitWindowHandler::new(
+                    context.clone(),
+                    close.clone(),
+                    proxy,
+                    assets_path,
+                    opens_windows,
+                    self.is_enabled(DefaultPlugin::Navigation),
+                ))),
+            };
+            engine.add_plugin(WindowPlugin::new(handler));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without() {
+        let plugins = DefaultPlugins::new()
+            .without(DefaultPlugin::Dialog)
+            .without(DefaultPlugin::Window)
+            .without(DefaultPlugin::Dialog);
+        assert_eq!(
+            plugins.disabled,
+            vec![DefaultPlugin::Dialog, DefaultPlugin::Window]
+        );
+        assert!(!plugins.is_enabled(DefaultPlugin::Dialog));
+        assert!(!plugins.is_enabled(DefaultPlugin::Window));
+        assert!(plugins.is_enabled(DefaultPlugin::Navigation));
+        assert!(DefaultPlugins::new().is_enabled(DefaultPlugin::Dialog));
+    }
+
+    /// Needs a display for the event loop, CI runs it under `xvfb-run`.
+    #[cfg(all(feature = "mock", target_os = "linux"))]
+    #[test]
+    fn test_register() {
+        use flutter_engine::codec::{json_codec::CODEC, MethodCall, MethodCodec, Value};
+        use flutter_engine::mock::{run_engine, MockHandler};
+        use flutter_plugins::platform::{AppSwitcherDescription, MimeError};
+        use flutter_plugins::window::PositionParams;
+        use flutter_plugins::{platform, textinput};
+        use glutin::event_loop::EventLoop;
+        use glutin::platform::unix::EventLoopExtUnix;
+
+        /// Records the calls the plugins make to their handlers.
+        #[derive(Default)]
+        struct Recorder(Vec<&'static str>, Option<CloseHandle>);
+
+        impl PlatformHandler for Recorder {
+            fn set_application_switcher_description(&mut self, _: AppSwitcherDescription) {}
+
+            fn set_clipboard_data(&mut self, _: String) {}
+
+            fn get_clipboard_data(&mut self, _: &str) -> Result<String, MimeError> {
+                self.0.push("get_clipboard_data");
+                Ok(String::new())
+            }
+        }
+
+        impl TextInputHandler for Recorder {
+            fn show(&mut self) {
+                self.0.push("show_keyboard");
+            }
+
+            fn hide(&mut self) {}
+        }
+
+        impl WindowHandler for Recorder {
+            fn close(&mut self) {
+                self.0.push("close");
+                if let Some(close) = &self.1 {
+                    close.close();
+                }
+            }
+
+            fn show(&mut self) {}
+
+            fn hide(&mut self) {}
+
+            fn maximize(&mut self) {}
+
+            fn iconify(&mut self) {}
+
+            fn restore(&mut self) {}
+
+            fn is_maximized(&mut self) -> bool {
+                false
+            }
+
+            fn is_iconified(&mut self) -> bool {
+                false
+            }
+
+            fn is_visible(&mut self) -> bool {
+                true
+            }
+
+            fn set_pos(&mut self, _: PositionParams) {}
+
+            fn get_pos(&mut self) -> PositionParams {
+                PositionParams { x: 0.0, y: 0.0 }
+            }
+
+            fn start_drag(&mut self) {}
+
+            fn end_drag(&mut self) {}
+        }
+
+        let event_loop = EventLoop::<FlutterEvent>::new_any_thread();
+        let handler = MockHandler::new();
+        let (engine, mock) = run_engine(&handler);
+        let platform = Arc::new(Mutex::new(Recorder::default()));
+        let text_input = Arc::new(Mutex::new(Recorder::default()));
+        let window = Arc::new(Mutex::new(Recorder::default()));
+        let window_factory = window.clone();
+        let close = CloseHandle::default();
+        DefaultPlugins::new()
+            .without(DefaultPlugin::Dialog)
+            .with_platform_handler(platform.clone())
+            .with_text_input_handler(text_input.clone())
+            .with_window_handler(move |close| {
+                window_factory.lock().1 = Some(close);
+                window_factory.clone()
+            })
+            .register(
+                &engine,
+                &Arc::new(Mutex::new(Context::empty())),
+                &close,
+                FlutterEventProxy::new(event_loop.create_proxy()),
+                PathBuf::from("assets"),
+                true,
+            )
+            .unwrap();
+
+        let mut dialog = false;
+        engine.with_plugin(|_: &DialogPlugin| dialog = true);
+        assert!(!dialog);
+        let mut navigation = false;
+        engine.with_plugin(|_: &NavigationPlugin| navigation = true);
+        assert!(navigation);
+
+        let invoke = |channel: &str, method: &str, args: Value| {
+            let call = MethodCall {
+                method: method.into(),
+                args,
+            };
+            mock.send_platform_message(channel, &CODEC.encode_method_call(&call));
+        };
+        invoke(
+            platform::CHANNEL_NAME,
+            "Clipboard.getData",
+            Value::String("text/plain".into()),
+        );
+        invoke(textinput::CHANNEL_NAME, "TextInput.show", Value::Null);
+        invoke("flutter-rs/window", "close", Value::Null);
+        handler.pump(&engine);
+
+        assert_eq!(platform.lock().0, vec!["get_clipboard_data"]);
+        assert_eq!(text_input.lock().0, vec!["show_keyboard"]);
+        assert_eq!(window.lock().0, vec!["close"]);
+        // the custom handler closed the window it was created for
+        assert!(close.is_closed());
+    }
+}
@@ -1,29 +1,25 @@
+use crate::application::FlutterApplication;
 use crate::context::Context;
-use crate::handler::{
-    WinitFlutterEngineHandler, WinitPlatformHandler, WinitTextInputHandler, WinitWindowHandler,
-};
+use crate::handler::WinitFlutterEngineHandler;
 use crate::keyboard::raw_key;
+use crate::plugins::DefaultPlugins;
 use crate::pointer::Pointers;
+use flutter_engine::builder::FlutterEngineBuilder;
 use flutter_engine::channel::Channel;
 use flutter_engine::plugins::Plugin;
 use flutter_engine::texture_registry::Texture;
 use flutter_engine::{FlutterEngine, FlutterEngineHandler};
-use flutter_plugins::dialog::DialogPlugin;
-use flutter_plugins::isolate::IsolatePlugin;
 use flutter_plugins::keyevent::{KeyAction, KeyActionType, KeyEventPlugin};
 use flutter_plugins::lifecycle::LifecyclePlugin;
 use flutter_plugins::localization::LocalizationPlugin;
 use flutter_plugins::navigation::NavigationPlugin;
-use flutter_plugins::platform::PlatformPlugin;
-use flutter_plugins::settings::SettingsPlugin;
-use flutter_plugins::system::SystemPlugin;
 use flutter_plugins::textinput::TextInputPlugin;
-use flutter_plugins::window::WindowPlugin;
+use flutter_plugins::window::OpenWindowParams;
 use glutin::event::{
     ElementState, Event, KeyboardInput, MouseScrollDelta, Touch, VirtualKeyCode, WindowEvent,
 };
-use glutin::event_loop::{ControlFlow, EventLoop};
-use glutin::window::WindowBuilder;
+use glutin::event_loop::{ControlFlow, EventLoop, EventLoopProxy, EventLoopWindowTarget};
+use glutin::window::{WindowBuilder, WindowId};
 use glutin::ContextBuilder;
 use parking_lot::Mutex;
 use std::error::Error;
@@ -31,88 +27,201 @@ use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
+/// The events a window sends to its event loop. Loops owned by the caller
+/// can use any user event that can be built from them.
 pub enum FlutterEvent {
     WakePlatformThread,
     IsolateCreated,
+    /// Dart asked for another window over the `flutter-rs/window` channel.
+    OpenWindow {
+        assets_path: PathBuf,
+        params: OpenWindowParams,
+    },
+}
+
+/// Sends `FlutterEvent`s to the event loop of a window, whatever its user
+/// event type.
+#[derive(Clone)]
+pub(crate) struct FlutterEventProxy(Arc<Mutex<dyn Fn(FlutterEvent) -> bool + Send>>);
+
+impl FlutterEventProxy {
+    pub(crate) fn new<T>(proxy: EventLoopProxy<T>) -> Self
+    where
+        T: From<FlutterEvent> + Send + 'static,
+    {
+        Self(Arc::new(Mutex::new(move |event: FlutterEvent| {
+            proxy.send_event(event.into()).is_ok()
+        })))
+    }
+
+    /// Returns `false` if the event loop no longer exists.
+    pub(crate) fn send_event(&self, event: FlutterEvent) -> bool {
+        (self.0.lock())(event)
+    }
 }
 
+/// Closes one window, e.g. from a custom `WindowHandler`.
+#[derive(Clone, Default)]
+pub struct CloseHandle(Arc<AtomicBool>);
+
+impl CloseHandle {
+    /// Marks the window as closed. A `FlutterApplication` shuts it down once
+    /// it handles the next event.
+    pub fn close(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Returned when a window is started with an initial route but
+/// `DefaultPlugin::Navigation` is disabled.
+#[derive(Debug)]
+pub struct MissingNavigationPlugin;
+
+impl std::fmt::Display for MissingNavigationPlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Initial route needs the navigation plugin")
+    }
+}
+
+impl Error for MissingNavigationPlugin {}
+
 pub struct FlutterWindow {
-    event_loop: EventLoop<FlutterEvent>,
+    /// Only set for windows created with `new`, which own their event loop
+    /// until `run` hands it to a `FlutterApplication` that opens windows
+    /// with the same plugins.
+    pub(crate) event_loop: Option<(EventLoop<FlutterEvent>, DefaultPlugins)>,
     context: Arc<Mutex<Context>>,
     resource_context: Arc<Mutex<Context>>,
     engine: FlutterEngine,
     engine_handler: Arc<WinitFlutterEngineHandler>,
-    close: Arc<AtomicBool>,
+    close: CloseHandle,
+    pointers: Pointers,
 }
 
 impl FlutterWindow {
     pub fn new(window: WindowBuilder, assets_path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        Self::new_with_plugins(window, assets_path, DefaultPlugins::default())
+    }
+
+    /// Like `new`, but only registers the built-in plugins enabled in
+    /// `plugins`.
+    pub fn new_with_plugins(
+        window: WindowBuilder,
+        assets_path: PathBuf,
+        plugins: DefaultPlugins,
+    ) -> Result<Self, Box<dyn Error>> {
         let event_loop = EventLoop::with_user_event();
-        let proxy = event_loop.create_proxy();
+        let mut window = Self::create(
+            &event_loop,
+            FlutterEventProxy::new(event_loop.create_proxy()),
+            window,
+            assets_path,
+            None,
+            &plugins,
+            true,
+        )?;
+        window.event_loop = Some((event_loop, plugins));
+        Ok(window)
+    }
+
+    /// Creates a window on an event loop owned by the caller. Events have to
+    /// be forwarded with `handle_event`; `run` must not be called. Dart
+    /// cannot open more windows from it.
+    ///
+    /// The window wakes the loop by sending `FlutterEvent`s, converted to its
+    /// user event, through `proxy`.
+    pub fn with_window_target<T>(
+        target: &EventLoopWindowTarget<T>,
+        proxy: EventLoopProxy<T>,
+        window: WindowBuilder,
+        assets_path: PathBuf,
+        plugins: DefaultPlugins,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        T: From<FlutterEvent> + Send + 'static,
+    {
+        let proxy = FlutterEventProxy::new(proxy);
+        Self::create(target, proxy, window, assets_path, None, &plugins, false)
+    }
 
-        let context = ContextBuilder::new().build_windowed(window, &event_loop)?;
+    pub(crate) fn create<T>(
+        target: &EventLoopWindowTarget<T>,
+        proxy: FlutterEventProxy,
+        window: WindowBuilder,
+        assets_path: PathBuf,
+        entrypoint: Option<&str>,
+        plugins: &DefaultPlugins,
+        opens_windows: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let context = ContextBuilder::new().build_windowed(window, target)?;
         let context = Arc::new(Mutex::new(Context::from_context(context)));
         let resource_context = Arc::new(Mutex::new(Context::empty()));
 
         let engine_handler = Arc::new(WinitFlutterEngineHandler::new(
-            proxy,
+            proxy.clone(),
             context.clone(),
             resource_context.clone(),
         ));
-        let engine = FlutterEngine::new(Arc::downgrade(&engine_handler) as _, assets_path);
+        let mut builder =
+            FlutterEngineBuilder::new(Arc::downgrade(&engine_handler) as _, assets_path.clone());
+        if let Some(entrypoint) = entrypoint {
+            builder = builder.with_dart_entrypoint(entrypoint);
+        }
+        let engine = builder.build()?;
+        #[cfg(all(feature = "accessibility", target_os = "linux"))]
+        engine_handler.accessibility().init(engine.downgrade());
 
-        let proxy = event_loop.create_proxy();
-        let isolate_cb = move || {
-            proxy.send_event(FlutterEvent::IsolateCreated).ok();
-        };
-        let platform_handler = Arc::new(Mutex::new(WinitPlatformHandler::new(context.clone())?));
-        let close = Arc::new(AtomicBool::new(false));
-        let window_handler = Arc::new(Mutex::new(WinitWindowHandler::new(
-            context.clone(),
-            close.clone(),
-        )));
-        let textinput_handler = Arc::new(Mutex::new(WinitTextInputHandler::default()));
-
-        engine.add_plugin(DialogPlugin::default());
-        engine.add_plugin(IsolatePlugin::new(isolate_cb));
-        engine.add_plugin(KeyEventPlugin::default());
-        engine.add_plugin(LifecyclePlugin::default());
-        engine.add_plugin(LocalizationPlugin::default());
-        engine.add_plugin(NavigationPlugin::default());
-        engine.add_plugin(PlatformPlugin::new(platform_handler));
-        engine.add_plugin(SettingsPlugin::default());
-        engine.add_plugin(SystemPlugin::default());
-        engine.add_plugin(TextInputPlugin::new(textinput_handler));
-        engine.add_plugin(WindowPlugin::new(window_handler));
+        let close = CloseHandle::default();
+        plugins.register(&engine, &context, &close, proxy, assets_path, opens_windows)?;
 
+        let pointers = Pointers::new(engine.clone());
         Ok(Self {
-            event_loop,
+            event_loop: None,
             context,
             resource_context,
             engine,
             engine_handler,
             close,
+            pointers,
         })
     }
 
     pub fn with_resource_context(self) -> Result<Self, Box<dyn Error>> {
-        {
-            let window = WindowBuilder::new().with_visible(false);
-            let context = self.context.lock();
-            let resource_context = ContextBuilder::new()
-                .with_shared_lists(context.context().unwrap())
-                .build_windowed(window, &self.event_loop)?;
-
-            let resource_context = unsafe { resource_context.make_current().unwrap() };
-            gl::load_with(|s| resource_context.get_proc_address(s));
-            let resource_context = unsafe { resource_context.make_not_current().unwrap() };
-
-            let mut guard = self.resource_context.lock();
-            *guard = Context::from_context(resource_context);
+        if let Some((event_loop, _)) = &self.event_loop {
+            self.create_resource_context(event_loop)?;
         }
         Ok(self)
     }
 
+    /// Creates the resource context of a window made with
+    /// `with_window_target`.
+    pub fn create_resource_context<T>(
+        &self,
+        target: &EventLoopWindowTarget<T>,
+    ) -> Result<(), Box<dyn Error>> {
+        let window = WindowBuilder::new().with_visible(false);
+        let context = self.context.lock();
+        let resource_context = ContextBuilder::new()
+            .with_shared_lists(context.context().unwrap())
+            .build_windowed(window, target)?;
+
+        let resource_context = unsafe { resource_context.make_current().unwrap() };
+        gl::load_with(|s| resource_context.get_proc_address(s));
+        let resource_context = unsafe { resource_context.make_not_current().unwrap() };
+
+        let mut guard = self.resource_context.lock();
+        *guard = Context::from_context(resource_context);
+        Ok(())
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.context.lock().window().id()
+    }
+
     pub fn engine(&self) -> FlutterEngine {
         self.engine.clone()
     }
@@ -166,170 +275,238 @@ impl FlutterWindow {
 
     pub fn start_engine(&self, arguments: &[String]) -> Result<(), Box<dyn Error>> {
         self.engine.run(arguments)?;
+        self.init();
         Ok(())
     }
 
-    pub fn run(self) -> ! {
-        let engine = self.engine.clone();
-        let context = self.context.clone();
-        let close = self.close.clone();
+    /// Starts the engine like `start_engine`, but lets the Dart app open at
+    /// `route` instead of `/`. Fails with `MissingNavigationPlugin` if the
+    /// window has no navigation plugin.
+    pub fn start_engine_with_route(
+        &self,
+        arguments: &[String],
+        route: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut has_navigation = false;
+        self.engine
+            .with_plugin(|_: &NavigationPlugin| has_navigation = true);
+        if !has_navigation {
+            return Err(MissingNavigationPlugin.into());
+        }
+        self.engine.initialize(arguments)?;
+        self.engine
+            .with_plugin(|navigation: &NavigationPlugin| navigation.set_initial_route(route));
+        self.engine.run_initialized()?;
+        self.init();
+        Ok(())
+    }
 
-        resize(&engine, &context);
+    /// Runs this window until it is closed. Use `FlutterApplication` to open
+    /// more than one window.
+    pub fn run(self) -> ! {
+        FlutterApplication::from_window(self).run()
+    }
 
-        engine.with_plugin(|localization: &LocalizationPlugin| {
-            localization.send_locale(locale_config::Locale::current());
-        });
+    /// Sends the initial window metrics and locale.
+    fn init(&self) {
+        resize(&self.engine, &self.context);
+        #[cfg(all(feature = "accessibility", target_os = "linux"))]
+        self.engine_handler
+            .accessibility()
+            .update_window_bounds(self.context.lock().window());
+
+        self.engine
+            .with_plugin(|localization: &LocalizationPlugin| {
+                localization.send_locale(locale_config::Locale::current());
+            });
+    }
 
-        let mut pointers = Pointers::new(engine.clone());
-        self.event_loop
-            .run(move |event, _, control_flow| match event {
-                Event::WindowEvent { event, .. } => {
-                    match event {
-                        WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(_) => resize(&engine, &context),
-                        WindowEvent::HiDpiFactorChanged(_) => resize(&engine, &context),
-                        WindowEvent::CursorEntered { device_id } => pointers.enter(device_id),
-                        WindowEvent::CursorLeft { device_id } => pointers.leave(device_id),
-                        WindowEvent::CursorMoved {
-                            device_id,
-                            position,
-                            ..
-                        } => {
-                            let dpi = { context.lock().hidpi_factor() };
-                            let position = position.to_physical(dpi);
-                            pointers.moved(device_id, position.into());
-                        }
-                        WindowEvent::MouseInput {
-                            device_id,
-                            state,
-                            button,
-                            ..
-                        } => {
-                            pointers.input(device_id, state, button);
-                        }
-                        WindowEvent::MouseWheel {
-                            device_id, delta, ..
-                        } => {
-                            let delta = match delta {
-                                MouseScrollDelta::LineDelta(_, _) => (0.0, 0.0), // TODO
-                                MouseScrollDelta::PixelDelta(position) => {
-                                    let dpi = { context.lock().hidpi_factor() };
-                                    let (dx, dy): (f64, f64) = position.to_physical(dpi).into();
-                                    (-dx, dy)
-                                }
-                            };
-                            pointers.wheel(device_id, delta);
-                        }
-                        WindowEvent::Touch(Touch {
-                            device_id,
-                            phase,
-                            location,
-                            ..
-                        }) => {
-                            let dpi = { context.lock().hidpi_factor() };
-                            let position = location.to_physical(dpi);
-                            pointers.touch(device_id, phase, position.into());
+    /// Handles an event of the event loop the window was created on. Events
+    /// of other windows are ignored.
+    ///
+    /// `control_flow` is only ever moved to an earlier wake up for pending
+    /// engine tasks, so the caller should reset it to `ControlFlow::Wait`
+    /// for every new batch of events. Closing the window does not exit the
+    /// loop, check `is_closed` instead.
+    pub fn handle_event<T>(&mut self, event: &Event<T>, control_flow: &mut ControlFlow) {
+        match event {
+            Event::WindowEvent { window_id, event } => {
+                if *window_id == self.id() {
+                    self.handle_window_event(event.clone());
+                }
+            }
+            Event::LoopDestroyed => self.shutdown(),
+            _ => {
+                if let Some(next_task_time) = self.engine.execute_platform_tasks() {
+                    match *control_flow {
+                        ControlFlow::Wait => *control_flow = ControlFlow::WaitUntil(next_task_time),
+                        ControlFlow::WaitUntil(time) if next_task_time < time => {
+                            *control_flow = ControlFlow::WaitUntil(next_task_time)
                         }
-                        WindowEvent::ReceivedCharacter(ch) => {
-                            if !ch.is_control() {
-                                engine.with_plugin_mut(|text_input: &mut TextInputPlugin| {
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_window_event(&mut self, event: WindowEvent) {
+        let engine = &self.engine;
+        let context = &self.context;
+        let pointers = &mut self.pointers;
+        #[cfg(all(feature = "accessibility", target_os = "linux"))]
+        self.engine_handler
+            .accessibility()
+            .handle_event(context.lock().window(), &event);
+
+        match event {
+            WindowEvent::CloseRequested => self.close.close(),
+            WindowEvent::Resized(_) => resize(engine, context),
+            WindowEvent::HiDpiFactorChanged(_) => resize(engine, context),
+            WindowEvent::Focused(focused) => {
+                engine.with_plugin(|lifecycle: &LifecyclePlugin| {
+                    if focused {
+                        lifecycle.send_app_is_resumed();
+                    } else {
+                        lifecycle.send_app_is_inactive();
+                    }
+                });
+            }
+            WindowEvent::CursorEntered { device_id } => pointers.enter(device_id),
+            WindowEvent::CursorLeft { device_id } => pointers.leave(device_id),
+            WindowEvent::CursorMoved {
+                device_id,
+                position,
+                ..
+            } => {
+                let dpi = { context.lock().hidpi_factor() };
+                let position = position.to_physical(dpi);
+                pointers.moved(device_id, position.into());
+            }
+            WindowEvent::MouseInput {
+                device_id,
+                state,
+                button,
+                ..
+            } => {
+                pointers.input(device_id, state, button);
+            }
+            WindowEvent::MouseWheel {
+                device_id, delta, ..
+            } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(_, _) => (0.0, 0.0), // TODO
+                    MouseScrollDelta::PixelDelta(position) => {
+                        let dpi = { context.lock().hidpi_factor() };
+                        let (dx, dy): (f64, f64) = position.to_physical(dpi).into();
+                        (-dx, dy)
+                    }
+                };
+                pointers.wheel(device_id, delta);
+            }
+            WindowEvent::Touch(Touch {
+                device_id,
+                phase,
+                location,
+                ..
+            }) => {
+                let dpi = { context.lock().hidpi_factor() };
+                let position = location.to_physical(dpi);
+                pointers.touch(device_id, phase, position.into());
+            }
+            WindowEvent::ReceivedCharacter(ch) => {
+                if !ch.is_control() {
+                    engine.with_plugin_mut(|text_input: &mut TextInputPlugin| {
+                        text_input.with_state(|state| {
+                            state.add_characters(&ch.to_string());
+                        });
+                        text_input.notify_changes();
+                    });
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode,
+                        modifiers,
+                        scancode,
+                    },
+                ..
+            } => {
+                let raw_key = if let Some(raw_key) = raw_key(virtual_keycode) {
+                    raw_key
+                } else {
+                    return;
+                };
+
+                let shift = modifiers.shift as u32;
+                let ctrl = modifiers.ctrl as u32;
+                let alt = modifiers.alt as u32;
+                let logo = modifiers.logo as u32;
+                let raw_modifiers = shift | ctrl << 1 | alt << 2 | logo << 3;
+
+                match state {
+                    ElementState::Pressed => {
+                        if let Some(key) = virtual_keycode {
+                            engine.with_plugin_mut(|text_input: &mut TextInputPlugin| match key {
+                                VirtualKeyCode::Return => {
                                     text_input.with_state(|state| {
-                                        state.add_characters(&ch.to_string());
+                                        state.add_characters(&"\n");
                                     });
                                     text_input.notify_changes();
-                                });
-                            }
-                        }
-                        WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state,
-                                    virtual_keycode,
-                                    modifiers,
-                                    scancode,
-                                },
-                            ..
-                        } => {
-                            let raw_key = if let Some(raw_key) = raw_key(virtual_keycode) {
-                                raw_key
-                            } else {
-                                return;
-                            };
-
-                            let shift = modifiers.shift as u32;
-                            let ctrl = modifiers.ctrl as u32;
-                            let alt = modifiers.alt as u32;
-                            let logo = modifiers.logo as u32;
-                            let raw_modifiers = shift | ctrl << 1 | alt << 2 | logo << 3;
-
-                            match state {
-                                ElementState::Pressed => {
-                                    if let Some(key) = virtual_keycode {
-                                        engine.with_plugin_mut(
-                                            |text_input: &mut TextInputPlugin| match key {
-                                                VirtualKeyCode::Return => {
-                                                    text_input.with_state(|state| {
-                                                        state.add_characters(&"\n");
-                                                    });
-                                                    text_input.notify_changes();
-                                                }
-                                                VirtualKeyCode::Back => {
-                                                    text_input.with_state(|state| {
-                                                        state.backspace();
-                                                    });
-                                                    text_input.notify_changes();
-                                                }
-                                                _ => {}
-                                            },
-                                        );
-                                    }
-
-                                    engine.with_plugin_mut(|keyevent: &mut KeyEventPlugin| {
-                                        keyevent.key_action(KeyAction {
-                                            toolkit: "glfw".to_string(),
-                                            key_code: raw_key as _,
-                                            scan_code: scancode as _,
-                                            modifiers: raw_modifiers as _,
-                                            keymap: "linux".to_string(),
-                                            _type: KeyActionType::Keydown,
-                                        });
-                                    });
                                 }
-                                ElementState::Released => {
-                                    engine.with_plugin_mut(|keyevent: &mut KeyEventPlugin| {
-                                        keyevent.key_action(KeyAction {
-                                            toolkit: "glfw".to_string(),
-                                            key_code: raw_key as _,
-                                            scan_code: scancode as _,
-                                            modifiers: raw_modifiers as _,
-                                            keymap: "linux".to_string(),
-                                            _type: KeyActionType::Keyup,
-                                        });
+                                VirtualKeyCode::Back => {
+                                    text_input.with_state(|state| {
+                                        state.backspace();
                                     });
+                                    text_input.notify_changes();
                                 }
-                            }
+                                _ => {}
+                            });
                         }
-                        _ => {}
+
+                        engine.with_plugin_mut(|keyevent: &mut KeyEventPlugin| {
+                            keyevent.key_action(KeyAction {
+                                toolkit: "glfw".to_string(),
+                                key_code: raw_key as _,
+                                scan_code: scancode as _,
+                                modifiers: raw_modifiers as _,
+                                keymap: "linux".to_string(),
+                                _type: KeyActionType::Keydown,
+                            });
+                        });
                     }
-                }
-                Event::LoopDestroyed => {
-                    engine.shutdown();
-                }
-                _ => {
-                    if close.load(Ordering::Relaxed) {
-                        *control_flow = ControlFlow::Exit;
-                        return;
+                    ElementState::Released => {
+                        engine.with_plugin_mut(|keyevent: &mut KeyEventPlugin| {
+                            keyevent.key_action(KeyAction {
+                                toolkit: "glfw".to_string(),
+                                key_code: raw_key as _,
+                                scan_code: scancode as _,
+                                modifiers: raw_modifiers as _,
+                                keymap: "linux".to_string(),
+                                _type: KeyActionType::Keyup,
+                            });
+                        });
                     }
+                }
+            }
+            _ => {}
+        }
+    }
 
-                    let next_task_time = engine.execute_platform_tasks();
+    /// Whether the window was closed by the user or by Dart.
+    pub fn is_closed(&self) -> bool {
+        self.close.is_closed()
+    }
 
-                    if let Some(next_task_time) = next_task_time {
-                        *control_flow = ControlFlow::WaitUntil(next_task_time)
-                    } else {
-                        *control_flow = ControlFlow::Wait
-                    }
-                }
-            });
+    /// A handle that closes this window.
+    pub fn close_handle(&self) -> CloseHandle {
+        self.close.clone()
+    }
+
+    pub(crate) fn shutdown(&self) {
+        self.engine.shutdown();
     }
 
     pub fn wake_platform_thread(&self) {
@@ -350,3 +527,161 @@ fn resize(engine: &FlutterEngine, context: &Arc<Mutex<Context>>) {
     context.resize(size);
     engine.send_window_metrics_event(size.width as usize, size.height as usize, dpi);
 }
+
+#[cfg(all(test, feature = "mock", target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::plugins::DefaultPlugin;
+    use flutter_engine::mock::{mock_engine, Call, MockEngine};
+    use flutter_plugins::{lifecycle, navigation};
+    use glutin::event::StartCause;
+    use glutin::platform::desktop::EventLoopExtDesktop;
+    use glutin::platform::unix::EventLoopExtUnix;
+    use std::time::{Duration, Instant};
+
+    fn ran_task(mock: &MockEngine, task: u64) -> bool {
+        mock.calls()
+            .into_iter()
+            .any(|call| matches!(call, Call::RunTask(id) if id == task))
+    }
+
+    fn open_window<T>(event_loop: &EventLoop<T>, plugins: DefaultPlugins) -> FlutterWindow
+    where
+        T: From<FlutterEvent> + Send + 'static,
+    {
+        let window = FlutterWindow::with_window_target(
+            event_loop,
+            event_loop.create_proxy(),
+            WindowBuilder::new(),
+            PathBuf::from("assets"),
+            plugins,
+        )
+        .unwrap();
+        window.create_resource_context(event_loop).unwrap();
+        window
+    }
+
+    /// Needs a display, CI runs it under `xvfb-run`.
+    #[test]
+    fn test_start_engine_with_route() {
+        let event_loop = EventLoop::<FlutterEvent>::new_any_thread();
+
+        let window = open_window(&event_loop, DefaultPlugins::default());
+        window.start_engine_with_route(&[], "/settings").unwrap();
+        let mock = mock_engine(&window.engine());
+        assert_eq!(mock.sent_messages(navigation::CHANNEL_NAME).len(), 1);
+
+        let plugins = DefaultPlugins::default().without(DefaultPlugin::Navigation);
+        let window = open_window(&event_loop, plugins);
+        let err = window
+            .start_engine_with_route(&[], "/settings")
+            .unwrap_err();
+        assert!(err.is::<MissingNavigationPlugin>());
+        // the engine was not started
+        assert!(window.engine().engine_ptr().is_null());
+    }
+
+    /// Needs a display, CI runs it under `xvfb-run`.
+    #[test]
+    fn test_two_engines_on_one_event_loop() {
+        let mut event_loop = EventLoop::<FlutterEvent>::new_any_thread();
+        let mut windows: Vec<_> = (0..2)
+            .map(|_| {
+                let window = open_window(&event_loop, DefaultPlugins::default());
+                window.start_engine(&[]).unwrap();
+                window
+            })
+            .collect();
+        let mocks: Vec<_> = windows
+            .iter()
+            .map(|window| mock_engine(&window.engine()))
+            .collect();
+        assert_ne!(windows[0].id(), windows[1].id());
+
+        // every engine renders into the context of its own window
+        for mock in &mocks {
+            assert!(mock.present_gl_surface());
+        }
+
+        // window events only reach the engine of their window
+        let event = Event::<FlutterEvent>::WindowEvent {
+            window_id: windows[1].id(),
+            event: WindowEvent::Focused(true),
+        };
+        let mut control_flow = ControlFlow::Wait;
+        for window in &mut windows {
+            window.handle_event(&event, &mut control_flow);
+        }
+        assert!(mocks[0].sent_messages(lifecycle::CHANNEL_NAME).is_empty());
+        assert_eq!(mocks[1].sent_messages(lifecycle::CHANNEL_NAME).len(), 1);
+
+        // both engines run their tasks on the shared event loop
+        mocks[0].post_task(1, 0);
+        mocks[1].post_task(2, 0);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::WaitUntil(deadline);
+            if let Event::NewEvents(StartCause::ResumeTimeReached { .. }) = event {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            for window in &mut windows {
+                window.handle_event(&event, control_flow);
+            }
+            if ran_task(&mocks[0], 1) && ran_task(&mocks[1], 2) {
+                *control_flow = ControlFlow::Exit;
+            }
+        });
+        assert!(ran_task(&mocks[0], 1) && !ran_task(&mocks[0], 2));
+        assert!(ran_task(&mocks[1], 2) && !ran_task(&mocks[1], 1));
+
+        // the end of the loop shut both engines down
+        for mock in &mocks {
+            assert!(matches!(mock.calls().last(), Some(Call::Shutdown)));
+        }
+    }
+
+    /// The user event of an application that embeds a window in its own loop.
+    enum AppEvent {
+        Flutter(FlutterEvent),
+    }
+
+    impl From<FlutterEvent> for AppEvent {
+        fn from(event: FlutterEvent) -> Self {
+            AppEvent::Flutter(event)
+        }
+    }
+
+    /// Needs a display, CI runs it under `xvfb-run`.
+    #[test]
+    fn test_window_on_external_event_loop() {
+        let mut event_loop = EventLoop::<AppEvent>::new_any_thread();
+        let mut window = open_window(&event_loop, DefaultPlugins::default());
+        window.start_engine(&[]).unwrap();
+        let mock = mock_engine(&window.engine());
+
+        // the engine wakes the loop with the user event of the application
+        mock.post_task(1, 0);
+        let mut woken = false;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::WaitUntil(deadline);
+            match &event {
+                Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                Event::UserEvent(AppEvent::Flutter(FlutterEvent::WakePlatformThread)) => {
+                    woken = true;
+                }
+                _ => {}
+            }
+            window.handle_event(&event, control_flow);
+            if ran_task(&mock, 1) {
+                *control_flow = ControlFlow::Exit;
+            }
+        });
+        assert!(woken);
+        assert!(ran_task(&mock, 1));
+    }
+}